@@ -0,0 +1,426 @@
+use crate::bitimage::BitImage;
+use crate::font;
+use crate::printing::{barcode_check_digit, barcode_data, Barcode, BarcodeOptions, PrinterError};
+
+/// Bar and space widths of the Code 128 symbols, indexed by value, the last
+/// one being the stop pattern with its final bar
+const CODE128_PATTERNS: [&str; 107] = [
+  "212222", "222122", "222221", "121223", "121322", "131222", "122213", "122312",
+  "132212", "221213", "221312", "231212", "112232", "122132", "122231", "113222",
+  "123122", "123221", "223211", "221132", "221231", "213212", "223112", "312131",
+  "311222", "321122", "321221", "312212", "322112", "322211", "212123", "212321",
+  "232121", "111323", "131123", "131321", "112313", "132113", "132311", "211313",
+  "231113", "231311", "112133", "112331", "132131", "113123", "113321", "133121",
+  "313121", "211331", "231131", "213113", "213311", "213131", "311123", "311321",
+  "331121", "312113", "312311", "332111", "314111", "221411", "431111", "111224",
+  "111422", "121124", "121421", "141122", "141221", "112214", "112412", "122114",
+  "122411", "142112", "142211", "241211", "221114", "413111", "241112", "134111",
+  "111242", "121142", "121241", "114212", "124112", "124211", "411212", "421112",
+  "421211", "212141", "214121", "412121", "111143", "111341", "131141", "114113",
+  "114311", "411113", "411311", "113141", "114131", "311141", "411131", "211412",
+  "211214", "211232", "2331112",
+];
+
+const CODE128_STOP: usize = 106;
+
+/// Characters of Code 39 and their narrow (``n``) and wide (``w``) elements,
+/// alternating bar and space starting with a bar
+const CODE39_PATTERNS: [(u8, &str); 44] = [
+  (b'0', "nnnwwnwnn"), (b'1', "wnnwnnnnw"), (b'2', "nnwwnnnnw"), (b'3', "wnwwnnnnn"),
+  (b'4', "nnnwwnnnw"), (b'5', "wnnwwnnnn"), (b'6', "nnwwwnnnn"), (b'7', "nnnwnnwnw"),
+  (b'8', "wnnwnnwnn"), (b'9', "nnwwnnwnn"), (b'A', "wnnnnwnnw"), (b'B', "nnwnnwnnw"),
+  (b'C', "wnwnnwnnn"), (b'D', "nnnnwwnnw"), (b'E', "wnnnwwnnn"), (b'F', "nnwnwwnnn"),
+  (b'G', "nnnnnwwnw"), (b'H', "wnnnnwwnn"), (b'I', "nnwnnwwnn"), (b'J', "nnnnwwwnn"),
+  (b'K', "wnnnnnnww"), (b'L', "nnwnnnnww"), (b'M', "wnwnnnnwn"), (b'N', "nnnnwnnww"),
+  (b'O', "wnnnwnnwn"), (b'P', "nnwnwnnwn"), (b'Q', "nnnnnnwww"), (b'R', "wnnnnnwwn"),
+  (b'S', "nnwnnnwwn"), (b'T', "nnnnwnwwn"), (b'U', "wwnnnnnnw"), (b'V', "nwwnnnnnw"),
+  (b'W', "wwwnnnnnn"), (b'X', "nwnnwnnnw"), (b'Y', "wwnnwnnnn"), (b'Z', "nwwnwnnnn"),
+  (b'-', "nwnnnnwnw"), (b'.', "wwnnnnwnn"), (b' ', "nwwnnnwnn"), (b'*', "nwnnwnwnn"),
+  (b'$', "nwnwnwnnn"), (b'/', "nwnwnnnwn"), (b'+', "nwnnnwnwn"), (b'%', "nnnwnwnwn"),
+];
+
+/// Width of a wide Code 39 element in modules
+const CODE39_WIDE: usize = 3;
+
+/// The 7 modules of each digit in the left half of an EAN or UPC code with
+/// odd parity, the "L" code. The even parity "G" code is the right half "R"
+/// code reversed, and the "R" code is the "L" code with every module flipped.
+const EAN_L_CODES: [u8; 10] = [
+  0b0001101, 0b0011001, 0b0010011, 0b0111101, 0b0100011,
+  0b0110001, 0b0101111, 0b0111011, 0b0110111, 0b0001011,
+];
+
+/// Which digits of the left half of an EAN-13 code use the "G" code, from
+/// the most significant bit, for each value of the first digit
+const EAN13_PARITY: [u8; 10] = [
+  0b000000, 0b001011, 0b001101, 0b001110, 0b010011,
+  0b011001, 0b011100, 0b010101, 0b010110, 0b011010,
+];
+
+/// # About
+/// Encodes ``data`` as the modules of a ``symbology`` barcode, ``true`` for
+/// the bars, along with the text printed under it. ``data`` is checked like
+/// in ``Printer::print_barcode``, Code 128 taking the same ``{A``, ``{B``,
+/// ``{C``, ``{S``, ``{1`` to ``{4`` and ``{{`` escapes, with code set C
+/// values given as bytes from 0 to 99.
+/// # Examples
+/// ```
+/// let (modules, text) = barcode::encode(Barcode::Ean8, b"9638507")?;
+/// assert_eq!(modules.len(), 67);
+/// assert_eq!(text, "96385074");
+/// ```
+/// # Errors
+/// - ``PrinterError::InvalidArgument`` if ``data`` can't be encoded with
+///   ``symbology``
+pub fn encode(symbology: Barcode, data: &[u8]) -> Result<(Vec<bool>, String), PrinterError> {
+  let data = barcode_data(symbology, data)?;
+  match symbology {
+    Barcode::UpcA => {
+      // a UPC-A code is an EAN-13 code starting with 0
+      let digits = with_check_digit(&data, 12);
+      let (modules, _) = encode_ean13(&[b"0", digits.as_slice()].concat());
+      Ok((modules, String::from_utf8_lossy(&digits).into_owned()))
+    },
+    Barcode::Ean13 => Ok(encode_ean13(&with_check_digit(&data, 13))),
+    Barcode::Ean8 => Ok(encode_ean8(&with_check_digit(&data, 8))),
+    Barcode::Code39 => Ok(encode_code39(&data)),
+    Barcode::Code128 => encode_code128(&data)
+  }
+}
+
+/// # About
+/// Draws a ``symbology`` barcode holding ``data`` as a ``BitImage``, each
+/// module ``options.module_width`` dots wide, with the text below the bars
+/// when ``options.text`` is set, see ``encode``.
+/// # Examples
+/// ```
+/// let bitmap = barcode::render(Barcode::Code128, b"ORDER-1234", &BarcodeOptions::default())?;
+/// ```
+/// # Errors
+/// - ``PrinterError::InvalidArgument`` if ``data`` can't be encoded with
+///   ``symbology``, or ``options`` has a module width or height of 0
+pub fn render(symbology: Barcode, data: &[u8], options: &BarcodeOptions) -> Result<BitImage, PrinterError> {
+  if options.module_width == 0 || options.height == 0 {
+    return Err(PrinterError::InvalidArgument(format!(
+      "barcode modules of {}x{} dots can't be drawn", options.module_width, options.height
+    )))
+  }
+  let (modules, text) = encode(symbology, data)?;
+  let module_width = options.module_width as usize;
+  let (width, bar_height) = (modules.len() * module_width, options.height as usize);
+
+  let text = match options.text {
+    true => Some(font::render_text(&text, width, 1)),
+    false => None
+  };
+  // a blank row of dots between the bars and the text
+  let text_height = text.as_ref().map(|t| t.get_height() + 2).unwrap_or(0);
+
+  let mut bitmap = BitImage::from_fn(width, bar_height + text_height, |x, y| {
+    y < bar_height && modules[x / module_width]
+  });
+  if let Some(text) = text {
+    let x = width.saturating_sub(text.get_width()) / 2;
+    bitmap.paste(&text, x as isize, (bar_height + 2) as isize);
+  }
+  Ok(bitmap)
+}
+
+/// Appends the check digit to UPC/EAN ``digits`` that are shorter than
+/// ``len`` with it, ``barcode_data`` already checked the ones that have it.
+fn with_check_digit(digits: &[u8], len: usize) -> Vec<u8> {
+  let mut digits = digits.to_vec();
+  if digits.len() < len {
+    digits.push(b'0' + barcode_check_digit(&digits));
+  }
+  digits
+}
+
+/// Appends the ``bits`` lowest bits of ``code``, most significant first
+fn push_bits(modules: &mut Vec<bool>, code: u8, bits: u32) {
+  modules.extend((0..bits).rev().map(|bit| code >> bit & 1 != 0));
+}
+
+/// Appends alternating bars and spaces of the given widths, starting with a
+/// bar
+fn push_widths(modules: &mut Vec<bool>, widths: impl Iterator<Item = usize>) {
+  for (i, width) in widths.enumerate() {
+    modules.extend(std::iter::repeat_n(i % 2 == 0, width));
+  }
+}
+
+fn encode_ean13(digits: &[u8]) -> (Vec<bool>, String) {
+  let values: Vec<u8> = digits.iter().map(|d| d - b'0').collect();
+  let parity = EAN13_PARITY[values[0] as usize];
+  let mut modules = Vec::with_capacity(95);
+  push_bits(&mut modules, 0b101, 3);
+  for (i, value) in values[1..7].iter().enumerate() {
+    let l_code = EAN_L_CODES[*value as usize];
+    let code = match parity >> (5 - i) & 1 {
+      // G code, the R code reversed
+      1 => (!l_code & 0x7f).reverse_bits() >> 1,
+      _ => l_code
+    };
+    push_bits(&mut modules, code, 7);
+  }
+  push_bits(&mut modules, 0b01010, 5);
+  for value in &values[7..] {
+    push_bits(&mut modules, !EAN_L_CODES[*value as usize] & 0x7f, 7);
+  }
+  push_bits(&mut modules, 0b101, 3);
+  (modules, String::from_utf8_lossy(digits).into_owned())
+}
+
+fn encode_ean8(digits: &[u8]) -> (Vec<bool>, String) {
+  let mut modules = Vec::with_capacity(67);
+  push_bits(&mut modules, 0b101, 3);
+  for digit in &digits[..4] {
+    push_bits(&mut modules, EAN_L_CODES[(digit - b'0') as usize], 7);
+  }
+  push_bits(&mut modules, 0b01010, 5);
+  for digit in &digits[4..] {
+    push_bits(&mut modules, !EAN_L_CODES[(digit - b'0') as usize] & 0x7f, 7);
+  }
+  push_bits(&mut modules, 0b101, 3);
+  (modules, String::from_utf8_lossy(digits).into_owned())
+}
+
+fn encode_code39(data: &[u8]) -> (Vec<bool>, String) {
+  let text = format!("*{}*", String::from_utf8_lossy(data));
+  let mut modules = Vec::new();
+  for (i, c) in text.bytes().enumerate() {
+    if i > 0 {
+      // narrow space between characters
+      modules.push(false);
+    }
+    // barcode_data only lets through characters in the table
+    let (_, pattern) = CODE39_PATTERNS.iter().find(|(k, _)| *k == c).unwrap();
+    push_widths(&mut modules, pattern.bytes().map(|e| if e == b'w' { CODE39_WIDE } else { 1 }));
+  }
+  (modules, text)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CodeSet {
+  A,
+  B,
+  C,
+}
+
+fn encode_code128(data: &[u8]) -> Result<(Vec<bool>, String), PrinterError> {
+  let invalid = |reason: String| Err(PrinterError::InvalidArgument(format!(
+    "Code128 barcode {:?} {}", String::from_utf8_lossy(data), reason
+  )));
+  let value_in = |set: CodeSet, c: u8| match set {
+    CodeSet::A if c < 32 => Some(c + 64),
+    CodeSet::A if c < 96 => Some(c - 32),
+    CodeSet::B if (32..128).contains(&c) => Some(c - 32),
+    CodeSet::C if c < 100 => Some(c),
+    _ => None
+  };
+
+  let mut values: Vec<u8> = Vec::new();
+  let mut text = String::new();
+  let mut set = CodeSet::B;
+  // code set the next character is read in, after a shift
+  let mut shifted: Option<CodeSet> = None;
+  let mut i = 0;
+  while i < data.len() {
+    let c = data[i];
+    if c == b'{' && i + 1 < data.len() && data[i + 1] != b'{' {
+      let escape = data[i + 1];
+      let value = match (escape, set) {
+        (b'A' | b'B' | b'C', _) => {
+          let new_set = match escape {
+            b'A' => CodeSet::A,
+            b'B' => CodeSet::B,
+            _ => CodeSet::C
+          };
+          let value = match (values.is_empty(), new_set) {
+            (true, CodeSet::A) => 103,
+            (true, CodeSet::B) => 104,
+            (true, CodeSet::C) => 105,
+            (false, CodeSet::A) => 101,
+            (false, CodeSet::B) => 100,
+            (false, CodeSet::C) => 99
+          };
+          set = new_set;
+          value
+        },
+        (b'S', CodeSet::A) => {
+          shifted = Some(CodeSet::B);
+          98
+        },
+        (b'S', CodeSet::B) => {
+          shifted = Some(CodeSet::A);
+          98
+        },
+        (b'1', _) => 102,
+        (b'2', CodeSet::A | CodeSet::B) => 97,
+        (b'3', CodeSet::A | CodeSet::B) => 96,
+        (b'4', CodeSet::A) => 101,
+        (b'4', CodeSet::B) => 100,
+        _ => return invalid(format!("has {{{} where it can't be used", escape as char))
+      };
+      values.push(value);
+      i += 2;
+      continue;
+    }
+    // a doubled brace is a literal one
+    if c == b'{' {
+      i += 1;
+    }
+    let char_set = shifted.take().unwrap_or(set);
+    match value_in(char_set, c) {
+      Some(value) => values.push(value),
+      None => return invalid(format!("has byte {} that code set {} can't encode", c, match char_set {
+        CodeSet::A => 'A',
+        CodeSet::B => 'B',
+        CodeSet::C => 'C'
+      }))
+    }
+    match char_set {
+      CodeSet::C => text.push_str(&format!("{:02}", c)),
+      _ if c.is_ascii_graphic() || c == b' ' => text.push(c as char),
+      _ => ()
+    }
+    i += 1;
+  }
+  if values.len() < 2 {
+    return invalid("has no characters".to_string())
+  }
+
+  let checksum = values.iter().enumerate()
+    .map(|(i, value)| i.max(1) * *value as usize)
+    .sum::<usize>() % 103;
+  let mut modules = Vec::new();
+  for value in values.iter().map(|v| *v as usize).chain([checksum, CODE128_STOP]) {
+    push_widths(&mut modules, CODE128_PATTERNS[value].bytes().map(|w| (w - b'0') as usize));
+  }
+  Ok((modules, text))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Reads the widths of the alternating bars and spaces of ``modules``
+  fn widths(modules: &[bool]) -> Vec<usize> {
+    let mut widths = Vec::new();
+    let mut run = 0;
+    for (i, module) in modules.iter().enumerate() {
+      run += 1;
+      if modules.get(i + 1) != Some(module) {
+        widths.push(run);
+        run = 0;
+      }
+    }
+    widths
+  }
+
+  #[test]
+  fn code128_patterns_are_well_formed() {
+    for (value, pattern) in CODE128_PATTERNS[..CODE128_STOP].iter().enumerate() {
+      let widths: Vec<u32> = pattern.bytes().map(|w| (w - b'0') as u32).collect();
+      assert_eq!(widths.iter().sum::<u32>(), 11, "value {}", value);
+      // the bars of every symbol add up to an even number of modules
+      assert_eq!((widths[0] + widths[2] + widths[4]) % 2, 0, "value {}", value);
+    }
+    let mut unique = CODE128_PATTERNS.to_vec();
+    unique.sort();
+    unique.dedup();
+    assert_eq!(unique.len(), CODE128_PATTERNS.len());
+  }
+
+  #[test]
+  fn code128_decodes_back_to_its_values() {
+    let (modules, text) = encode(Barcode::Code128, b"Wikipedia").unwrap();
+    assert_eq!(text, "Wikipedia");
+    // start, 9 characters, checksum, and the 13 module stop
+    assert_eq!(modules.len(), 11 * 11 + 13);
+    let values: Vec<usize> = modules[..11 * 11].chunks(11)
+      .map(|symbol| {
+        let pattern: String = widths(symbol).iter().map(|w| w.to_string()).collect();
+        CODE128_PATTERNS.iter().position(|p| *p == pattern).unwrap()
+      })
+      .collect();
+    // start B, the characters, then (104 + 1 * 55 + 2 * 73 + ...) % 103
+    assert_eq!(values, [104, 55, 73, 75, 73, 80, 69, 68, 73, 65, 88]);
+  }
+
+  #[test]
+  fn code128_code_sets() {
+    let (_, text) = encode(Barcode::Code128, &[b'{', b'C', 12, 34, b'{', b'B', b'X']).unwrap();
+    assert_eq!(text, "1234X");
+    assert!(encode(Barcode::Code128, &[b'{', b'C', 100]).is_err());
+    assert!(encode(Barcode::Code128, b"{A").is_err());
+    let (_, text) = encode(Barcode::Code128, b"{Ba{{b").unwrap();
+    assert_eq!(text, "a{b");
+  }
+
+  #[test]
+  fn code128_braces_in_plain_text_are_literal() {
+    let (modules, text) = encode(Barcode::Code128, b"a{b").unwrap();
+    assert_eq!(text, "a{b");
+    // start, 3 characters, checksum and stop, the brace being a single value
+    assert_eq!(modules.len(), 5 * 11 + 13);
+    assert_eq!(encode(Barcode::Code128, b"{Ba{{b").unwrap().0, modules);
+    let (_, text) = encode(Barcode::Code128, b"{").unwrap();
+    assert_eq!(text, "{");
+  }
+
+  #[test]
+  fn code39_has_three_wide_elements_per_character() {
+    for (c, pattern) in CODE39_PATTERNS {
+      assert_eq!(pattern.len(), 9, "{}", c as char);
+      assert_eq!(pattern.bytes().filter(|e| *e == b'w').count(), 3, "{}", c as char);
+    }
+    let (modules, text) = encode(Barcode::Code39, b"A1").unwrap();
+    assert_eq!(text, "*A1*");
+    // 4 characters of 6 narrow and 3 wide elements, and 3 gaps between them
+    assert_eq!(modules.len(), 4 * (6 + 3 * CODE39_WIDE) + 3);
+    assert!(encode(Barcode::Code39, b"a").is_err());
+  }
+
+  #[test]
+  fn ean13_layout() {
+    let (modules, text) = encode(Barcode::Ean13, b"400638133393").unwrap();
+    assert_eq!(text, "4006381333931");
+    assert_eq!(modules.len(), 95);
+    let bits = |range: std::ops::Range<usize>| -> u8 {
+      modules[range].iter().fold(0, |code, m| code << 1 | *m as u8)
+    };
+    assert_eq!(bits(0..3), 0b101);
+    assert_eq!(bits(45..50), 0b01010);
+    assert_eq!(bits(92..95), 0b101);
+    // the first digit, 4, makes the second digit L and the third G
+    assert_eq!(bits(3..10), EAN_L_CODES[0]);
+    assert_eq!(bits(10..17), 0b0100111);
+    // the right half uses R codes, the last digit being the check digit 1
+    assert_eq!(bits(85..92), !EAN_L_CODES[1] & 0x7f);
+  }
+
+  #[test]
+  fn upca_and_ean8_lengths() {
+    let (modules, text) = encode(Barcode::UpcA, b"03600029145").unwrap();
+    assert_eq!(text, "036000291452");
+    assert_eq!(modules.len(), 95);
+    let (modules, text) = encode(Barcode::Ean8, b"9638507").unwrap();
+    assert_eq!(text, "96385074");
+    assert_eq!(modules.len(), 67);
+  }
+
+  #[test]
+  fn render_scales_modules_and_adds_text() {
+    let options = BarcodeOptions { module_width: 2, height: 40, text: true, vertical: false };
+    let bitmap = render(Barcode::Ean8, b"9638507", &options).unwrap();
+    assert_eq!(bitmap.get_width(), 67 * 2);
+    assert_eq!(bitmap.get_height(), 40 + 2 + font::GLYPH_SIZE);
+    // the start guard, bar space bar, 2 dots per module
+    let row: Vec<bool> = (0..6).map(|x| bitmap.get_pixel(x, 0)).collect();
+    assert_eq!(row, [true, true, false, false, true, true]);
+
+    let bars_only = BarcodeOptions { text: false, ..options };
+    assert_eq!(render(Barcode::Ean8, b"9638507", &bars_only).unwrap().get_height(), 40);
+  }
+}
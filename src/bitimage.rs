@@ -1,3 +1,44 @@
+use std::fmt;
+
+/// # About
+/// Order of the pixels inside each byte of a bitmap when it's sent.
+///
+/// ``BitImage`` always stores them ``MsbFirst``, which is what ``GS v 0`` and
+/// ``GS ( L`` expect on almost every printer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BitOrder {
+  /// The most significant bit is the leftmost pixel
+  #[default]
+  MsbFirst,
+  /// The least significant bit is the leftmost pixel
+  LsbFirst,
+}
+
+/// # About
+/// Clockwise rotation applied by ``BitImage::rotate``
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+  Cw90,
+  Cw180,
+  Cw270,
+}
+
+/// # About
+/// Returned when reading or writing a pixel outside of a ``BitImage``
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutOfBounds {
+  pub x: isize,
+  pub y: isize,
+}
+
+impl fmt::Display for OutOfBounds {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "tried to access BitImage out of bounds at coords: {}, {}", self.x, self.y)
+  }
+}
+
+impl std::error::Error for OutOfBounds {}
+
 /// # About
 /// A struct to handle a bitmap image in which each bit represents a pixel
 /// # Creating
@@ -15,8 +56,9 @@
 /// ```
 /// To use with ```Printer::print_bitmap()```
 /// ```
-/// printer.print_bitmap(bitmap.get_width() as u32, bitmap.get_height() as u32, bitmap.as_slice())
+/// printer.print_bitmap(bitmap.get_width() as u16, bitmap.get_height() as u16, bitmap.get_width_in_bytes(), bitmap.as_slice())?;
 /// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct BitImage {
   bytes: Vec<u8>,
   width: usize,
@@ -30,18 +72,57 @@ impl BitImage {
   /// let mut bitmap = BitImage::new(128, 64);
   /// ```
   pub fn new(w: usize, h: usize) -> Self {
-    let mut bytes_vec = Vec::with_capacity((w as f64 / 8.0).ceil() as usize * h);
-    for i in 0..bytes_vec.capacity() {
-      bytes_vec.push(0);
-    }
+    // every row starts on a new byte, the last one padded with unset bits,
+    // so the pixel at x, y is always in byte x / 8 + y * w_bytes, which is
+    // below w_bytes * h for any x < w and y < h
+    let w_bytes = w.div_ceil(8);
     BitImage {
-      bytes: bytes_vec,
+      // the length is set exactly, a Vec may be given more capacity than
+      // requested, which would make as_slice longer than the image
+      bytes: vec![0; w_bytes * h],
       width: w,
       height: h,
-      w_bytes: (w as f64 / 8.0).ceil() as usize
+      w_bytes
     }
   }
 
+  /// # About
+  /// Turns a grayscale image into a bitmap, printing the pixels darker than
+  /// ``threshold``, with no dithering.
+  ///
+  /// Handy for images that were already processed, or dithered, by other
+  /// means.
+  /// # Examples
+  /// ```
+  /// let gray = image::open("logo.png")?.to_luma8();
+  /// let bitmap = BitImage::from_luma(&gray, 128);
+  /// ```
+  pub fn from_luma(img: &image::GrayImage, threshold: u8) -> Self {
+    BitImage::from_fn(img.width() as usize, img.height() as usize, |x, y| {
+      img.get_pixel(x as u32, y as u32).0[0] < threshold
+    })
+  }
+
+  /// # About
+  /// Creates a ``w`` by ``h`` bitmap with the pixels for which ``f(x, y)``
+  /// returns ``true`` set.
+  /// # Examples
+  /// ```
+  /// // a checkerboard of 8x8 squares
+  /// let checkers = BitImage::from_fn(64, 64, |x, y| (x / 8 + y / 8) % 2 == 0);
+  /// ```
+  pub fn from_fn(w: usize, h: usize, f: impl Fn(usize, usize) -> bool) -> Self {
+    let mut bitmap = BitImage::new(w, h);
+    for y in 0..h {
+      for x in 0..w {
+        if f(x, y) {
+          bitmap.set_pixel(x as isize, y as isize, true);
+        }
+      }
+    }
+    bitmap
+  }
+
   pub fn get_width(&self) -> usize {
     self.width
   }
@@ -54,38 +135,409 @@ impl BitImage {
     self.w_bytes
   }
 
-  fn is_within_bounds(&self, x: isize, y: isize) {
-    if x < 0 || x >= self.width as isize || y < 0 || y >= self.height as isize {
-      panic!("tried to read out of bounds at coords: {}, {}", x, y);
-    }
+  fn is_within_bounds(&self, x: isize, y: isize) -> bool {
+    x >= 0 && x < self.width as isize && y >= 0 && y < self.height as isize
   }
 
-  pub fn get_pixel(&self, x: isize, y: isize) -> bool {
-    self.is_within_bounds(x, y);
-    let position: u8 = 1 << x % 8;
-    let pixel_byte: &u8 = match self.bytes.get(x as usize / 8 + (y as usize * self.w_bytes)) {
-      Some(o) => o,
-      None => panic!("tried to read BitImage pixel that is out of bounds!")
-    };
-    return *pixel_byte | position == *pixel_byte
+  /// # About
+  /// The pixel at ``x``, ``y``, or ``None`` if it's outside of the image.
+  /// Preferred over ``get_pixel``, which panics instead.
+  pub fn try_get_pixel(&self, x: isize, y: isize) -> Option<bool> {
+    if !self.is_within_bounds(x, y) {
+      return None
+    }
+    let position: u8 = 128 >> (x % 8);
+    let pixel_byte = self.bytes.get(x as usize / 8 + (y as usize * self.w_bytes))?;
+    Some(*pixel_byte | position == *pixel_byte)
   }
 
-  pub fn set_pixel(&mut self, x:isize, y: isize, val: bool) {
-    self.is_within_bounds(x, y);
-    let position: u8 = 128 >> x % 8;
-    let byte_pos = (x as f64 / 8.0).floor() as usize + (y as usize * self.w_bytes);
-    let pixel_byte: &mut u8 = match self.bytes.get_mut((x as f64 / 8.0).floor() as usize + (y as usize * self.w_bytes)) {
+  /// # About
+  /// Sets the pixel at ``x``, ``y``, failing without touching the image if
+  /// it's outside of it. Preferred over ``set_pixel``, which panics instead.
+  pub fn try_set_pixel(&mut self, x: isize, y: isize, val: bool) -> Result<(), OutOfBounds> {
+    if !self.is_within_bounds(x, y) {
+      return Err(OutOfBounds { x, y })
+    }
+    let position: u8 = 128 >> (x % 8);
+    let pixel_byte: &mut u8 = match self.bytes.get_mut(x as usize / 8 + (y as usize * self.w_bytes)) {
       Some(o) => o,
-      None => panic!("tried to read BitImage pixel that is out of bounds at: {}, {}", x, y)
+      None => return Err(OutOfBounds { x, y })
     };
     if val{
       *pixel_byte |= position;
     } else {
-      *pixel_byte = *pixel_byte & !position;
+      *pixel_byte &= !position;
+    }
+    Ok(())
+  }
+
+  /// # Panics
+  /// - if ``x``, ``y`` is outside of the image, see ``try_get_pixel``
+  pub fn get_pixel(&self, x: isize, y: isize) -> bool {
+    match self.try_get_pixel(x, y) {
+      Some(o) => o,
+      None => panic!("{}", OutOfBounds { x, y })
+    }
+  }
+
+  /// # Panics
+  /// - if ``x``, ``y`` is outside of the image, see ``try_set_pixel``
+  pub fn set_pixel(&mut self, x:isize, y: isize, val: bool) {
+    if let Err(e) = self.try_set_pixel(x, y, val) {
+      panic!("{}", e);
+    }
+  }
+
+  /// # About
+  /// Returns a copy of the image rotated clockwise by ``rotation``.
+  ///
+  /// Width and height are swapped for ``Cw90`` and ``Cw270``, with the rows
+  /// packed again for the new width.
+  /// # Examples
+  /// ```
+  /// let bitmap = BitImage::new(128, 64);
+  /// let rotated = bitmap.rotate(Rotation::Cw90);
+  /// assert_eq!(rotated.get_width(), 64);
+  /// assert_eq!(rotated.get_height(), 128);
+  /// ```
+  pub fn rotate(&self, rotation: Rotation) -> BitImage {
+    let (w, h) = (self.width as isize, self.height as isize);
+    let mut rotated = match rotation {
+      Rotation::Cw180 => BitImage::new(self.width, self.height),
+      _ => BitImage::new(self.height, self.width)
+    };
+    for y in 0..h {
+      for x in 0..w {
+        if !self.get_pixel(x, y) {
+          continue;
+        }
+        let (new_x, new_y) = match rotation {
+          Rotation::Cw90 => (h - 1 - y, x),
+          Rotation::Cw180 => (w - 1 - x, h - 1 - y),
+          Rotation::Cw270 => (y, w - 1 - x)
+        };
+        rotated.set_pixel(new_x, new_y, true);
+      }
+    }
+    rotated
+  }
+
+  /// # About
+  /// Returns a copy of the image mirrored left to right.
+  /// # Examples
+  /// ```
+  /// let mirrored = bitmap.flip_horizontal();
+  /// assert_eq!(mirrored.get_pixel(0, 0), bitmap.get_pixel(bitmap.get_width() as isize - 1, 0));
+  /// ```
+  pub fn flip_horizontal(&self) -> BitImage {
+    let mut flipped = BitImage::new(self.width, self.height);
+    let w = self.width as isize;
+    for y in 0..self.height as isize {
+      for x in 0..w {
+        if self.get_pixel(x, y) {
+          flipped.set_pixel(w - 1 - x, y, true);
+        }
+      }
+    }
+    flipped
+  }
+
+  /// # About
+  /// Returns a copy of the ``w`` by ``h`` region with its top-left corner at
+  /// ``x``, ``y``, packed again for its own width.
+  ///
+  /// The region is clipped to the image, so the copy is smaller when it
+  /// reaches past the edges.
+  /// # Examples
+  /// ```
+  /// let bitmap = BitImage::new(128, 64);
+  /// let corner = bitmap.crop(96, 32, 64, 64);
+  /// assert_eq!(corner.get_width(), 32);
+  /// assert_eq!(corner.get_height(), 32);
+  /// ```
+  pub fn crop(&self, x: usize, y: usize, w: usize, h: usize) -> BitImage {
+    let x = x.min(self.width);
+    let y = y.min(self.height);
+    let w = w.min(self.width - x);
+    let h = h.min(self.height - y);
+    BitImage::from_fn(w, h, |src_x, src_y| {
+      self.get_pixel((x + src_x) as isize, (y + src_y) as isize)
+    })
+  }
+
+  /// # About
+  /// Copies ``src`` onto this image with its top-left corner at ``x``, ``y``.
+  ///
+  /// Parts of ``src`` falling outside of this image are clipped.
+  /// # Examples
+  /// ```
+  /// let mut strip = BitImage::new(384, 64);
+  /// let logo = BitImage::new(64, 64);
+  /// strip.paste(&logo, 160, 0);
+  /// ```
+  pub fn paste(&mut self, src: &BitImage, x: isize, y: isize) {
+    for src_y in 0..src.height as isize {
+      for src_x in 0..src.width as isize {
+        let (dest_x, dest_y) = (x + src_x, y + src_y);
+        if dest_x < 0 || dest_x >= self.width as isize || dest_y < 0 || dest_y >= self.height as isize {
+          continue;
+        }
+        self.set_pixel(dest_x, dest_y, src.get_pixel(src_x, src_y));
+      }
+    }
+  }
+
+  /// # About
+  /// Like ``paste``, but only copies the pixels of ``src`` whose pixel in
+  /// ``mask`` is set, leaving this image untouched under the rest. ``mask``
+  /// must be the same size as ``src``.
+  /// # Panics
+  /// - if ``mask`` is smaller than ``src``
+  pub fn paste_masked(&mut self, src: &BitImage, mask: &BitImage, x: isize, y: isize) {
+    for src_y in 0..src.height as isize {
+      for src_x in 0..src.width as isize {
+        let (dest_x, dest_y) = (x + src_x, y + src_y);
+        if dest_x < 0 || dest_x >= self.width as isize || dest_y < 0 || dest_y >= self.height as isize {
+          continue;
+        }
+        if mask.get_pixel(src_x, src_y) {
+          self.set_pixel(dest_x, dest_y, src.get_pixel(src_x, src_y));
+        }
+      }
+    }
+  }
+
+  /// # About
+  /// Flips every pixel, for printing white on black.
+  ///
+  /// The bits padding the last byte of each row when the width isn't a
+  /// multiple of 8 are kept clear, so they don't print a black stripe on
+  /// printers that don't ignore them.
+  /// # Examples
+  /// ```
+  /// let mut bitmap = BitImage::new(9, 1);
+  /// bitmap.invert();
+  /// assert_eq!(bitmap.as_slice(), &[0xff, 0x80]);
+  /// ```
+  pub fn invert(&mut self) {
+    for byte in self.bytes.iter_mut() {
+      *byte ^= 0xff;
+    }
+    let padding = self.w_bytes * 8 - self.width;
+    if padding == 0 {
+      return
+    }
+    let last_byte_mask = 0xffu8 << padding;
+    for row in self.bytes.chunks_mut(self.w_bytes) {
+      if let Some(last) = row.last_mut() {
+        *last &= last_byte_mask;
+      }
     }
   }
 
+  /// # About
+  /// Fraction of the pixels that are set, from 0.0 for a blank image to 1.0
+  /// for a solid black one, roughly how much of the paper the print blackens.
+  pub fn coverage(&self) -> f32 {
+    self.coverage_in(0, 0, self.width, self.height)
+  }
+
+  /// # About
+  /// ``coverage`` of the ``w`` by ``h`` area with its top-left corner at ``x``,
+  /// ``y``, clipped to the image. Empty areas have no coverage.
+  pub fn coverage_in(&self, x: usize, y: usize, w: usize, h: usize) -> f32 {
+    let (x_end, y_end) = ((x + w).min(self.width), (y + h).min(self.height));
+    if x >= x_end || y >= y_end {
+      return 0.0
+    }
+    let mut set = 0usize;
+    for pixel_y in y..y_end {
+      for pixel_x in x..x_end {
+        if self.get_pixel(pixel_x as isize, pixel_y as isize) {
+          set += 1;
+        }
+      }
+    }
+    set as f32 / ((x_end - x) * (y_end - y)) as f32
+  }
+
   pub fn as_slice(&self) -> &[u8]{
     self.bytes.as_slice()
   }
 }
+
+/// # About
+/// Draws the image with half block characters, two rows of dots per line of
+/// text, with set dots drawn as blocks. Handy for previewing images in a
+/// terminal without wasting paper.
+/// # Examples
+/// ```
+/// println!("{}", bitmap);
+/// ```
+impl fmt::Display for BitImage {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for y in (0..self.height as isize).step_by(2) {
+      for x in 0..self.width as isize {
+        let top = self.get_pixel(x, y);
+        let bottom = y + 1 < self.height as isize && self.get_pixel(x, y + 1);
+        let c = match (top, bottom) {
+          (true, true) => '█',
+          (true, false) => '▀',
+          (false, true) => '▄',
+          (false, false) => ' '
+        };
+        write!(f, "{}", c)?;
+      }
+      writeln!(f)?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const WIDTHS: [usize; 5] = [1, 7, 9, 17, 127];
+
+  /// Whether the bits past ``width`` in the last byte of every row are clear
+  fn padding_is_clear(img: &BitImage) -> bool {
+    let padding = img.get_width_in_bytes() * 8 - img.get_width();
+    let mask = !(0xffu8 << padding);
+    img.as_slice()
+      .chunks(img.get_width_in_bytes())
+      .all(|row| row.last().is_none_or(|last| last & mask == 0))
+  }
+
+  #[test]
+  fn new_allocates_whole_rows() {
+    for width in WIDTHS {
+      let img = BitImage::new(width, 3);
+      assert_eq!(img.get_width_in_bytes(), width.div_ceil(8));
+      assert_eq!(img.as_slice().len(), width.div_ceil(8) * 3);
+    }
+  }
+
+  #[test]
+  fn every_pixel_is_addressable() {
+    for width in WIDTHS {
+      let height = 3;
+      let mut img = BitImage::new(width, height);
+      for y in 0..height as isize {
+        for x in 0..width as isize {
+          img.set_pixel(x, y, true);
+          assert!(img.get_pixel(x, y), "{}x{} at {}, {}", width, height, x, y);
+          assert!(padding_is_clear(&img), "padding set by {}, {} at width {}", x, y, width);
+        }
+      }
+      assert_eq!(img.coverage(), 1.0);
+
+      for y in 0..height as isize {
+        for x in 0..width as isize {
+          img.set_pixel(x, y, false);
+          assert!(!img.get_pixel(x, y));
+        }
+      }
+      assert!(img.as_slice().iter().all(|byte| *byte == 0));
+    }
+  }
+
+  #[test]
+  fn pixels_outside_are_rejected() {
+    for width in WIDTHS {
+      let mut img = BitImage::new(width, 2);
+      let w = width as isize;
+      assert_eq!(img.try_get_pixel(w, 0), None);
+      assert_eq!(img.try_get_pixel(0, 2), None);
+      assert_eq!(img.try_get_pixel(-1, 0), None);
+      assert!(img.try_set_pixel(w, 0, true).is_err());
+      assert!(img.try_set_pixel(0, -1, true).is_err());
+      assert!(img.as_slice().iter().all(|byte| *byte == 0));
+    }
+  }
+
+  /// An asymmetric pattern, so any wrong rotation or flip shows up
+  fn pattern(w: usize, h: usize) -> BitImage {
+    BitImage::from_fn(w, h, |x, y| (x * 3 + y * 5) % 7 < 3 || (x == 0 && y == 0))
+  }
+
+  #[test]
+  fn rotate_round_trips() {
+    let img = pattern(13, 5);
+    let quarter = img.rotate(Rotation::Cw90);
+    assert_eq!((quarter.get_width(), quarter.get_height()), (5, 13));
+    assert_eq!(quarter.get_width_in_bytes(), 1);
+    assert!(padding_is_clear(&quarter));
+
+    let full_turn = quarter.rotate(Rotation::Cw90).rotate(Rotation::Cw90).rotate(Rotation::Cw90);
+    assert_eq!(full_turn, img);
+    assert_eq!(img.rotate(Rotation::Cw180).rotate(Rotation::Cw180), img);
+    assert_eq!(quarter.rotate(Rotation::Cw270), img);
+    assert_eq!(img.rotate(Rotation::Cw90).rotate(Rotation::Cw90), img.rotate(Rotation::Cw180));
+  }
+
+  #[test]
+  fn rotate_moves_corners_clockwise() {
+    let mut img = BitImage::new(13, 5);
+    img.set_pixel(0, 0, true);
+    // the top-left corner ends up top-right, then bottom-right, then bottom-left
+    assert!(img.rotate(Rotation::Cw90).get_pixel(4, 0));
+    assert!(img.rotate(Rotation::Cw180).get_pixel(12, 4));
+    assert!(img.rotate(Rotation::Cw270).get_pixel(0, 12));
+  }
+
+  #[test]
+  fn paste_at_odd_offsets() {
+    let square = BitImage::from_fn(3, 3, |_, _| true);
+    for x in [1, 3, 5, 7, 9, 14, -3] {
+      let mut strip = BitImage::new(17, 5);
+      strip.paste(&square, x, 1);
+      for dest_y in 0..5 {
+        for dest_x in 0..17 {
+          let inside = (x..x + 3).contains(&dest_x) && (1..4).contains(&dest_y);
+          assert_eq!(strip.get_pixel(dest_x, dest_y), inside, "square at {}, pixel {}, {}", x, dest_x, dest_y);
+        }
+      }
+      assert!(padding_is_clear(&strip));
+    }
+  }
+
+  #[test]
+  fn paste_clips_at_the_edges() {
+    let square = BitImage::from_fn(3, 3, |_, _| true);
+    let mut img = BitImage::new(9, 4);
+    img.paste(&square, 7, 2);
+    img.paste(&square, -2, -2);
+    img.paste(&square, 20, 20);
+    let expected = BitImage::from_fn(9, 4, |x, y| (x >= 7 && y >= 2) || (x == 0 && y == 0));
+    assert_eq!(img, expected);
+  }
+
+  #[test]
+  fn crop_takes_back_a_pasted_square() {
+    let square = BitImage::from_fn(3, 3, |_, _| true);
+    let mut img = BitImage::new(17, 5);
+    img.paste(&square, 9, 1);
+    assert_eq!(img.crop(9, 1, 3, 3), square);
+    // clipped to the image
+    let corner = img.crop(15, 3, 8, 8);
+    assert_eq!((corner.get_width(), corner.get_height()), (2, 2));
+  }
+
+  #[test]
+  fn invert_keeps_padding_clear() {
+    let mut img = BitImage::new(9, 3);
+    img.set_pixel(0, 0, true);
+    img.set_pixel(8, 2, true);
+    img.invert();
+    assert!(padding_is_clear(&img));
+    assert_eq!(img.as_slice(), &[0x7f, 0x80, 0xff, 0x80, 0xff, 0x00]);
+    assert!(!img.get_pixel(0, 0) && !img.get_pixel(8, 2) && img.get_pixel(8, 0));
+
+    img.invert();
+    let mut original = BitImage::new(9, 3);
+    original.set_pixel(0, 0, true);
+    original.set_pixel(8, 2, true);
+    assert_eq!(img, original);
+  }
+}
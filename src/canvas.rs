@@ -0,0 +1,169 @@
+use crate::barcode;
+use crate::bitimage::{BitImage, Rotation};
+use crate::font;
+use crate::pdf;
+use crate::printing::{Barcode, BarcodeOptions, PrinterError};
+
+/// # About
+/// A blank raster to lay out text and images on with dot precision, printed
+/// in one go with ``Printer::print_canvas``. The printer's own text engine
+/// isn't used at all, so the result looks the same on every printer.
+///
+/// Everything drawn only adds dots, so text can be drawn over images and
+/// overlapping images are merged.
+/// # Creating
+/// ```
+/// let mut ticket = Canvas::new(384, 160);
+/// ```
+/// # Usage
+/// ```
+/// ticket.draw_image(0, 0, &logo);
+/// ticket.draw_text(80, 16, "ADMIT ONE", 3);
+/// ticket.draw_text(80, 64, "Row F, Seat 12", 1);
+/// ticket.draw_barcode(80, 80, Barcode::Code128, b"F12-0042", &BarcodeOptions::default())?;
+/// printer.print_canvas(&ticket)?;
+/// ```
+pub struct Canvas {
+  image: BitImage
+}
+
+// TODO: draw_qr(x, y, data), which needs a QR encoder drawing into a
+// BitImage, the QR codes in printing are all drawn by the printer
+impl Canvas {
+  pub fn new(width: usize, height: usize) -> Self {
+    Canvas {
+      image: BitImage::new(width, height)
+    }
+  }
+
+  pub fn get_width(&self) -> usize {
+    self.image.get_width()
+  }
+
+  pub fn get_height(&self) -> usize {
+    self.image.get_height()
+  }
+
+  /// # About
+  /// Draws ``text`` with its top-left corner at ``x``, ``y`` using the built-in
+  /// 8x8 font scaled up ``scale`` times, see ``font::render_text``.
+  ///
+  /// The text wraps before the right edge of the canvas, anything past the
+  /// bottom edge is clipped.
+  pub fn draw_text(&mut self, x: isize, y: isize, text: &str, scale: usize) {
+    let width = (self.image.get_width() as isize - x.max(0)).max(0) as usize;
+    let rendered = font::render_text(text, width, scale);
+    self.draw_image(x, y, &rendered);
+  }
+
+  /// # About
+  /// Draws every set dot of ``img`` with its top-left corner at ``x``, ``y``,
+  /// clipping the parts that fall outside of the canvas.
+  pub fn draw_image(&mut self, x: isize, y: isize, img: &BitImage) {
+    for src_y in 0..img.get_height() as isize {
+      for src_x in 0..img.get_width() as isize {
+        let (dest_x, dest_y) = (x + src_x, y + src_y);
+        if dest_x < 0 || dest_x >= self.image.get_width() as isize
+          || dest_y < 0 || dest_y >= self.image.get_height() as isize {
+          continue;
+        }
+        if img.get_pixel(src_x, src_y) {
+          self.image.set_pixel(dest_x, dest_y, true);
+        }
+      }
+    }
+  }
+
+  /// # About
+  /// Draws a ``symbology`` barcode holding ``data`` with its top-left corner
+  /// at ``x``, ``y``, see ``barcode::render``. ``options.vertical`` turns it
+  /// 90° clockwise. Parts outside of the canvas are clipped, so check that it
+  /// fits if it has to be scannable.
+  /// # Errors
+  /// - ``PrinterError::InvalidArgument`` if ``data`` can't be encoded with
+  ///   ``symbology``
+  pub fn draw_barcode(
+    &mut self,
+    x: isize,
+    y: isize,
+    symbology: Barcode,
+    data: &[u8],
+    options: &BarcodeOptions
+  ) -> Result<(), PrinterError> {
+    let mut rendered = barcode::render(symbology, data, options)?;
+    if options.vertical {
+      rendered = rendered.rotate(Rotation::Cw90);
+    }
+    self.draw_image(x, y, &rendered);
+    Ok(())
+  }
+
+  pub fn as_image(&self) -> &BitImage {
+    &self.image
+  }
+
+  pub fn into_image(self) -> BitImage {
+    self.image
+  }
+
+  /// # About
+  /// Saves the canvas as a single page PDF at ``path``, with the page as wide
+  /// as the paper at ``dpi``, to archive exactly what ``print_canvas``
+  /// prints, see ``pdf::write_pdf``.
+  /// # Examples
+  /// ```
+  /// printer.print_canvas(&ticket)?;
+  /// ticket.save_pdf("ticket.pdf", printer.get_capabilities().dpi)?;
+  /// ```
+  /// # Errors
+  /// - if the file cannot be created or written
+  /// - if ``dpi`` is 0
+  pub fn save_pdf(&self, path: &str, dpi: u16) -> Result<(), PrinterError> {
+    let file = std::fs::File::create(path)?;
+    pdf::write_pdf(&self.image, dpi, std::io::BufWriter::new(file))?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::printing::Printer;
+
+  #[test]
+  fn composed_ticket() {
+    let mut ticket = Canvas::new(280, 120);
+    let logo = BitImage::from_fn(16, 16, |x, y| x == 0 || y == 0 || x == 15 || y == 15);
+    ticket.draw_image(4, 4, &logo);
+    ticket.draw_text(28, 8, "ADMIT ONE", 2);
+    let options = BarcodeOptions { module_width: 2, height: 40, text: true, vertical: false };
+    ticket.draw_barcode(4, 40, Barcode::Code128, b"F12-0042", &options).unwrap();
+    let image = ticket.as_image();
+
+    // the logo's frame and its empty middle
+    assert!(image.get_pixel(4, 4) && image.get_pixel(19, 19));
+    assert!(!image.get_pixel(12, 12));
+    // the title, 9 characters of 16 dots, and nothing past it
+    assert!(image.coverage_in(28, 8, 9 * 16, 16) > 0.0);
+    assert_eq!(image.coverage_in(28 + 9 * 16, 8, 280 - 28 - 9 * 16, 16), 0.0);
+    // every row of the bars is the same as the encoded modules
+    let (modules, text) = barcode::encode(Barcode::Code128, b"F12-0042").unwrap();
+    assert_eq!(text, "F12-0042");
+    for y in 40..80 {
+      let row: Vec<bool> = (0..modules.len() * 2).map(|x| image.get_pixel(4 + x as isize, y)).collect();
+      let expected: Vec<bool> = modules.iter().flat_map(|m| [*m, *m]).collect();
+      assert_eq!(row, expected, "row {}", y);
+    }
+    // the text under the bars, then blank paper
+    assert!(image.coverage_in(4, 82, modules.len() * 2, font::GLYPH_SIZE) > 0.0);
+    assert_eq!(image.coverage_in(0, 82 + font::GLYPH_SIZE, 280, 120 - 82 - font::GLYPH_SIZE), 0.0);
+
+    // printed as one raster, in a single band
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.set_raster_band_height(120);
+    printer.print_canvas(&ticket).unwrap();
+    let sent = printer.into_inner().unwrap();
+    assert_eq!(&sent[..8], &[0x1d, b'v', b'0', 0, 35, 0, 120, 0]);
+    assert_eq!(&sent[8..], image.as_slice());
+  }
+}
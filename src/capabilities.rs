@@ -0,0 +1,96 @@
+use crate::bitimage::BitOrder;
+
+/// # About
+/// Describes the printer's dimensions and the optional features it supports.
+///
+/// Commands that aren't part of every ESC-POS implementation check these
+/// before sending anything, since unknown commands can make some printers
+/// hang or print garbage.
+/// # Creating
+/// ```
+/// let caps = Capabilities {
+///   print_speed: true,
+///   ..Default::default()
+/// };
+/// printer.set_capabilities(caps);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities {
+  /// Printable width in dots, usually 384 on 58mm paper and 576 on 80mm
+  pub print_width: u16,
+  /// Dots per inch of the print head, 203 on almost every receipt printer
+  pub dpi: u16,
+  /// Supports selecting the print speed with ``GS ( K`` function 50
+  pub print_speed: bool,
+  /// Bytes of raster data that fit in the graphics print buffer used by
+  /// ``GS ( L``, 0 if those commands aren't supported
+  pub graphics_buffer_size: usize,
+  /// Size of the printer's buffer for ``GS v 0`` raster data in bytes, 0 if
+  /// unknown
+  pub max_raster_bytes: usize,
+  /// Order of the pixels inside each byte of raster data
+  pub bit_order: BitOrder,
+  /// Can print in red on two-color paper, see ``Printer::set_color``
+  pub two_color: bool,
+  /// Sends status responses back, which can be read by opening the printer's
+  /// path for reading
+  pub status_readback: bool,
+  /// Width to height ratio of a single dot, ``(1, 1)`` for square dots. On
+  /// printers with different horizontal and vertical resolutions images are
+  /// stretched vertically by ``dot_aspect.0 / dot_aspect.1`` so they keep
+  /// their proportions, a 180x360 dpi head would be ``(2, 1)``
+  pub dot_aspect: (u16, u16),
+  /// Lines fed by ``Printer::feed_and_cut`` before cutting, so the last line
+  /// clears the gap between the print head and the cutter. Too few cut
+  /// through the text, too many waste paper
+  pub cut_feed_lines: u8,
+  /// Supports feeding and cutting in a single command with ``GS V`` functions
+  /// 65 and 66, see ``Printer::cut_paper_with_feed``
+  pub feed_cut: bool,
+  /// Supports multiple tone graphics with ``GS ( L`` function 112, which
+  /// the printer dithers itself, see ``Dither::Printer``. Also needs a
+  /// ``graphics_buffer_size`` big enough for four planes of the image
+  pub multi_tone: bool,
+  /// Has a gap or black mark sensor to print on label paper, see
+  /// ``Printer::set_paper_type``
+  pub label_sensor: bool,
+}
+
+impl Default for Capabilities {
+  fn default() -> Self {
+    Capabilities {
+      print_width: 384,
+      dpi: 203,
+      print_speed: false,
+      graphics_buffer_size: 0,
+      max_raster_bytes: 0,
+      bit_order: BitOrder::MsbFirst,
+      two_color: false,
+      status_readback: false,
+      dot_aspect: (1, 1),
+      cut_feed_lines: 4,
+      feed_cut: false,
+      multi_tone: false,
+      label_sensor: false,
+    }
+  }
+}
+
+impl Capabilities {
+  /// # About
+  /// Typical 58mm receipt printer, the same as ``Capabilities::default()``.
+  pub fn paper_58mm() -> Self {
+    Capabilities::default()
+  }
+
+  /// # About
+  /// Typical 80mm receipt printer, such as the Epson TM-T20 and TM-T88,
+  /// which have the cutter a bit further from the head.
+  pub fn paper_80mm() -> Self {
+    Capabilities {
+      print_width: 576,
+      cut_feed_lines: 5,
+      ..Default::default()
+    }
+  }
+}
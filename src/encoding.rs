@@ -0,0 +1,131 @@
+/// # About
+/// Character sets the text methods of ``Printer`` can transcode to, so
+/// non-ASCII characters print right instead of as the bytes of their UTF-8
+/// encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+  /// Code page 437, the original IBM PC set with box drawing characters
+  Cp437,
+  /// Code page 850, multilingual Latin-1 with fewer box drawing characters
+  Cp850,
+  /// ISO 8859-1, whose upper half is shared by Windows-1252
+  Latin1,
+}
+
+impl Encoding {
+  /// # About
+  /// The encoding of the Epson code page numbered ``page`` for ``ESC t``, if
+  /// it's one of the supported ones. Page 16 (WPC1252) is encoded as
+  /// ``Latin1``, leaving out the characters Windows-1252 adds in 0x80 to 0x9f.
+  /// # Examples
+  /// ```
+  /// assert_eq!(Encoding::for_code_page(2), Some(Encoding::Cp850));
+  /// ```
+  pub fn for_code_page(page: u8) -> Option<Encoding> {
+    match page {
+      0 => Some(Encoding::Cp437),
+      2 => Some(Encoding::Cp850),
+      16 => Some(Encoding::Latin1),
+      _ => None
+    }
+  }
+
+  /// # About
+  /// Encodes ``text`` one byte per character, replacing the characters the
+  /// encoding doesn't have with ``?``. ASCII, control characters included,
+  /// passes through unchanged.
+  /// # Examples
+  /// ```
+  /// assert_eq!(Encoding::Cp850.encode("café"), b"caf\x82");
+  /// assert_eq!(Encoding::Cp437.encode("5 €"), b"5 ?");
+  /// ```
+  pub fn encode(&self, text: &str) -> Vec<u8> {
+    text.chars().map(|c| self.encode_char(c).unwrap_or(b'?')).collect()
+  }
+
+  fn encode_char(&self, c: char) -> Option<u8> {
+    if c.is_ascii() {
+      return Some(c as u8)
+    }
+    let upper_half = match self {
+      Encoding::Cp437 => &CP437_UPPER,
+      Encoding::Cp850 => &CP850_UPPER,
+      Encoding::Latin1 => {
+        return (0xa0..=0xff).contains(&(c as u32)).then_some(c as u8)
+      }
+    };
+    upper_half.iter().position(|&u| u == c).map(|i| 0x80 + i as u8)
+  }
+}
+
+/// Characters 0x80 to 0xff of code page 437
+const CP437_UPPER: [char; 128] = [
+  'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç',
+  'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+  'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù',
+  'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ',
+  'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º',
+  '¿', '⌐', '¬', '½', '¼', '¡', '«', '»',
+  '░', '▒', '▓', '│', '┤', '╡', '╢', '╖',
+  '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+  '└', '┴', '┬', '├', '─', '┼', '╞', '╟',
+  '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+  '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫',
+  '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+  'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ',
+  'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩',
+  '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈',
+  '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{00a0}',
+];
+
+/// Characters 0x80 to 0xff of code page 850
+const CP850_UPPER: [char; 128] = [
+  'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç',
+  'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+  'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù',
+  'ÿ', 'Ö', 'Ü', 'ø', '£', 'Ø', '×', 'ƒ',
+  'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º',
+  '¿', '®', '¬', '½', '¼', '¡', '«', '»',
+  '░', '▒', '▓', '│', '┤', 'Á', 'Â', 'À',
+  '©', '╣', '║', '╗', '╝', '¢', '¥', '┐',
+  '└', '┴', '┬', '├', '─', '┼', 'ã', 'Ã',
+  '╚', '╔', '╩', '╦', '╠', '═', '╬', '¤',
+  'ð', 'Ð', 'Ê', 'Ë', 'È', 'ı', 'Í', 'Î',
+  'Ï', '┘', '┌', '█', '▄', '¦', 'Ì', '▀',
+  'Ó', 'ß', 'Ô', 'Ò', 'õ', 'Õ', 'µ', 'þ',
+  'Þ', 'Ú', 'Û', 'Ù', 'ý', 'Ý', '¯', '´',
+  '\u{00ad}', '±', '‗', '¾', '¶', '§', '÷', '¸',
+  '°', '¨', '·', '¹', '³', '²', '■', '\u{00a0}',
+];
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::printing::Printer;
+
+  #[test]
+  fn cafe_in_cp850() {
+    assert_eq!(Encoding::Cp850.encode("café"), b"caf\x82");
+    assert_eq!(Encoding::Cp437.encode("café"), b"caf\x82");
+    assert_eq!(Encoding::Latin1.encode("café"), b"caf\xe9");
+  }
+
+  #[test]
+  fn missing_characters_become_question_marks() {
+    assert_eq!(Encoding::Latin1.encode("5 €"), b"5 ?");
+    assert_eq!(Encoding::Cp437.encode("5 €"), b"5 ?");
+    // CP850 swapped most of CP437's box drawing characters for À, Ê and co.
+    assert_eq!(Encoding::Cp437.encode("À"), b"?");
+    assert_eq!(Encoding::Cp850.encode("À"), b"\xb7");
+    // one byte per character, even for ones outside the Basic Multilingual Plane
+    assert_eq!(Encoding::Cp850.encode("a😀b"), b"a?b");
+  }
+
+  #[test]
+  fn println_transcodes_for_the_code_page() {
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.set_code_page(2).unwrap();
+    printer.println("café").unwrap();
+    assert_eq!(printer.into_inner().unwrap(), b"\x1bt\x02caf\x82\n");
+  }
+}
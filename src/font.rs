@@ -0,0 +1,163 @@
+use crate::bitimage::BitImage;
+use crate::printing::wrap_text;
+
+/// Width and height of a glyph in ``FONT_8X8``, in dots
+pub const GLYPH_SIZE: usize = 8;
+
+/// # About
+/// Renders ``text`` into a ``BitImage`` at most ``width`` dots wide, wrapping
+/// it on whitespace like the text layout helpers of ``Printer``.
+///
+/// Every glyph is scaled up ``scale`` times, so each character takes
+/// ``8 * scale`` by ``8 * scale`` dots. Characters outside printable ASCII are
+/// drawn as ``?``.
+/// # Examples
+/// ```
+/// let bitmap = font::render_text("Hello World!", 384, 2);
+/// assert_eq!(bitmap.get_height(), 16);
+/// ```
+pub fn render_text(text: &str, width: usize, scale: usize) -> BitImage {
+  let scale = scale.max(1);
+  let cell = GLYPH_SIZE * scale;
+  let columns = (width / cell).max(1);
+  let lines: Vec<String> = text.lines().flat_map(|line| wrap_text(line, columns)).collect();
+  let text_width = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0) * cell;
+
+  let mut bitmap = BitImage::new(text_width, lines.len() * cell);
+  for (row, line) in lines.iter().enumerate() {
+    for (column, c) in line.chars().enumerate() {
+      let glyph = glyph(c);
+      for (y, bits) in glyph.iter().enumerate() {
+        for x in 0..GLYPH_SIZE {
+          if bits & (128 >> x) == 0 {
+            continue;
+          }
+          for dy in 0..scale {
+            for dx in 0..scale {
+              bitmap.set_pixel(
+                (column * cell + x * scale + dx) as isize,
+                (row * cell + y * scale + dy) as isize,
+                true
+              );
+            }
+          }
+        }
+      }
+    }
+  }
+  bitmap
+}
+
+/// # About
+/// Returns the ``FONT_8X8`` glyph for ``c``, or the one for ``?`` if it isn't
+/// printable ASCII.
+pub fn glyph(c: char) -> &'static [u8; 8] {
+  match c {
+    ' '..='~' => &FONT_8X8[c as usize - 0x20],
+    _ => &FONT_8X8[b'?' as usize - 0x20]
+  }
+}
+
+/// # About
+/// An 8x8 font covering printable ASCII (``0x20`` to ``0x7e``), based on the
+/// public domain font8x8 by Daniel Hepper.
+///
+/// Each glyph is 8 rows read top-to-bottom, with the most significant bit
+/// being the leftmost dot, the same layout as ``BitImage``.
+pub const FONT_8X8: [[u8; 8]; 95] = [
+  [0b00000000,0b00000000,0b00000000,0b00000000,0b00000000,0b00000000,0b00000000,0b00000000], // space
+  [0b00011000,0b00111100,0b00111100,0b00011000,0b00011000,0b00000000,0b00011000,0b00000000], // !
+  [0b01101100,0b01101100,0b00000000,0b00000000,0b00000000,0b00000000,0b00000000,0b00000000], // "
+  [0b01101100,0b01101100,0b11111110,0b01101100,0b11111110,0b01101100,0b01101100,0b00000000], // #
+  [0b00110000,0b01111100,0b11000000,0b01111000,0b00001100,0b11111000,0b00110000,0b00000000], // $
+  [0b00000000,0b11000110,0b11001100,0b00011000,0b00110000,0b01100110,0b11000110,0b00000000], // %
+  [0b00111000,0b01101100,0b00111000,0b01110110,0b11011100,0b11001100,0b01110110,0b00000000], // &
+  [0b01100000,0b01100000,0b11000000,0b00000000,0b00000000,0b00000000,0b00000000,0b00000000], // '
+  [0b00011000,0b00110000,0b01100000,0b01100000,0b01100000,0b00110000,0b00011000,0b00000000], // (
+  [0b01100000,0b00110000,0b00011000,0b00011000,0b00011000,0b00110000,0b01100000,0b00000000], // )
+  [0b00000000,0b01100110,0b00111100,0b11111111,0b00111100,0b01100110,0b00000000,0b00000000], // *
+  [0b00000000,0b00110000,0b00110000,0b11111100,0b00110000,0b00110000,0b00000000,0b00000000], // +
+  [0b00000000,0b00000000,0b00000000,0b00000000,0b00000000,0b00110000,0b00110000,0b01100000], // ,
+  [0b00000000,0b00000000,0b00000000,0b11111100,0b00000000,0b00000000,0b00000000,0b00000000], // -
+  [0b00000000,0b00000000,0b00000000,0b00000000,0b00000000,0b00110000,0b00110000,0b00000000], // .
+  [0b00000110,0b00001100,0b00011000,0b00110000,0b01100000,0b11000000,0b10000000,0b00000000], // /
+  [0b01111100,0b11000110,0b11001110,0b11011110,0b11110110,0b11100110,0b01111100,0b00000000], // 0
+  [0b00110000,0b01110000,0b00110000,0b00110000,0b00110000,0b00110000,0b11111100,0b00000000], // 1
+  [0b01111000,0b11001100,0b00001100,0b00111000,0b01100000,0b11001100,0b11111100,0b00000000], // 2
+  [0b01111000,0b11001100,0b00001100,0b00111000,0b00001100,0b11001100,0b01111000,0b00000000], // 3
+  [0b00011100,0b00111100,0b01101100,0b11001100,0b11111110,0b00001100,0b00011110,0b00000000], // 4
+  [0b11111100,0b11000000,0b11111000,0b00001100,0b00001100,0b11001100,0b01111000,0b00000000], // 5
+  [0b00111000,0b01100000,0b11000000,0b11111000,0b11001100,0b11001100,0b01111000,0b00000000], // 6
+  [0b11111100,0b11001100,0b00001100,0b00011000,0b00110000,0b00110000,0b00110000,0b00000000], // 7
+  [0b01111000,0b11001100,0b11001100,0b01111000,0b11001100,0b11001100,0b01111000,0b00000000], // 8
+  [0b01111000,0b11001100,0b11001100,0b01111100,0b00001100,0b00011000,0b01110000,0b00000000], // 9
+  [0b00000000,0b00110000,0b00110000,0b00000000,0b00000000,0b00110000,0b00110000,0b00000000], // :
+  [0b00000000,0b00110000,0b00110000,0b00000000,0b00000000,0b00110000,0b00110000,0b01100000], // ;
+  [0b00011000,0b00110000,0b01100000,0b11000000,0b01100000,0b00110000,0b00011000,0b00000000], // <
+  [0b00000000,0b00000000,0b11111100,0b00000000,0b00000000,0b11111100,0b00000000,0b00000000], // =
+  [0b01100000,0b00110000,0b00011000,0b00001100,0b00011000,0b00110000,0b01100000,0b00000000], // >
+  [0b01111000,0b11001100,0b00001100,0b00011000,0b00110000,0b00000000,0b00110000,0b00000000], // ?
+  [0b01111100,0b11000110,0b11011110,0b11011110,0b11011110,0b11000000,0b01111000,0b00000000], // @
+  [0b00110000,0b01111000,0b11001100,0b11001100,0b11111100,0b11001100,0b11001100,0b00000000], // A
+  [0b11111100,0b01100110,0b01100110,0b01111100,0b01100110,0b01100110,0b11111100,0b00000000], // B
+  [0b00111100,0b01100110,0b11000000,0b11000000,0b11000000,0b01100110,0b00111100,0b00000000], // C
+  [0b11111000,0b01101100,0b01100110,0b01100110,0b01100110,0b01101100,0b11111000,0b00000000], // D
+  [0b11111110,0b01100010,0b01101000,0b01111000,0b01101000,0b01100010,0b11111110,0b00000000], // E
+  [0b11111110,0b01100010,0b01101000,0b01111000,0b01101000,0b01100000,0b11110000,0b00000000], // F
+  [0b00111100,0b01100110,0b11000000,0b11000000,0b11001110,0b01100110,0b00111110,0b00000000], // G
+  [0b11001100,0b11001100,0b11001100,0b11111100,0b11001100,0b11001100,0b11001100,0b00000000], // H
+  [0b01111000,0b00110000,0b00110000,0b00110000,0b00110000,0b00110000,0b01111000,0b00000000], // I
+  [0b00011110,0b00001100,0b00001100,0b00001100,0b11001100,0b11001100,0b01111000,0b00000000], // J
+  [0b11100110,0b01100110,0b01101100,0b01111000,0b01101100,0b01100110,0b11100110,0b00000000], // K
+  [0b11110000,0b01100000,0b01100000,0b01100000,0b01100010,0b01100110,0b11111110,0b00000000], // L
+  [0b11000110,0b11101110,0b11111110,0b11111110,0b11010110,0b11000110,0b11000110,0b00000000], // M
+  [0b11000110,0b11100110,0b11110110,0b11011110,0b11001110,0b11000110,0b11000110,0b00000000], // N
+  [0b00111000,0b01101100,0b11000110,0b11000110,0b11000110,0b01101100,0b00111000,0b00000000], // O
+  [0b11111100,0b01100110,0b01100110,0b01111100,0b01100000,0b01100000,0b11110000,0b00000000], // P
+  [0b01111000,0b11001100,0b11001100,0b11001100,0b11011100,0b01111000,0b00011100,0b00000000], // Q
+  [0b11111100,0b01100110,0b01100110,0b01111100,0b01101100,0b01100110,0b11100110,0b00000000], // R
+  [0b01111000,0b11001100,0b11100000,0b01110000,0b00011100,0b11001100,0b01111000,0b00000000], // S
+  [0b11111100,0b10110100,0b00110000,0b00110000,0b00110000,0b00110000,0b01111000,0b00000000], // T
+  [0b11001100,0b11001100,0b11001100,0b11001100,0b11001100,0b11001100,0b11111100,0b00000000], // U
+  [0b11001100,0b11001100,0b11001100,0b11001100,0b11001100,0b01111000,0b00110000,0b00000000], // V
+  [0b11000110,0b11000110,0b11000110,0b11010110,0b11111110,0b11101110,0b11000110,0b00000000], // W
+  [0b11000110,0b11000110,0b01101100,0b00111000,0b00111000,0b01101100,0b11000110,0b00000000], // X
+  [0b11001100,0b11001100,0b11001100,0b01111000,0b00110000,0b00110000,0b01111000,0b00000000], // Y
+  [0b11111110,0b11000110,0b10001100,0b00011000,0b00110010,0b01100110,0b11111110,0b00000000], // Z
+  [0b01111000,0b01100000,0b01100000,0b01100000,0b01100000,0b01100000,0b01111000,0b00000000], // [
+  [0b11000000,0b01100000,0b00110000,0b00011000,0b00001100,0b00000110,0b00000010,0b00000000], // backslash
+  [0b01111000,0b00011000,0b00011000,0b00011000,0b00011000,0b00011000,0b01111000,0b00000000], // ]
+  [0b00010000,0b00111000,0b01101100,0b11000110,0b00000000,0b00000000,0b00000000,0b00000000], // ^
+  [0b00000000,0b00000000,0b00000000,0b00000000,0b00000000,0b00000000,0b00000000,0b11111111], // _
+  [0b00110000,0b00110000,0b00011000,0b00000000,0b00000000,0b00000000,0b00000000,0b00000000], // `
+  [0b00000000,0b00000000,0b01111000,0b00001100,0b01111100,0b11001100,0b01110110,0b00000000], // a
+  [0b11100000,0b01100000,0b01100000,0b01111100,0b01100110,0b01100110,0b11011100,0b00000000], // b
+  [0b00000000,0b00000000,0b01111000,0b11001100,0b11000000,0b11001100,0b01111000,0b00000000], // c
+  [0b00011100,0b00001100,0b00001100,0b01111100,0b11001100,0b11001100,0b01110110,0b00000000], // d
+  [0b00000000,0b00000000,0b01111000,0b11001100,0b11111100,0b11000000,0b01111000,0b00000000], // e
+  [0b00111000,0b01101100,0b01100000,0b11110000,0b01100000,0b01100000,0b11110000,0b00000000], // f
+  [0b00000000,0b00000000,0b01110110,0b11001100,0b11001100,0b01111100,0b00001100,0b11111000], // g
+  [0b11100000,0b01100000,0b01101100,0b01110110,0b01100110,0b01100110,0b11100110,0b00000000], // h
+  [0b00110000,0b00000000,0b01110000,0b00110000,0b00110000,0b00110000,0b01111000,0b00000000], // i
+  [0b00001100,0b00000000,0b00001100,0b00001100,0b00001100,0b11001100,0b11001100,0b01111000], // j
+  [0b11100000,0b01100000,0b01100110,0b01101100,0b01111000,0b01101100,0b11100110,0b00000000], // k
+  [0b01110000,0b00110000,0b00110000,0b00110000,0b00110000,0b00110000,0b01111000,0b00000000], // l
+  [0b00000000,0b00000000,0b11001100,0b11111110,0b11111110,0b11010110,0b11000110,0b00000000], // m
+  [0b00000000,0b00000000,0b11111000,0b11001100,0b11001100,0b11001100,0b11001100,0b00000000], // n
+  [0b00000000,0b00000000,0b01111000,0b11001100,0b11001100,0b11001100,0b01111000,0b00000000], // o
+  [0b00000000,0b00000000,0b11011100,0b01100110,0b01100110,0b01111100,0b01100000,0b11110000], // p
+  [0b00000000,0b00000000,0b01110110,0b11001100,0b11001100,0b01111100,0b00001100,0b00011110], // q
+  [0b00000000,0b00000000,0b11011100,0b01110110,0b01100110,0b01100000,0b11110000,0b00000000], // r
+  [0b00000000,0b00000000,0b01111100,0b11000000,0b01111000,0b00001100,0b11111000,0b00000000], // s
+  [0b00010000,0b00110000,0b01111100,0b00110000,0b00110000,0b00110100,0b00011000,0b00000000], // t
+  [0b00000000,0b00000000,0b11001100,0b11001100,0b11001100,0b11001100,0b01110110,0b00000000], // u
+  [0b00000000,0b00000000,0b11001100,0b11001100,0b11001100,0b01111000,0b00110000,0b00000000], // v
+  [0b00000000,0b00000000,0b11000110,0b11010110,0b11111110,0b11111110,0b01101100,0b00000000], // w
+  [0b00000000,0b00000000,0b11000110,0b01101100,0b00111000,0b01101100,0b11000110,0b00000000], // x
+  [0b00000000,0b00000000,0b11001100,0b11001100,0b11001100,0b01111100,0b00001100,0b11111000], // y
+  [0b00000000,0b00000000,0b11111100,0b10011000,0b00110000,0b01100100,0b11111100,0b00000000], // z
+  [0b00011100,0b00110000,0b00110000,0b11100000,0b00110000,0b00110000,0b00011100,0b00000000], // {
+  [0b00011000,0b00011000,0b00011000,0b00000000,0b00011000,0b00011000,0b00011000,0b00000000], // |
+  [0b11100000,0b00110000,0b00110000,0b00011100,0b00110000,0b00110000,0b11100000,0b00000000], // }
+  [0b01110110,0b11011100,0b00000000,0b00000000,0b00000000,0b00000000,0b00000000,0b00000000], // ~
+];
@@ -1,22 +1,73 @@
 #![allow(dead_code, unused_variables)]
 
 pub mod printing;
+pub mod barcode;
 pub mod bitimage;
+pub mod capabilities;
+pub mod font;
+pub mod canvas;
+pub mod encoding;
+pub mod pdf;
 
-use std::{env, path::PathBuf, fs::File, io::{BufReader}};
-use clap::Arg;
+use std::{env, path::PathBuf, io::BufRead, time::Duration};
+use clap::{Arg, ArgMatches};
+
+/// # About
+/// Prints the warnings logged by the printing code to stderr.
+struct StderrLogger;
+
+impl log::Log for StderrLogger {
+  fn enabled(&self, metadata: &log::Metadata) -> bool {
+    metadata.level() <= log::Level::Warn
+  }
+
+  fn log(&self, record: &log::Record) {
+    if self.enabled(record.metadata()) {
+      let level = match record.level() {
+        log::Level::Error => "error",
+        _ => "warning"
+      };
+      eprintln!("{}: {}", level, record.args());
+    }
+  }
+
+  fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
 
 fn main() {
   #[cfg(debug_assertions)]
   env::set_var("RUST_BACKTRACE", "1");
+  if log::set_logger(&LOGGER).is_ok() {
+    log::set_max_level(log::LevelFilter::Warn);
+  }
 
   let mut cmd = clap::Command::new("thermal_printer")
     .arg(Arg::new("path_to_printer")
       .short('p')
       .long("path")
-      .required(true)
+      .required_unless_present_any(["preview", "pdf", "host", "output_file"])
+      .takes_value(true)
+      .help("path to the printer file handle, see documentation for help\non Windows it's the name of the shared printer, elsewhere the device path, such as /dev/usb/lp0")
+    )
+    .arg(Arg::new("host")
+      .long("host")
       .takes_value(true)
-      .help("path to the printer file handle, see documentation for help")
+      .conflicts_with("path_to_printer")
+      .help("address of a network printer to print to instead of a --path, such as 192.168.1.50:9100\nthe port defaults to 9100")
+    )
+    .arg(Arg::new("output_file")
+      .long("output-file")
+      .takes_value(true)
+      .conflicts_with_all(&["path_to_printer", "host"])
+      .value_parser(clap::value_parser!(PathBuf))
+      .help("writes the ESC-POS commands to this file instead of a printer, replacing it\nthe file can be sent to a printer later, such as with cat file > /dev/usb/lp0")
+    )
+    .arg(Arg::new("raw_path")
+      .long("raw-path")
+      .takes_value(false)
+      .help("uses the --path as is on Windows too, instead of the name of a printer shared on this machine")
     )
     .arg(Arg::new("input")
       .short('i')
@@ -36,7 +87,23 @@ fn main() {
       .long("qr_width")
       .takes_value(true)
       .default_value("8")
-      .help("width of the qr code, must be in range 0..16")
+      .help("width of the qr code, must be in range 1..=16")
+    )
+    .arg(Arg::new("force_qr_size")
+      .long("force-qr-size")
+      .takes_value(false)
+      .help("prints qr codes with the given --qr_width even when it's too small to scan reliably, instead of raising it")
+    )
+    .arg(Arg::new("qr_quiet_zone")
+      .long("qr-quiet-zone")
+      .takes_value(true)
+      .help("blank modules fed above and below qr codes, 4 by default as the qr specification asks")
+    )
+    .arg(Arg::new("qr_ecc")
+      .long("qr-ecc")
+      .takes_value(true)
+      .default_value("L")
+      .help("error correction level of the qr code\ncan be either \"L\", \"M\", \"Q\" or \"H\", higher levels survive more damage but make the code bigger")
     )
     .arg(Arg::new("qr_code")
       .short('q')
@@ -44,6 +111,28 @@ fn main() {
       .takes_value(true)
       .help("print a qr code with the given text encoded into it")
     )
+    .arg(Arg::new("barcode")
+      .short('b')
+      .long("barcode")
+      .takes_value(true)
+      .help("print a barcode with the given text encoded into it")
+    )
+    .arg(Arg::new("symbology")
+      .long("symbology")
+      .takes_value(true)
+      .default_value("code128")
+      .help("symbology of the barcode\navailable symbologies: code128, code39, ean13, ean8, upca\nfalls back to code128")
+    )
+    .arg(Arg::new("raster_barcode")
+      .long("raster_barcode")
+      .takes_value(false)
+      .help("draws the barcode and prints it as an image, for printers that can't print barcodes themselves")
+    )
+    .arg(Arg::new("vertical_barcode")
+      .long("vertical_barcode")
+      .takes_value(false)
+      .help("draws the barcode turned 90 degrees so it runs down the paper, for codes too long to fit across it")
+    )
     .arg(Arg::new("text")
       .short('t')
       .long("text")
@@ -56,6 +145,37 @@ fn main() {
     .value_parser(clap::value_parser!(PathBuf))
     .help("print the given markdown file")
   )
+    .arg(Arg::new("raw_file")
+    .long("raw-file")
+    .takes_value(true)
+    .value_parser(clap::value_parser!(PathBuf))
+    .help("sends the raw bytes of the given file to the printer\nonly use with files you trust, they can contain any command")
+    )
+    .arg(Arg::new("stdin_raw")
+    .long("stdin-raw")
+    .takes_value(false)
+    .help("forwards the raw bytes read from stdin to the printer until it closes, unlike --text nothing is converted\nonly use with data you trust, it can contain any command")
+    )
+    .arg(Arg::new("commands")
+    .long("commands")
+    .takes_value(false)
+    .help("keeps the printer open and runs the commands read from stdin, one per line, until it closes\navailable commands: \"text: <text>\", \"qr: <text>\", \"barcode: <text>\", \"image: <path>\", \"align: left|center|right\", \"feed: <lines>\", \"cut\", \"cut: partial\", \"drawer\", \"reset\"")
+    )
+    .arg(Arg::new("chunk_delay")
+      .long("chunk-delay-ms")
+      .takes_value(true)
+      .help("pause between each band of 64 rows of an image, in milliseconds\nneeded when printing through a print server that overflows the printer's buffer, 1500 works on slow printers")
+    )
+    .arg(Arg::new("upside_down")
+      .long("upside-down")
+      .takes_value(false)
+      .help("prints the text upside down, for printers mounted that way\nimages aren't rotated, use --rotate 180 for them")
+    )
+    .arg(Arg::new("line_spacing")
+      .long("line-spacing")
+      .takes_value(true)
+      .help("distance between lines of text in dots, the printer's default is usually 30")
+    )
     .arg(Arg::new("justification")
       .short('j')
       .long("justification")
@@ -63,6 +183,20 @@ fn main() {
       .default_value("left")
       .help("must be either \"left\", \"center\" or \"right\", falls back to \"left\"")
     )
+    .arg(Arg::new("cut")
+    .long("cut")
+    .takes_value(true)
+    .min_values(0)
+    .default_missing_value("full")
+    .help("feeds the paper past the cutter and cuts it after printing\ncan be either \"full\" or \"partial\", falls back to \"full\"")
+    )
+    .arg(Arg::new("open_drawer")
+    .long("open-drawer")
+    .takes_value(true)
+    .min_values(0)
+    .default_missing_value("2")
+    .help("opens the cash drawer connected to the printer after printing\ncan be either \"2\" or \"5\", the pin the drawer is on, falls back to \"2\"")
+    )
     .arg(Arg::new("reset")
     .long("reset")
     .takes_value(false)
@@ -72,7 +206,41 @@ fn main() {
     .long("dithering")
     .takes_value(true)
     .default_value("2sierra")
-    .help("select the dithering mode used to print images\navailable modes: sierra, 2sierra, fs, none\nfalls back to 2sierra")
+    .help("select the dithering mode used to print images\navailable modes: sierra, 2sierra, sierralite, fs, burkes, stucki, atkinson, bayer4, bayer8, none, printer\nfalls back to 2sierra")
+    )
+    .arg(Arg::new("invert")
+    .long("invert")
+    .takes_value(false)
+    .help("prints images white on black")
+    )
+    .arg(Arg::new("rotate")
+    .long("rotate")
+    .takes_value(true)
+    .help("rotates images clockwise before printing them\ncan be either \"90\", \"180\" or \"270\", falls back to no rotation")
+    )
+    .arg(Arg::new("max_coverage")
+    .long("max-coverage")
+    .takes_value(true)
+    .help("low-ink mode, prints the darkest parts of images inverted to keep at most this percentage of the image black")
+    )
+    .arg(Arg::new("preview")
+    .long("preview")
+    .takes_value(false)
+    .requires("input")
+    .help("shows the dithered image in the terminal instead of printing it\nno printer is needed")
+    )
+    .arg(Arg::new("pdf")
+    .long("pdf")
+    .takes_value(true)
+    .value_parser(clap::value_parser!(PathBuf))
+    .requires("input")
+    .help("saves the dithered image to this PDF file instead of printing it, with the page as wide as the paper\nno printer is needed")
+    )
+    .arg(Arg::new("fit")
+    .long("fit")
+    .takes_value(true)
+    .default_value("scale")
+    .help("how images wider than the width are made to fit\nmust be either \"scale\" or \"crop\", falls back to \"scale\"")
     )
   ;
   #[cfg(debug_assertions)]
@@ -94,24 +262,165 @@ fn main() {
 
   let cur_dir = env::current_dir().expect("error getting cwd!").to_str().expect("error turning path into string!").to_owned();
 
+  let dithering = match args.get_one::<String>("dithering").unwrap().to_lowercase().as_str() {
+    "sierra" => printing::Dither::Sierra,
+    "fs" => printing::Dither::FloydSteinberg,
+    "burkes" => printing::Dither::Burkes,
+    "stucki" => printing::Dither::Stucki,
+    "sierralite" => printing::Dither::SierraLite,
+    "atkinson" => printing::Dither::Atkinson,
+    "none" => printing::Dither::Threshold,
+    "bayer4" => printing::Dither::Bayer4,
+    "bayer8" => printing::Dither::Bayer8,
+    "printer" => printing::Dither::Printer,
+    _ => printing::Dither::TwoRowSierra
+  };
+  let image_options = printing::ImageOptions {
+    fit: match args.get_one::<String>("fit").unwrap().to_lowercase().as_str() {
+      "crop" => printing::ImageFit::Crop,
+      _ => printing::ImageFit::Scale
+    },
+    invert: args.contains_id("invert"),
+    rotation: match args.get_one::<String>("rotate").map(|r| r.as_str()) {
+      Some("90") => Some(bitimage::Rotation::Cw90),
+      Some("180") => Some(bitimage::Rotation::Cw180),
+      Some("270") => Some(bitimage::Rotation::Cw270),
+      _ => None
+    },
+    max_coverage: args.get_one::<String>("max_coverage").map(|c| c.parse().expect("error parsing max coverage!")),
+    ..Default::default()
+  };
+  let image_width: u32 = args.get_one::<String>("width").expect("error parsing image width!").parse().expect("error parsing image width!");
+
+  if args.contains_id("preview") {
+    let path = args.get_one::<PathBuf>("input").expect("error parsing image path!");
+    // shrinks the preview to fit the terminal, one character per dot
+    let columns: u32 = env::var("COLUMNS").ok().and_then(|c| c.parse().ok()).unwrap_or(80);
+    match printing::dither_image(path.to_str().expect("error parsing image path!"), image_width.min(columns), &dithering, &image_options) {
+      Ok(bitmap) => print!("{}", bitmap),
+      Err(e) => {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+      }
+    }
+    return
+  }
+
+  if let Some(pdf_path) = args.get_one::<PathBuf>("pdf") {
+    let path = args.get_one::<PathBuf>("input").expect("error parsing image path!");
+    let saved = printing::dither_image(path.to_str().expect("error parsing image path!"), image_width, &dithering, &image_options)
+      .and_then(|bitmap| {
+        let file = std::fs::File::create(pdf_path)?;
+        pdf::write_pdf(&bitmap, capabilities::Capabilities::default().dpi, std::io::BufWriter::new(file))?;
+        Ok(())
+      });
+    if let Err(e) = saved {
+      eprintln!("error: {}", e);
+      std::process::exit(1);
+    }
+    return
+  }
+
+  if let Some(path) = args.get_one::<PathBuf>("output_file") {
+    let path = path.to_str().expect("error parsing output file path!");
+    run_opened(printing::Printer::new(path), path, &args, &dithering, &image_options, image_width);
+    return
+  }
+
+  if let Some(host) = args.get_one::<String>("host") {
+    run_opened(printing::Printer::connect_tcp(host), host, &args, &dithering, &image_options, image_width);
+    return
+  }
+
   let printer_path = {
     let path_arg = args.get_one::<String>("path_to_printer").expect("path argument invalid!");
-    String::from("\\\\127.0.0.1\\") + path_arg
+    // shared printers are opened through this machine's UNC path on Windows,
+    // every other platform has device files that are used verbatim
+    if cfg!(windows) && !args.contains_id("raw_path") && !path_arg.starts_with("\\\\") {
+      String::from("\\\\127.0.0.1\\") + path_arg
+    } else {
+      path_arg.to_string()
+    }
+  };
+  run_opened(printing::Printer::new(&printer_path), &printer_path, &args, &dithering, &image_options, image_width);
+}
+
+/// # About
+/// Exits if the printer at ``address`` couldn't be opened, otherwise does
+/// whatever the arguments asked for with it, see ``run``.
+fn run_opened<C: printing::Connection>(
+  printer: Result<printing::Printer<C>, printing::PrinterError>,
+  address: &str,
+  args: &ArgMatches,
+  dithering: &printing::Dither,
+  image_options: &printing::ImageOptions,
+  image_width: u32
+) {
+  let mut printer = match printer {
+    Ok(o) => o,
+    Err(e) => {
+      eprintln!("error: couldn't open the printer at {}: {}", address, e);
+      std::process::exit(1);
+    }
   };
-  let mut printer = printing::Printer::new(&printer_path);
+  if let Some(ms) = args.get_one::<String>("chunk_delay") {
+    printer.set_chunk_delay(Duration::from_millis(ms.parse().expect("error parsing chunk delay!")));
+  }
+
+  if let Err(e) = run(&mut printer, args, dithering, image_options, image_width) {
+    eprintln!("error: {}", e);
+    std::process::exit(1);
+  }
+}
+
+/// # About
+/// Does whatever the arguments asked for with the printer.
+fn run<C: printing::Connection>(
+  printer: &mut printing::Printer<C>,
+  args: &ArgMatches,
+  dithering: &printing::Dither,
+  image_options: &printing::ImageOptions,
+  image_width: u32
+) -> Result<(), printing::PrinterError> {
+  // raw input is sent before anything else so it reaches the printer unmodified
+  if args.contains_id("stdin_raw") {
+    printer.print_reader(std::io::stdin().lock())?;
+    return Ok(())
+  }
+
+  if let Some(path) = args.get_one::<PathBuf>("raw_file") {
+    printer.print_file(path.to_str().expect("error parsing file path!"))?;
+    return Ok(())
+  }
 
   match args.get_one::<String>("justification").unwrap().to_lowercase().as_str() {
     "left" => printer.set_justification(0),
     "center" => printer.set_justification(1),
     "right" => printer.set_justification(2),
     _ => printer.set_justification(0)
+  }?;
+
+  if args.contains_id("upside_down") {
+    printer.set_upside_down(true)?;
+  }
+
+  if let Some(dots) = args.get_one::<String>("line_spacing") {
+    printer.set_line_spacing(dots.parse().expect("error parsing line spacing!"))?;
+  }
+
+  if args.contains_id("force_qr_size") {
+    printer.set_allow_small_qr(true);
+  }
+
+  if let Some(modules) = args.get_one::<String>("qr_quiet_zone") {
+    printer.set_qr_quiet_zone(modules.parse().expect("error parsing qr quiet zone!"));
   }
 
   #[cfg(debug_assertions)]
   {
     if args.contains_id("test_buffer_size") {
-      printer.test_bitmap_buffer_size();
-      return
+      printer.test_bitmap_buffer_size()?;
+      return Ok(())
     }
 
     if args.contains_id("debug") {
@@ -120,43 +429,172 @@ fn main() {
   }
 
   if args.contains_id("reset") {
-    printer.reset();
-    return
+    printer.reset()?;
+    return Ok(())
+  }
+
+  if args.contains_id("commands") {
+    let qr_width: u8 = args.get_one::<String>("qr_code_width").expect("error parsing qr code width!").parse().expect("qr code width not a number!");
+    let qr_ecc = parse_qr_ecc(args);
+    for line in std::io::stdin().lock().lines() {
+      let line = line?;
+      // a failed command is reported without stopping the ones after it
+      if let Err(e) = run_command(printer, line.trim(), dithering, image_options, image_width, qr_width, qr_ecc) {
+        eprintln!("error: {}: {}", line.trim(), e);
+      }
+    }
+    return Ok(())
+  }
+
+  print_job(printer, args, dithering, image_options, image_width)?;
+
+  if let Some(mode) = args.get_one::<String>("cut") {
+    match mode.to_lowercase().as_str() {
+      "partial" => printer.feed_and_cut(printing::CutMode::Partial),
+      _ => printer.feed_and_cut(printing::CutMode::Full)
+    }?;
+  }
+
+  if let Some(pin) = args.get_one::<String>("open_drawer") {
+    let pin = match pin.as_str() {
+      "5" => printing::DrawerPin::Pin5,
+      _ => printing::DrawerPin::Pin2
+    };
+    printer.open_drawer(pin, 100, 200)?;
+  }
+  Ok(())
+}
+
+/// # About
+/// Raises ``size`` to the smallest module size the printer can print
+/// scannable QR codes with, warning when it does, unless ``--force-qr-size``
+/// allowed small modules. Sizes out of range are left for ``print_qr_code``
+/// to refuse.
+fn scannable_qr_size<C: printing::Connection>(printer: &printing::Printer<C>, size: u8) -> u8 {
+  let min_size = printer.min_qr_size();
+  if !printer.get_allow_small_qr() && (1..min_size).contains(&size) {
+    log::warn!("QR code module size {} is too small to scan reliably, using {} instead", size, min_size);
+    return min_size
+  }
+  size
+}
+
+/// # About
+/// Reads ``--qr-ecc``, falling back to level L.
+fn parse_qr_ecc(args: &ArgMatches) -> printing::QrEcc {
+  match args.get_one::<String>("qr_ecc").unwrap().to_uppercase().as_str() {
+    "M" => printing::QrEcc::M,
+    "Q" => printing::QrEcc::Q,
+    "H" => printing::QrEcc::H,
+    _ => printing::QrEcc::L
+  }
+}
+
+/// # About
+/// Runs a single line read in ``--commands`` mode, such as ``text: hello``.
+/// Empty lines and lines starting with ``#`` are skipped.
+fn run_command<C: printing::Connection>(
+  printer: &mut printing::Printer<C>,
+  line: &str,
+  dithering: &printing::Dither,
+  image_options: &printing::ImageOptions,
+  image_width: u32,
+  qr_width: u8,
+  qr_ecc: printing::QrEcc
+) -> Result<(), printing::PrinterError> {
+  if line.is_empty() || line.starts_with('#') {
+    return Ok(())
+  }
+  let (command, arg) = match line.split_once(':') {
+    Some((command, arg)) => (command.trim(), arg.trim_start()),
+    None => (line, "")
+  };
+  match command.to_lowercase().as_str() {
+    "text" => printer.println(arg),
+    "qr" => printer.print_qr_code(scannable_qr_size(printer, qr_width), qr_ecc, arg.as_bytes()),
+    "barcode" => printer.print_barcode(printing::Barcode::Code128, arg.as_bytes()),
+    "image" => printer.print_image(arg, image_width, dithering, image_options),
+    "align" => match arg.to_lowercase().as_str() {
+      "center" => printer.set_justification(1),
+      "right" => printer.set_justification(2),
+      _ => printer.set_justification(0)
+    },
+    "feed" => match arg.parse() {
+      Ok(lines) => printer.blank_lines(lines),
+      Err(_) => Err(printing::PrinterError::InvalidArgument(format!("{:?} isn't a number of lines", arg)))
+    },
+    "cut" if arg.eq_ignore_ascii_case("partial") => printer.feed_and_cut(printing::CutMode::Partial),
+    "cut" => printer.feed_and_cut(printing::CutMode::Full),
+    "drawer" => printer.open_drawer(printing::DrawerPin::Pin2, 100, 200),
+    "reset" => printer.reset(),
+    _ => Err(printing::PrinterError::InvalidArgument(format!("unknown command {:?}", command)))
   }
+}
 
+/// # About
+/// Prints the markdown file, image, QR code, barcode or text given in the
+/// arguments.
+fn print_job<C: printing::Connection>(
+  printer: &mut printing::Printer<C>,
+  args: &ArgMatches,
+  dithering: &printing::Dither,
+  image_options: &printing::ImageOptions,
+  image_width: u32
+) -> Result<(), printing::PrinterError> {
   if let Some(path) = args.get_one::<PathBuf>("markdown") {
     if path.to_str().unwrap().ends_with(".md") {
-      let md_file = File::open(path).unwrap();
-      let md_lines = BufReader::new(md_file);
-      printer.print_markdown(md_lines);
+      printer.print_markdown_file(path)?;
     }
   }
 
   if let Some(path) = args.get_one::<PathBuf>("input") {
     let image_path: &str;
-    let dithering: u8 = match args.get_one::<String>("dithering").unwrap().to_lowercase().as_str() {
-      "sierra" => 2,
-      "fs" => 0,
-      "none" => 255,
-      _ => 1
-    };
     if path.exists() {
       image_path = path.to_str().expect("error parsing image path!");
-      printer.print_image(image_path, args.get_one::<String>("width").expect("error parsing image width!").parse().expect("error parsing image width!"), dithering);
+      printer.print_image(image_path, image_width, dithering, image_options)?;
     }
-    return
+    return Ok(())
   }
 
   if let Some(qr_code_text) = args.get_one::<String>("qr_code") {
-    printer.print_qr_code(args.get_one::<String>("qr_code_width").expect("error parsing qr code width!").parse().expect("qr code width not a number!"), qr_code_text.as_bytes());
+    let qr_width = args.get_one::<String>("qr_code_width").expect("error parsing qr code width!").parse().expect("qr code width not a number!");
+    printer.print_qr_code(
+      scannable_qr_size(printer, qr_width),
+      parse_qr_ecc(args),
+      qr_code_text.as_bytes()
+    )?;
     if args.contains_id("debug") {
-      printer.println(qr_code_text);
+      printer.println(qr_code_text)?;
     }
-    return
+    return Ok(())
+  }
+
+  if let Some(barcode_text) = args.get_one::<String>("barcode") {
+    let symbology = match args.get_one::<String>("symbology").unwrap().to_lowercase().as_str() {
+      "code39" => printing::Barcode::Code39,
+      "ean13" => printing::Barcode::Ean13,
+      "ean8" => printing::Barcode::Ean8,
+      "upca" => printing::Barcode::UpcA,
+      _ => printing::Barcode::Code128
+    };
+    if args.contains_id("raster_barcode") || args.contains_id("vertical_barcode") {
+      let options = printing::BarcodeOptions {
+        vertical: args.contains_id("vertical_barcode"),
+        ..Default::default()
+      };
+      let length = printer.barcode_length_mm(symbology, barcode_text.as_bytes(), &options)?;
+      if length > printing::LONG_BARCODE_MM {
+        log::warn!("the barcode is {:.0}mm long, which many scanners can't read in one pass", length);
+      }
+      printer.print_barcode_raster(symbology, barcode_text.as_bytes(), &options)?;
+    } else {
+      printer.print_barcode(symbology, barcode_text.as_bytes())?;
+    }
+    return Ok(())
   }
 
   if let Some(text) = args.get_one::<String>("text") {
-    printer.println(text);
-    return
+    printer.println(text)?;
   }
+  Ok(())
 }
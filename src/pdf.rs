@@ -0,0 +1,106 @@
+use std::io::{self, Write};
+
+use crate::bitimage::BitImage;
+
+/// # About
+/// Writes ``image`` as a single page PDF to ``out``, for archiving what was
+/// printed. The page is as wide as the image at ``dpi`` dots per inch, so a
+/// 384 dots wide receipt printed at 203 dpi gives a 48mm wide page, and as
+/// long as the image.
+///
+/// The dots are embedded uncompressed, one bit each, exactly as they are
+/// sent to the printer, so nothing is lost or smoothed in the archive.
+/// # Examples
+/// ```
+/// let file = std::fs::File::create("receipt.pdf")?;
+/// pdf::write_pdf(ticket.as_image(), 203, file)?;
+/// ```
+/// # Errors
+/// - if writing to ``out`` fails
+/// - if ``dpi`` is 0
+pub fn write_pdf<W: Write>(image: &BitImage, dpi: u16, mut out: W) -> io::Result<()> {
+  if dpi == 0 {
+    return Err(io::Error::new(io::ErrorKind::InvalidInput, "dpi must be at least 1"))
+  }
+  // PDF sizes are in points, 72 per inch
+  let width = image.get_width() as f32 * 72.0 / dpi as f32;
+  let height = image.get_height() as f32 * 72.0 / dpi as f32;
+  let contents = format!("q {:.2} 0 0 {:.2} 0 0 cm /Im0 Do Q", width, height);
+
+  let mut pdf: Vec<u8> = Vec::from(&b"%PDF-1.4\n"[..]);
+  let mut offsets: Vec<usize> = Vec::new();
+  let mut object = |pdf: &mut Vec<u8>, body: &[u8]| {
+    offsets.push(pdf.len());
+    pdf.extend_from_slice(format!("{} 0 obj\n", offsets.len()).as_bytes());
+    pdf.extend_from_slice(body);
+    pdf.extend_from_slice(b"\nendobj\n");
+  };
+  object(&mut pdf, b"<< /Type /Catalog /Pages 2 0 R >>");
+  object(&mut pdf, b"<< /Type /Pages /Kids [3 0 R] /Count 1 >>");
+  object(&mut pdf, format!(
+    "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.2} {:.2}] /Resources << /XObject << /Im0 5 0 R >> >> /Contents 4 0 R >>",
+    width, height
+  ).as_bytes());
+  object(&mut pdf, format!("<< /Length {} >>\nstream\n{}\nendstream", contents.len(), contents).as_bytes());
+  // set dots are 1 in a BitImage, Decode swaps DeviceGray's 1 for white to
+  // 1 for black, rows are already padded to whole bytes like PDF wants them
+  let mut dots: Vec<u8> = format!(
+    "<< /Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceGray /BitsPerComponent 1 /Decode [1 0] /Length {} >>\nstream\n",
+    image.get_width(), image.get_height(), image.as_slice().len()
+  ).into_bytes();
+  dots.extend_from_slice(image.as_slice());
+  dots.extend_from_slice(b"\nendstream");
+  object(&mut pdf, &dots);
+
+  let xref = pdf.len();
+  pdf.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", offsets.len() + 1).as_bytes());
+  for offset in &offsets {
+    pdf.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+  }
+  pdf.extend_from_slice(format!(
+    "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+    offsets.len() + 1, xref
+  ).as_bytes());
+  out.write_all(&pdf)?;
+  out.flush()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn find(haystack: &[u8], needle: &[u8]) -> usize {
+    haystack.windows(needle.len()).position(|w| w == needle).unwrap()
+  }
+
+  #[test]
+  fn page_matches_the_paper_width() {
+    let image = BitImage::from_fn(384, 203, |x, y| (x + y) % 3 == 0);
+    let mut pdf = Vec::new();
+    write_pdf(&image, 203, &mut pdf).unwrap();
+    let text = String::from_utf8_lossy(&pdf);
+
+    assert!(pdf.starts_with(b"%PDF-1.4\n"));
+    assert!(pdf.ends_with(b"%%EOF\n"));
+    // 384 dots at 203 dpi is 136.2 points wide, 203 rows are an inch long
+    assert!(text.contains("/MediaBox [0 0 136.20 72.00]"), "{}", text);
+    assert!(text.contains("/Width 384 /Height 203"));
+    // the dots are embedded as they are
+    let decode = find(&pdf, b"/Decode [1 0]");
+    let start = decode + find(&pdf[decode..], b"stream\n") + 7;
+    assert!(pdf[start..].starts_with(image.as_slice()));
+    // every xref entry points at its object
+    let xref = find(&pdf, b"xref\n");
+    for (i, line) in String::from_utf8_lossy(&pdf[xref..]).lines().skip(3).take(5).enumerate() {
+      let offset: usize = line[..10].parse().unwrap();
+      assert!(pdf[offset..].starts_with(format!("{} 0 obj", i + 1).as_bytes()), "object {}", i + 1);
+    }
+  }
+
+  #[test]
+  fn zero_dpi_is_refused() {
+    let mut pdf = Vec::new();
+    assert!(write_pdf(&BitImage::new(8, 8), 0, &mut pdf).is_err());
+    assert!(pdf.is_empty());
+  }
+}
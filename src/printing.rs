@@ -1,475 +1,4373 @@
-use std::{fs::File, path::Path, io::{Write, BufReader, BufRead}};
+use std::{borrow::Cow, fmt, fs::File, path::Path, io::{Read, Write, BufReader, BufRead, BufWriter}, net::TcpStream, time::{Duration, Instant}};
 use image::{Luma, imageops, Pixel};
 use regex::{self, Regex};
-use crate::bitimage::BitImage;
+use crate::barcode;
+use crate::bitimage::{BitImage, BitOrder, Rotation};
+use crate::canvas::Canvas;
+use crate::capabilities::Capabilities;
+use crate::encoding::Encoding;
+use crate::font;
 
+/// # About
+/// Everything that can go wrong while printing.
+#[derive(Debug)]
+pub enum PrinterError {
+  /// Reading from or writing to the printer, or a file being printed, failed
+  Io(std::io::Error),
+  /// An image couldn't be opened or decoded
+  Image(image::ImageError),
+  /// Something doesn't fit in the printable area
+  OutOfBounds(String),
+  /// A QR code can't be printed with the given module size or data
+  InvalidQrSize(String),
+  /// An argument is outside of the range a method accepts
+  InvalidArgument(String),
+  /// A write didn't finish within the job timeout, see
+  /// ``Printer::set_job_timeout``, or the printer didn't answer in time
+  Timeout(String),
+  /// The printer's capabilities don't include what was asked for
+  Unsupported(String),
+  /// A size or length doesn't fit in the two bytes a command has for it
+  TooLarge(String),
+  /// Sending a bitmap failed after its first ``row`` rows were sent, see
+  /// ``Printer::resume_bitmap``
+  Interrupted { row: u16, source: Box<PrinterError> },
+}
+
+impl fmt::Display for PrinterError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      PrinterError::Io(e) => write!(f, "io error: {}", e),
+      PrinterError::Image(e) => write!(f, "image error: {}", e),
+      PrinterError::OutOfBounds(msg) => write!(f, "out of bounds: {}", msg),
+      PrinterError::InvalidQrSize(msg) => write!(f, "invalid QR code: {}", msg),
+      PrinterError::InvalidArgument(msg) => write!(f, "invalid argument: {}", msg),
+      PrinterError::Timeout(msg) => write!(f, "timed out: {}", msg),
+      PrinterError::Unsupported(msg) => write!(f, "unsupported: {}", msg),
+      PrinterError::TooLarge(msg) => write!(f, "too large: {}", msg),
+      PrinterError::Interrupted { row, source } => write!(f, "interrupted after row {}: {}", row, source)
+    }
+  }
+}
+
+impl std::error::Error for PrinterError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      PrinterError::Io(e) => Some(e),
+      PrinterError::Image(e) => Some(e),
+      PrinterError::Interrupted { source, .. } => Some(source.as_ref()),
+      _ => None
+    }
+  }
+}
+
+impl From<std::io::Error> for PrinterError {
+  fn from(e: std::io::Error) -> Self {
+    PrinterError::Io(e)
+  }
+}
+
+impl From<crate::bitimage::OutOfBounds> for PrinterError {
+  fn from(e: crate::bitimage::OutOfBounds) -> Self {
+    PrinterError::OutOfBounds(e.to_string())
+  }
+}
+
+impl From<image::ImageError> for PrinterError {
+  fn from(e: image::ImageError) -> Self {
+    PrinterError::Image(e)
+  }
+}
+
+/// # About
+/// A connection a ``Printer`` sends its commands over, implemented for
+/// ``File`` (USB, serial and parallel ports and Windows shares), ``TcpStream``
+/// (network printers) and in-memory buffers (``Vec<u8>`` and ``Cursor``), to
+/// capture the commands instead of printing them.
+pub trait Connection: Write + Send + Sized + 'static {
+  /// # About
+  /// Opens the connection to the printer at ``address``, also used by
+  /// ``Printer::reconnect``.
+  fn open(address: &str) -> std::io::Result<Self>;
+
+  /// # About
+  /// Another handle writing to the same printer, so a job timeout can write
+  /// from a watchdog thread, see ``Printer::set_job_timeout``. ``None`` if the
+  /// connection can't be shared, then the timeout is ignored.
+  fn duplicate(&self) -> Option<std::io::Result<Self>> {
+    None
+  }
+
+  /// # About
+  /// A handle to read the responses of the printer at ``address`` from, see
+  /// ``Capabilities::status_readback``.
+  fn reader(&self, address: &str) -> std::io::Result<Box<dyn Read + Send>> {
+    let _ = address;
+    Err(std::io::Error::new(
+      std::io::ErrorKind::Unsupported,
+      "this connection can't read the printer's responses"
+    ))
+  }
+
+  /// # About
+  /// Whether a read of 0 bytes from ``reader`` means the printer is gone, as
+  /// on a socket closed by the other end, rather than that it has nothing to
+  /// send yet. Decides how ``AsbEvents`` ends, ``true`` by default.
+  fn reads_end_at_eof(&self) -> bool {
+    true
+  }
+}
+
+impl Connection for File {
+  fn open(address: &str) -> std::io::Result<Self> {
+    File::create(Path::new(address))
+  }
+
+  fn duplicate(&self) -> Option<std::io::Result<Self>> {
+    Some(self.try_clone())
+  }
+
+  fn reader(&self, address: &str) -> std::io::Result<Box<dyn Read + Send>> {
+    // the handle was opened write-only, responses are read from a new one
+    Ok(Box::new(File::open(address)?))
+  }
+
+  /// Printer device files return 0 bytes while nothing was received, a
+  /// disconnected printer fails the read instead
+  fn reads_end_at_eof(&self) -> bool {
+    false
+  }
+}
+
+impl Connection for TcpStream {
+  /// Addresses without a port use 9100, the raw printing port
+  fn open(address: &str) -> std::io::Result<Self> {
+    if address.contains(':') {
+      TcpStream::connect(address)
+    } else {
+      TcpStream::connect((address, 9100))
+    }
+  }
+
+  fn duplicate(&self) -> Option<std::io::Result<Self>> {
+    Some(self.try_clone())
+  }
+
+  fn reader(&self, _address: &str) -> std::io::Result<Box<dyn Read + Send>> {
+    Ok(Box::new(self.try_clone()?))
+  }
+}
+
+impl Connection for Vec<u8> {
+  /// Opens an empty buffer, so ``Printer::reconnect`` discards what was sent
+  fn open(_address: &str) -> std::io::Result<Self> {
+    Ok(Vec::new())
+  }
+}
+
+impl Connection for std::io::Cursor<Vec<u8>> {
+  fn open(_address: &str) -> std::io::Result<Self> {
+    Ok(std::io::Cursor::new(Vec::new()))
+  }
+}
+
+/// # About
+/// Base struct used for printing, sending its commands over a ``Connection``,
+/// a ``File`` unless it was opened with ``connect_tcp``
+/// # Creating
+/// On Windows:
+/// ```
+/// let mut printer = Printer::new("\\\\MACHINE NAME\\SHARED_PRINTER_NAME")?;
+/// ```
+/// On Linux:
+/// ```
+/// let mut printer = Printer::new("/dev/usb/lp0")?;
+/// ```
+/// Over the network:
+/// ```
+/// let mut printer = Printer::connect_tcp("192.168.1.50:9100")?;
+/// ```
+/// Into memory:
+/// ```
+/// let mut printer = Printer::new_with_writer(Vec::new());
+/// ```
+pub struct Printer<C: Connection = File> {
+  path: String,
+  file_handle: BufWriter<C>,
+  line_width: usize,
+  capabilities: Capabilities,
+  raster_progress: u16,
+  ascii_safe: bool,
+  pagination: Option<Pagination>,
+  page_lines: usize,
+  process_id: u16,
+  page_mode: bool,
+  raster_band_height: u16,
+  chunk_delay: Duration,
+  allow_small_qr: bool,
+  qr_quiet_zone: u8,
+  double_strike: bool,
+  job_timeout: Option<Duration>,
+  stateless: bool,
+  justification: u8,
+  text_mode: u8,
+  color: Color,
+  fixed_pitch: bool,
+  line_spacing: Option<u8>,
+  font: Font,
+  code_page: Option<u8>,
+  encoding: Option<Encoding>,
+  upside_down: bool,
+  tab_stops: Option<Vec<u8>>,
+  barcode_width: u8,
+  barcode_height: u8,
+  total_options: TotalOptions,
+}
+
+/// # About
+/// Splits text printed with ``println`` into pages of ``page_length`` lines,
+/// each one surrounded by ``header`` and ``footer`` and followed by a cut.
+/// # Example
+/// ```
+/// printer.set_pagination(Some(Pagination {
+///   page_length: 40,
+///   header: "MONTHLY REPORT".to_string(),
+///   footer: "-- continues --".to_string(),
+/// }));
+/// ```
+pub struct Pagination {
+  /// Lines of content per page, not counting the header and footer
+  pub page_length: usize,
+  pub header: String,
+  pub footer: String,
+}
+
+/// # About
+/// The fixed header and footer of a receipt, encoded once and reused for
+/// every receipt printed with ``Printer::print_template``, so only the
+/// variable part has to be encoded each time.
+///
+/// Each part is sent right after whatever came before it, so they should be
+/// self-contained: set every mode they rely on and turn off the ones they
+/// change, instead of depending on the printer's state.
+/// # Example
+/// ```
+/// let template = Template::from_text("ACME STORE\n123 Main St.", "Thank you!");
+/// for order in orders {
+///   printer.print_template(&template, &order.summary())?;
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Template {
+  header: Vec<u8>,
+  footer: Vec<u8>,
+}
+
+impl Template {
+  /// # About
+  /// Uses already encoded bytes as the header and footer, which can contain
+  /// any command.
+  pub fn new(header: Vec<u8>, footer: Vec<u8>) -> Self {
+    Template { header, footer }
+  }
+
+  /// # About
+  /// Encodes the lines of ``header`` and ``footer`` as plain text, each one
+  /// ending with a line feed. Control characters are removed, see
+  /// ``sanitize``.
+  pub fn from_text(header: &str, footer: &str) -> Self {
+    Template {
+      header: encode_lines(&sanitize(header), None),
+      footer: encode_lines(&sanitize(footer), None),
+    }
+  }
+
+  /// # About
+  /// The bytes of a whole receipt with ``body`` between the header and the
+  /// footer.
+  pub fn render(&self, body: &[u8]) -> Vec<u8> {
+    let mut receipt = Vec::with_capacity(self.header.len() + body.len() + self.footer.len());
+    receipt.extend_from_slice(&self.header);
+    receipt.extend_from_slice(body);
+    receipt.extend_from_slice(&self.footer);
+    receipt
+  }
+}
+
+
+/// # About
+/// Settings tracked by ``Printer``, see ``Printer::snapshot``. The default is
+/// the state right after ``Printer::reset``.
+///
+/// Print density isn't part of it. ``Printer`` has no density setting to
+/// track: on most printers it's a memory switch, which persists across
+/// ``reset`` and power cycles and is set from the printer's own setup mode.
+/// Tab stops, see ``Printer::set_tab_stops``, are left out too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PrinterState {
+  /// 0 left, 1 center, 2 right, see ``Printer::set_justification``
+  pub justification: u8,
+  /// The ``ESC !`` bits, see ``Printer::set_text_mode``
+  pub text_mode: u8,
+  pub double_strike: bool,
+  pub color: Color,
+  pub fixed_pitch: bool,
+  /// ``None`` for the printer's default, see ``Printer::set_line_spacing``
+  pub line_spacing: Option<u8>,
+  pub font: Font,
+  /// ``None`` for the printer's default, see ``Printer::set_code_page``
+  pub code_page: Option<u8>,
+  pub upside_down: bool,
+}
+
+/// # About
+/// How ``Printer::print_total`` formats and emphasizes amounts.
+/// # Example
+/// ```
+/// // 1.234,50 €
+/// printer.set_total_options(TotalOptions {
+///   thousands_separator: '.',
+///   decimal_separator: ',',
+///   symbol_after: true,
+///   ..Default::default()
+/// });
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TotalOptions {
+  pub thousands_separator: char,
+  pub decimal_separator: char,
+  /// Puts the currency symbol after the amount, separated by a space,
+  /// instead of right before it
+  pub symbol_after: bool,
+  /// Prints the line in bold, on by default
+  pub bold: bool,
+  /// Prints the line white on black with ``GS B``
+  pub reverse: bool,
+}
+
+impl Default for TotalOptions {
+  fn default() -> Self {
+    TotalOptions {
+      thousands_separator: ',',
+      decimal_separator: '.',
+      symbol_after: false,
+      bold: true,
+      reverse: false,
+    }
+  }
+}
+
+/// # About
+/// Alignment of text inside a fixed width cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Align {
+  #[default]
+  Left,
+  Center,
+  Right,
+}
+
+/// # About
+/// Ink colors of two-color thermal paper
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Color {
+  #[default]
+  Black,
+  Red,
+}
+
+/// # About
+/// Built-in fonts selected with ``Printer::set_font``
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Font {
+  /// 12x24 dots, 32 characters per line on 58mm paper and 48 on 80mm
+  #[default]
+  A,
+  /// 9x17 dots, 42 characters per line on 58mm paper and 64 on 80mm
+  B,
+}
+
+impl Font {
+  /// # About
+  /// Width of a single character in dots, before double width is applied.
+  pub fn char_width(self) -> u16 {
+    match self {
+      Font::A => 12,
+      Font::B => 9,
+    }
+  }
+}
+
+/// # About
+/// How far the cutter goes through the paper
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CutMode {
+  /// Cuts the paper off completely
+  #[default]
+  Full,
+  /// Leaves a small point uncut so the receipt doesn't fall, not supported
+  /// by every cutter
+  Partial,
+}
+
+/// # About
+/// Kind of paper loaded, which decides how the printer finds where each
+/// label starts, see ``Printer::set_paper_type``
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PaperType {
+  /// Receipt paper without marks, the default
+  #[default]
+  Continuous,
+  /// Die-cut labels on a backing, found by the gap sensor seeing through the
+  /// space between them
+  Gap,
+  /// Paper with black marks printed on its back, found by the reflective
+  /// sensor
+  BlackMark,
+}
+
+/// # About
+/// Pin of the drawer kick-out connector that ``open_drawer`` pulses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DrawerPin {
+  /// Pin 2, where a single drawer is usually connected
+  #[default]
+  Pin2,
+  /// Pin 5, used by a second drawer on printers that support two
+  Pin5,
+}
+
+/// # About
+/// QR code error correction levels, see ``Printer::print_qr_code``. Higher
+/// levels survive more smudged or torn paper but hold less data in the same
+/// number of modules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QrEcc {
+  /// Recovers about 7% of the code, the printer's default
+  #[default]
+  L = 48,
+  /// Recovers about 15% of the code
+  M = 49,
+  /// Recovers about 25% of the code
+  Q = 50,
+  /// Recovers about 30% of the code
+  H = 51,
+}
+
+/// # About
+/// Authentication used by a WiFi network, see ``print_qr_wifi``
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WifiSecurity {
+  #[default]
+  Wpa,
+  Wep,
+  Open,
+}
+
+/// # About
+/// 1D barcode symbologies supported by ``print_barcode`` and
+/// ``print_barcode_raster``
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Barcode {
+  /// 11 digits, or 12 with the check digit
+  UpcA,
+  /// 12 digits, or 13 with the check digit
+  Ean13,
+  /// 7 digits, or 8 with the check digit
+  Ean8,
+  /// Digits, uppercase letters, space and ``$%*+-./``
+  Code39,
+  /// Any ASCII, encoded with code set B unless the data starts with its own
+  /// ``{A``, ``{B`` or ``{C`` selection. Without one every ``{`` is printed
+  /// as is, with one the data is sent raw and a literal ``{`` is ``{{``
+  Code128,
+}
+
+/// # About
+/// Options for ``print_barcode_raster``, the defaults match the size
+/// ``print_barcode`` uses before ``set_barcode_size`` is called
+/// # Example
+/// ```
+/// let options = BarcodeOptions {
+///   module_width: 2,
+///   ..Default::default()
+/// };
+/// printer.print_barcode_raster(Barcode::Code128, b"ORDER-1234", &options)?;
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarcodeOptions {
+  /// Width of the narrowest bar in dots, 3 by default
+  pub module_width: u8,
+  /// Height of the bars in dots, 80 by default
+  pub height: u16,
+  /// Prints the encoded text below the bars, on by default
+  pub text: bool,
+  /// Rotates the barcode 90° clockwise so it runs down the paper, for codes
+  /// too long to fit across it, off by default
+  pub vertical: bool,
+}
+
+impl Default for BarcodeOptions {
+  fn default() -> Self {
+    Self {
+      module_width: 3,
+      height: 80,
+      text: true,
+      vertical: false,
+    }
+  }
+}
+
+/// # About
+/// A graphic stored in the printer's NV memory, see
+/// ``Printer::list_nv_logos``
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyInfo {
+  /// The two key code bytes the graphic was stored with
+  pub key: (u8, u8),
+}
+
+/// # About
+/// Which status changes the printer reports on its own once ``enable_asb``
+/// is called, see ``Printer::enable_asb``.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AsbFlags {
+  /// The drawer kick-out connector's pin 3, which sees if the drawer is open
+  pub drawer: bool,
+  /// Going online or offline, and the cover and feed button
+  pub online: bool,
+  /// Cutter and mechanical errors
+  pub error: bool,
+  /// Paper running low or out
+  pub paper: bool,
+}
+
+impl AsbFlags {
+  /// # About
+  /// Every status change.
+  pub fn all() -> Self {
+    AsbFlags { drawer: true, online: true, error: true, paper: true }
+  }
+}
+
+/// # About
+/// A status packet sent by the printer with automatic status back enabled,
+/// see ``Printer::asb_events``.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AsbStatus {
+  /// Level of the drawer kick-out connector's pin 3, its meaning depends on
+  /// the drawer
+  pub drawer_pin_high: bool,
+  pub offline: bool,
+  pub cover_open: bool,
+  /// Paper is being fed with the feed button
+  pub feeding: bool,
+  pub mechanical_error: bool,
+  pub cutter_error: bool,
+  /// An error that needs the printer to be turned off and on again
+  pub unrecoverable_error: bool,
+  /// An error that clears on its own, like the head overheating
+  pub recoverable_error: bool,
+  pub paper_near_end: bool,
+  pub paper_end: bool,
+}
+
+impl AsbStatus {
+  /// # About
+  /// Parses the 4 bytes of an ASB packet, returning ``None`` if the fixed
+  /// bits don't match the ones of a packet.
+  /// # Examples
+  /// ```
+  /// let status = AsbStatus::parse([0x38, 0x00, 0x0c, 0x00]).unwrap();
+  /// assert!(status.cover_open && status.paper_end);
+  /// ```
+  pub fn parse(packet: [u8; 4]) -> Option<Self> {
+    // byte 1 has bit 4 set and bits 0, 1 and 7 clear, the others have bits
+    // 4 and 7 clear
+    if packet[0] & 0x93 != 0x10 || packet[1..].iter().any(|b| b & 0x90 != 0) {
+      return None
+    }
+    Some(AsbStatus {
+      drawer_pin_high: packet[0] & 0x04 != 0,
+      offline: packet[0] & 0x08 != 0,
+      cover_open: packet[0] & 0x20 != 0,
+      feeding: packet[0] & 0x40 != 0,
+      mechanical_error: packet[1] & 0x04 != 0,
+      cutter_error: packet[1] & 0x08 != 0,
+      unrecoverable_error: packet[1] & 0x20 != 0,
+      recoverable_error: packet[1] & 0x40 != 0,
+      paper_near_end: packet[2] & 0x03 != 0,
+      paper_end: packet[2] & 0x0c != 0
+    })
+  }
+}
+
+/// # About
+/// The ASB packets read from the printer, see ``Printer::asb_events``.
+///
+/// Every call to ``next`` blocks until the printer sends a packet. How it
+/// stops depends on the connection:
+/// - over TCP the iterator ends, returning ``None``, once the printer closes
+///   the connection
+/// - on device files, which read 0 bytes while the printer is quiet, it
+///   returns ``PrinterError::Timeout`` after ``ASB_IDLE_TIMEOUT`` without a
+///   packet. The iterator isn't over, calling ``next`` again waits for
+///   another period, see ``set_idle_timeout``. A printer that's unplugged
+///   fails the read with ``PrinterError::Io`` instead
+///
+/// Read errors are returned as they happen and don't end the iterator either.
+pub struct AsbEvents {
+  reader: Box<dyn Read + Send>,
+  received: Vec<u8>,
+  ends_at_eof: bool,
+  idle_timeout: Duration,
+}
+
+impl AsbEvents {
+  /// # About
+  /// Sets how long ``next`` waits for a packet on connections that don't
+  /// signal a disconnect with an empty read, ``ASB_IDLE_TIMEOUT`` by default.
+  pub fn set_idle_timeout(&mut self, timeout: Duration) {
+    self.idle_timeout = timeout;
+  }
+}
+
+impl Iterator for AsbEvents {
+  type Item = Result<AsbStatus, PrinterError>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    let mut buf = [0u8; 64];
+    let started = Instant::now();
+    loop {
+      // drops bytes until a packet lines up, such as other responses sent in
+      // between
+      while self.received.len() >= 4 {
+        let packet = [self.received[0], self.received[1], self.received[2], self.received[3]];
+        if let Some(status) = AsbStatus::parse(packet) {
+          self.received.drain(..4);
+          return Some(Ok(status))
+        }
+        self.received.remove(0);
+      }
+      match self.reader.read(&mut buf) {
+        Ok(0) if self.ends_at_eof => return None,
+        Ok(0) if started.elapsed() >= self.idle_timeout => {
+          return Some(Err(PrinterError::Timeout(format!(
+            "no status packet in {} ms", self.idle_timeout.as_millis()
+          ))))
+        }
+        Ok(0) => std::thread::sleep(Duration::from_millis(50)),
+        Ok(read) => self.received.extend_from_slice(&buf[..read]),
+        Err(e) => return Some(Err(e.into()))
+      }
+    }
+  }
+}
+
+/// # About
+/// How ``print_image`` makes an image fit the requested width.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImageFit {
+  /// Scales the whole image to the width, keeping its aspect ratio
+  #[default]
+  Scale,
+  /// Trims equal amounts from both sides of images wider than the width,
+  /// keeping the center at full resolution. Narrower images are scaled.
+  Crop,
+}
+
+/// # About
+/// What transparent areas of an image are printed as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BgMode {
+  /// Composites the image over white paper
+  #[default]
+  White,
+  /// Composites the image over black, for logos made for dark backgrounds
+  Black,
+  /// Pixels with an alpha below the given threshold (0-255) are left blank,
+  /// every other pixel is printed as if it was fully opaque
+  Transparent(u8),
+}
+
+/// # About
+/// How ``print_image`` turns grays into black and white dots.
+///
+/// The error diffusion algorithms are described on
+/// <https://en.wikipedia.org/wiki/Dither#Algorithms>.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Dither {
+  FloydSteinberg,
+  #[default]
+  TwoRowSierra,
+  Sierra,
+  Burkes,
+  Stucki,
+  /// No dithering, pixels darker than 128 are printed
+  Threshold,
+  /// Ordered dithering with a user supplied threshold map, repeated over the
+  /// whole image. A pixel is printed when its lightness (0-255) is lower than
+  /// the map value at its position, so a map filled with 128 is the same as
+  /// ``Threshold`` and higher values print darker.
+  ///
+  /// The map must not be empty and all of its rows must have the same length.
+  Custom(Vec<Vec<u8>>),
+  /// Ordered dithering with the 4x4 Bayer matrix, which turns gradients into
+  /// regular patterns instead of the worms error diffusion leaves, good for
+  /// screenshots and charts
+  Bayer4,
+  /// Like ``Bayer4`` with the 8x8 matrix, smoother gradients with 64 levels
+  /// of gray instead of 16
+  Bayer8,
+  /// Diffuses half of the error to only three neighbors, the fastest of the
+  /// error diffusion algorithms
+  SierraLite,
+  /// Bill Atkinson's algorithm from the original Macintosh, diffuses only
+  /// 3/4 of the error so highlights and shadows lose detail but midtones
+  /// stay crisp
+  Atkinson,
+  /// Error diffusion with a user supplied kernel, each weight being the
+  /// ``(dx, dy, weight)`` of a neighbor that gets ``weight / divisor`` of the
+  /// error. Only pixels after the current one can receive error, so either
+  /// ``dy`` must be above 0 or ``dy`` must be 0 and ``dx`` above 0.
+  /// # Example
+  /// ```
+  /// // Floyd-Steinberg
+  /// let dithering = Dither::Kernel {
+  ///   weights: vec![(1, 0, 7), (-1, 1, 3), (0, 1, 5), (1, 1, 1)],
+  ///   divisor: 16,
+  /// };
+  /// ```
+  Kernel {
+    weights: Vec<(i32, i32, i32)>,
+    divisor: i32,
+  },
+  /// Sends the image as 16 shades of gray and lets the printer dither it,
+  /// which is faster and tuned to the print head. Needs
+  /// ``Capabilities::multi_tone``, otherwise and when the image doesn't fit
+  /// in the graphics buffer it's dithered like ``TwoRowSierra``.
+  Printer,
+}
+
+impl Dither {
+  /// # About
+  /// The error diffusion kernel as ``(dx, dy, weight)`` neighbors and the
+  /// divisor of the weights, with no neighbors for the modes that don't
+  /// diffuse the error.
+  fn kernel(&self) -> (&[(i32, i32, i32)], i32) {
+    const FLOYD_STEINBERG: [(i32, i32, i32); 4] = [
+                           (1, 0, 7),
+      (-1, 1, 3), (0, 1, 5), (1, 1, 1),
+    ];
+    const TWO_ROW_SIERRA: [(i32, i32, i32); 7] = [
+                                      (1, 0, 4), (2, 0, 3),
+      (-2, 1, 1), (-1, 1, 2), (0, 1, 3), (1, 1, 2), (2, 1, 1),
+    ];
+    const SIERRA: [(i32, i32, i32); 10] = [
+                                      (1, 0, 5), (2, 0, 3),
+      (-2, 1, 2), (-1, 1, 4), (0, 1, 5), (1, 1, 4), (2, 1, 2),
+                  (-1, 2, 2), (0, 2, 3), (1, 2, 2),
+    ];
+    const BURKES: [(i32, i32, i32); 7] = [
+                                      (1, 0, 8), (2, 0, 4),
+      (-2, 1, 2), (-1, 1, 4), (0, 1, 8), (1, 1, 4), (2, 1, 2),
+    ];
+    const STUCKI: [(i32, i32, i32); 12] = [
+                                      (1, 0, 8), (2, 0, 4),
+      (-2, 1, 2), (-1, 1, 4), (0, 1, 8), (1, 1, 4), (2, 1, 2),
+      (-2, 2, 1), (-1, 2, 2), (0, 2, 4), (1, 2, 2), (2, 2, 1),
+    ];
+    const SIERRA_LITE: [(i32, i32, i32); 3] = [
+                 (1, 0, 2),
+      (-1, 1, 1), (0, 1, 1),
+    ];
+    const ATKINSON: [(i32, i32, i32); 6] = [
+                             (1, 0, 1), (2, 0, 1),
+      (-1, 1, 1), (0, 1, 1), (1, 1, 1),
+                  (0, 2, 1),
+    ];
+    match self {
+      Dither::FloydSteinberg => (&FLOYD_STEINBERG, 16),
+      Dither::TwoRowSierra | Dither::Printer => (&TWO_ROW_SIERRA, 16),
+      Dither::Sierra => (&SIERRA, 32),
+      Dither::Burkes => (&BURKES, 32),
+      Dither::Stucki => (&STUCKI, 42),
+      Dither::SierraLite => (&SIERRA_LITE, 4),
+      Dither::Atkinson => (&ATKINSON, 8),
+      Dither::Kernel { weights, divisor } => (weights.as_slice(), *divisor),
+      _ => (&[], 1)
+    }
+  }
+}
+
+/// # About
+/// Unsharp mask applied to images after they're resized and before they're
+/// dithered, bringing back the edges lost to the resampling so text and line
+/// art dither crisper.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sharpen {
+  /// Radius of the blur the image is compared against, in dots
+  pub radius: u8,
+  /// How much of the difference to the blurred image is added back, in
+  /// percent, 100 doubles the contrast of the edges
+  pub amount: u16,
+}
+
+/// # About
+/// Options for ``print_image``
+/// # Example
+/// ```
+/// let options = ImageOptions {
+///   fit: ImageFit::Crop,
+///   ..Default::default()
+/// };
+/// printer.print_image("photo.jpg", 384, &Dither::default(), &options)?;
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ImageOptions {
+  pub fit: ImageFit,
+  pub background: BgMode,
+  /// Sharpens the image before dithering it, off by default
+  pub sharpen: Option<Sharpen>,
+  /// Low-ink mode, keeping the printed dots at most this percentage of the
+  /// image by printing the darkest ``LOW_INK_TILE`` square tiles inverted,
+  /// so large dark areas don't smear or wear out the head. The darkest tiles
+  /// are inverted first until the cap is met, tiles that would get darker
+  /// aren't, so caps below what that allows are only approached
+  pub max_coverage: Option<u8>,
+  /// Prints the image white on black, see ``BitImage::invert``. Transparent
+  /// areas are still left blank with ``BgMode::Transparent``
+  pub invert: bool,
+  /// Rotates the image clockwise before it's scaled to the width, so
+  /// sideways images still fill the paper
+  pub rotation: Option<Rotation>,
+}
+
+/// Images taller than this many rows are printed with ``Printer::print_raster``
+/// by ``Printer::print_image`` on printers with a graphics buffer
+pub const RASTER_HEIGHT_THRESHOLD: usize = 255;
+
+/// Most rows ``GS ( L`` function 112 stores at once at normal scale
+pub const GRAPHICS_MAX_ROWS: usize = 1662;
+
+/// Side in dots of the tiles inverted by ``ImageOptions::max_coverage``
+pub const LOW_INK_TILE: usize = 32;
+
+pub const GS: u8 = 0x1d;
+pub const ESC: u8 = 0x1b;
+pub const FS: u8 = 0x1c;
+pub const LF: u8 = 0x0a;
+pub const FF: u8 = 0x0c;
+pub const CR: u8 = 0x0d;
+pub const CAN: u8 = 0x18;
+
+impl Printer<File> {
+
+  /// # About
+  /// Opens the printer at ``printer_path``, failing if the path is wrong or
+  /// the printer share is offline.
+  ///
+  /// The path is opened as is, so it's a UNC path to a shared printer on
+  /// Windows and the printer's device file on Linux and macOS.
+  /// # Examples
+  /// On Windows:
+  /// ```
+  /// let mut printer = Printer::new("\\\\MACHINE NAME\\SHARED_PRINTER_NAME")?;
+  /// ```
+  /// On Linux, with a USB printer:
+  /// ```
+  /// let mut printer = Printer::new("/dev/usb/lp0")?;
+  /// ```
+  pub fn new(printer_path: &str) -> Result<Self, PrinterError> {
+    Ok(Printer::with_connection(<File as Connection>::open(printer_path)?, printer_path))
+  }
+}
+
+impl Printer<TcpStream> {
+  /// # About
+  /// Connects to a network printer accepting raw ESC-POS over TCP, failing
+  /// if it can't be reached. ``address`` is a ``host:port`` pair, the port can
+  /// be left out for the usual 9100.
+  /// # Examples
+  /// ```
+  /// let mut printer = Printer::connect_tcp("192.168.1.50:9100")?;
+  /// let mut printer = Printer::connect_tcp("printer.local")?;
+  /// ```
+  pub fn connect_tcp(address: &str) -> Result<Self, PrinterError> {
+    Ok(Printer::with_connection(<TcpStream as Connection>::open(address)?, address))
+  }
+}
+
+impl<C: Connection> Printer<C> {
+
+  /// # About
+  /// Sends the commands to ``writer``, such as a ``Vec<u8>`` to check the
+  /// bytes a method sends. ``reconnect`` opens a new one with
+  /// ``Connection::open``.
+  /// # Example
+  /// ```
+  /// let mut printer = Printer::new_with_writer(Vec::new());
+  /// printer.cut(CutMode::Partial)?;
+  /// assert_eq!(printer.into_inner()?, [GS, b'V', 1]);
+  /// ```
+  pub fn new_with_writer(writer: C) -> Self {
+    Printer::with_connection(writer, "")
+  }
+
+  /// # About
+  /// The connection the commands are sent to. Every method flushes before
+  /// returning, so it has received everything sent so far.
+  pub fn get_ref(&self) -> &C {
+    self.file_handle.get_ref()
+  }
+
+  /// # About
+  /// Flushes what's still buffered and returns the connection.
+  /// # Errors
+  /// - if the buffered bytes can't be written
+  pub fn into_inner(self) -> Result<C, PrinterError> {
+    self.file_handle.into_inner().map_err(|e| PrinterError::Io(e.into_error()))
+  }
+
+  /// # About
+  /// Wraps an already opened ``connection`` to the printer at ``address``.
+  fn with_connection(connection: C, address: &str) -> Self {
+    Printer {
+      file_handle: BufWriter::new(connection),
+      path: address.to_string(),
+      line_width: 32,
+      capabilities: Capabilities::default(),
+      raster_progress: 0,
+      ascii_safe: false,
+      pagination: None,
+      page_lines: 0,
+      process_id: 0,
+      page_mode: false,
+      raster_band_height: 64,
+      chunk_delay: Duration::ZERO,
+      allow_small_qr: false,
+      qr_quiet_zone: QR_QUIET_ZONE,
+      double_strike: false,
+      job_timeout: None,
+      stateless: false,
+      justification: 0,
+      text_mode: 0,
+      color: Color::Black,
+      fixed_pitch: false,
+      line_spacing: None,
+      font: Font::A,
+      code_page: None,
+      encoding: None,
+      upside_down: false,
+      tab_stops: None,
+      barcode_width: 3,
+      barcode_height: 80,
+      total_options: TotalOptions::default()
+    }
+  }
+
+  /// # About
+  /// Opens the printer's path or address again, replacing the current handle,
+  /// to recover after the printer was disconnected or turned off.
+  ///
+  /// The printer may have lost its settings in the meantime, call ``reset()``
+  /// afterwards to start from a known state. The progress of an interrupted
+  /// bitmap is kept, so it can be finished with ``resume_bitmap``.
+  /// # Example
+  /// ```
+  /// while printer.reconnect().is_err() {
+  ///   std::thread::sleep(Duration::from_secs(5));
+  /// }
+  /// printer.reset()?;
+  /// ```
+  pub fn reconnect(&mut self) -> Result<(), PrinterError> {
+    let handle = BufWriter::new(C::open(&self.path)?);
+    // whatever was still buffered for the old handle is dropped unsent
+    let _ = std::mem::replace(&mut self.file_handle, handle).into_parts();
+    Ok(())
+  }
+
+  fn write_byte(&mut self, byte: u8) -> Result<(), PrinterError> {
+    write_all_retrying(&mut self.file_handle, &[byte])?;
+    Ok(())
+  }
+
+  fn flush_buf(&mut self) -> Result<(), PrinterError> {
+    flush_retrying(&mut self.file_handle)?;
+    Ok(())
+  }
+
+  /// # About
+  /// Sends a single form feed (``0x0c``) and flushes.
+  ///
+  /// In standard mode most printers treat it as "print buffer", but some eject
+  /// a whole page, so it's never emitted implicitly by the other methods,
+  /// which end their lines with line feeds instead.
+  pub fn form_feed(&mut self) -> Result<(), PrinterError> {
+    self.write_byte(FF)?;
+    self.flush_buf()?;
+    Ok(())
+  }
+
+  fn line_feed(&mut self) -> Result<(), PrinterError> {
+    self.write_byte(LF)?;
+    Ok(())
+  }
+
+  /// # About
+  /// Feeds the paper by ``n`` empty lines, sending ``n`` line feeds and then
+  /// flushing once.
+  ///
+  /// Unlike calling ``println("")`` repeatedly it doesn't count towards the
+  /// lines of a page when paginating.
+  pub fn blank_lines(&mut self, n: usize) -> Result<(), PrinterError> {
+    self.print_bytes(&vec![LF; n])?;
+    Ok(())
+  }
+
+  /// # About
+  /// Appends raster data with rows of ``w_bytes`` bytes to a command,
+  /// reversing the bits of every byte for printers that expect
+  /// ``BitOrder::LsbFirst``.
+  ///
+  /// When ``width`` isn't a multiple of 8 the last byte of each row has
+  /// padding bits past the edge of the image, which some printers print as
+  /// stray dots, so they're always cleared before being sent.
+  fn extend_raster(&self, cmd: &mut Vec<u8>, data: &[u8], width: usize, w_bytes: usize) {
+    for (i, byte) in data.iter().enumerate() {
+      let valid_bits = width.saturating_sub((i % w_bytes.max(1)) * 8).min(8);
+      let byte = byte & !(0xFF_u8.checked_shr(valid_bits as u32).unwrap_or(0));
+      cmd.push(match self.capabilities.bit_order {
+        BitOrder::MsbFirst => byte,
+        BitOrder::LsbFirst => byte.reverse_bits()
+      });
+    }
+  }
+
+  /// # About
+  /// Turns a u16 into a format that can be sent to the printer
+  fn to_two_byte(&self, num: u16) -> [u8;2] {
+    let mut bytes: [u8;2] = num.to_be_bytes();
+    bytes.reverse();
+    bytes
+  }
+
+  /// # Examples
+  /// ```
+  /// printer.println("Hello World!")?;
+  /// ```
+  pub fn println(&mut self, message: &str) -> Result<(), PrinterError> {
+    let message = self.filter_text(message);
+    if self.stateless {
+      self.restore_modes()?;
+    }
+    let page_length = match &self.pagination {
+      Some(pagination) => pagination.page_length.max(1),
+      None => {
+        self.write_line(&message)?;
+        return Ok(())
+      }
+    };
+    for line in message.split('\n') {
+      if self.page_lines == 0 {
+        let header = self.pagination.as_ref().unwrap().header.clone();
+        for header_line in header.lines() {
+          self.write_line(header_line)?;
+        }
+      }
+      self.write_line(line)?;
+      self.page_lines += 1;
+      if self.page_lines >= page_length {
+        self.end_page()?;
+      }
+    }
+    Ok(())
+  }
+
+  /// # About
+  /// Sends ``text`` followed by a carriage return without a line feed, so the
+  /// next call prints over it. Useful for spinners and progress bars on
+  /// impact printers and kiosks without a display.
+  ///
+  /// This only works on printers that return the head on ``CR`` without
+  /// feeding the paper, most thermal printers ignore ``CR`` or treat it as a
+  /// line feed when auto line feed is enabled. Newlines in ``text`` are
+  /// dropped.
+  /// # Examples
+  /// ```
+  /// for percent in (0..=100).step_by(10) {
+  ///   printer.print_inplace(&format!("{:>3}%", percent))?;
+  /// }
+  /// printer.println("")?;
+  /// ```
+  pub fn print_inplace(&mut self, text: &str) -> Result<(), PrinterError> {
+    let text = self.filter_text(text).replace('\n', "");
+    let mut cmd: Vec<u8> = self.encode_text(&text).into_owned();
+    cmd.push(CR);
+    self.print_bytes(&cmd)?;
+    Ok(())
+  }
+
+  fn write_line(&mut self, line: &str) -> Result<(), PrinterError> {
+    let line = self.encode_text(line);
+    write_all_retrying(&mut self.file_handle, &line)?;
+    self.line_feed()?;
+    self.flush_buf()?;
+    Ok(())
+  }
+
+  /// # About
+  /// Enables or disables pagination for ``println``, see ``Pagination``.
+  ///
+  /// The header of a page is only printed once its first line is, so no empty
+  /// pages are left at the end of a job.
+  pub fn set_pagination(&mut self, pagination: Option<Pagination>) {
+    self.pagination = pagination;
+    self.page_lines = 0;
+  }
+
+  /// # About
+  /// Prints the footer and cuts the paper with ``feed_and_cut`` if the current
+  /// page has any lines.
+  ///
+  /// Call it at the end of a paginated job so the last page is closed too.
+  pub fn end_page(&mut self) -> Result<(), PrinterError> {
+    if self.page_lines == 0 {
+      return Ok(())
+    }
+    if let Some(pagination) = &self.pagination {
+      let footer = pagination.footer.clone();
+      for footer_line in footer.lines() {
+        self.write_line(footer_line)?;
+      }
+    }
+    self.page_lines = 0;
+    self.feed_and_cut(CutMode::Full)?;
+    Ok(())
+  }
+
+  /// # About
+  /// When enabled, ``println`` and the text layout helpers drop every control
+  /// character except newlines and replace non-ASCII characters with ``?``.
+  ///
+  /// Use this when printing untrusted input, otherwise anyone controlling the
+  /// text can embed ESC-POS commands in it (cut, drawer kick, reset...).
+  /// # Example
+  /// ```
+  /// printer.set_ascii_safe(true);
+  /// // prints "hello @world", the ESC is dropped and the @ becomes text
+  /// printer.println("hello \x1b@world")?;
+  /// ```
+  pub fn set_ascii_safe(&mut self, on: bool) {
+    self.ascii_safe = on;
+  }
+
+  /// # About
+  /// Transcodes the text used by ``println`` and the text layout helpers to
+  /// ``encoding``, replacing the characters it doesn't have with ``?``.
+  /// ``None``, the default, sends the bytes of the UTF-8 strings unchanged.
+  ///
+  /// ``set_code_page`` picks the encoding matching the Epson page numbers it
+  /// knows, see ``Encoding::for_code_page``, call this afterwards on printers
+  /// numbering their pages differently.
+  /// # Example
+  /// ```
+  /// printer.set_code_page(2)?;
+  /// // prints "café" on any printer, since 2 is CP850
+  /// printer.println("café")?;
+  /// ```
+  pub fn set_encoding(&mut self, encoding: Option<Encoding>) {
+    self.encoding = encoding;
+  }
+
+  fn encode_text<'a>(&self, text: &'a str) -> Cow<'a, [u8]> {
+    match self.encoding {
+      Some(encoding) => Cow::Owned(encoding.encode(text)),
+      None => Cow::Borrowed(text.as_bytes())
+    }
+  }
+
+  fn filter_text<'a>(&self, text: &'a str) -> Cow<'a, str> {
+    if self.ascii_safe {
+      Cow::Owned(to_ascii_safe(text))
+    } else {
+      Cow::Borrowed(text)
+    }
+  }
+
+  /// # About
+  /// Funcion used to send an array of bytes to the printer and flush its buffer.
+  /// # Warning
+  /// Sending a sequence of bytes might cause the printer to stop working,
+  /// requiring a reboot.
+  ///
+  /// Only use this if you know what you're doing.
+  ///
+  /// # Tip
+  /// use the constants ``printing::GS`` and ``printing::ESC`` as escape characters.
+  pub fn print_bytes(&mut self, message: &[u8]) -> Result<(), PrinterError> {
+    self.write_timed(message)
+  }
+
+  /// # About
+  /// Limits how long ``print_bytes`` and each band of ``print_bitmap`` may
+  /// take to be written, so a jammed printer can't hang the caller forever.
+  /// ``None``, the default, waits indefinitely.
+  ///
+  /// When the time runs out the call returns ``PrinterError::Timeout``.
+  ///
+  /// A blocking write to the printer's handle can't be interrupted, so with a
+  /// timeout the writes happen in a watchdog thread. The thread of a timed out
+  /// write stays blocked until the OS gives up on it, and the printer may have
+  /// received only part of the data, call ``reconnect()`` and ``reset()``
+  /// before printing again.
+  pub fn set_job_timeout(&mut self, timeout: Option<Duration>) {
+    self.job_timeout = timeout;
+  }
+
+  /// # About
+  /// Writes and flushes ``bytes``, giving up after the job timeout if one is
+  /// set, see ``set_job_timeout``.
+  fn write_timed(&mut self, bytes: &[u8]) -> Result<(), PrinterError> {
+    let timeout = match self.job_timeout {
+      Some(timeout) => timeout,
+      None => {
+        write_all_retrying(&mut self.file_handle, bytes)?;
+        flush_retrying(&mut self.file_handle)?;
+        return Ok(())
+      }
+    };
+    // the watchdog thread takes the buffered handle, so the bytes still
+    // waiting in its buffer are sent first
+    let clone = match self.file_handle.get_ref().duplicate() {
+      Some(clone) => BufWriter::new(clone?),
+      None => {
+        write_all_retrying(&mut self.file_handle, bytes)?;
+        flush_retrying(&mut self.file_handle)?;
+        return Ok(())
+      }
+    };
+    let (mut handle, buffered) = std::mem::replace(&mut self.file_handle, clone).into_parts();
+    let mut buffered = buffered.unwrap_or_default();
+    buffered.extend_from_slice(bytes);
+    let bytes = buffered;
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+      let result = write_all_retrying(&mut handle, &bytes).and_then(|_| flush_retrying(&mut handle));
+      // the receiver is gone if the write already timed out
+      let _ = sender.send(result);
+    });
+    match receiver.recv_timeout(timeout) {
+      Ok(result) => Ok(result?),
+      Err(_) => Err(PrinterError::Timeout(
+        format!("writing to the printer took longer than {:?}", timeout)
+      ))
+    }
+  }
+
+  /// # About
+  /// Sends the raw contents of a file to the printer, for replaying captured
+  /// ESC-POS dumps or vendor-provided command files.
+  ///
+  /// The file is streamed in chunks through ``print_bytes`` instead of being
+  /// loaded into memory all at once.
+  /// # Warning
+  /// The same caveats of ``print_bytes`` apply, only send files you trust.
+  /// # Errors
+  /// - if the file cannot be opened or read
+  pub fn print_file(&mut self, path: &str) -> Result<(), PrinterError> {
+    let file = File::open(path)?;
+    self.print_reader(file)?;
+    Ok(())
+  }
+
+  /// # About
+  /// Forwards everything read from ``reader`` to the printer unmodified until
+  /// it ends, in chunks through ``print_bytes``, so streams of any size can be
+  /// sent without holding them in memory.
+  /// # Examples
+  /// ```
+  /// printer.print_reader(std::io::stdin().lock())?;
+  /// ```
+  /// # Warning
+  /// The same caveats of ``print_bytes`` apply, only send data you trust.
+  /// # Errors
+  /// - if reading fails
+  pub fn print_reader(&mut self, mut reader: impl Read) -> Result<(), PrinterError> {
+    let mut buf = [0u8; 4096];
+    loop {
+      let read = match reader.read(&mut buf) {
+        Ok(0) => break,
+        Ok(o) => o,
+        Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+        Err(e) => return Err(e.into())
+      };
+      self.print_bytes(&buf[..read])?;
+    }
+    Ok(())
+  }
+
+  /// # About
+  /// Prints a receipt made of the header of ``template``, the lines of
+  /// ``body`` and the footer, sent with a single write.
+  ///
+  /// ``body`` goes through the same filtering as ``println``, but isn't
+  /// paginated.
+  pub fn print_template(&mut self, template: &Template, body: &str) -> Result<(), PrinterError> {
+    let body = encode_lines(&self.filter_text(body), self.encoding);
+    self.print_bytes(&template.render(&body))?;
+    Ok(())
+  }
+
+  /// # About
+  /// Sends several pre-encoded receipts back to back, cutting the paper after
+  /// each one, for batch printing over a single connection.
+  ///
+  /// Each job's bytes should be self-contained, starting with their own
+  /// ``ESC @`` and settings, since they're sent after whatever state the
+  /// previous job left the printer in.
+  /// # Warning
+  /// The same caveats of ``print_bytes`` apply to the contents of each job.
+  pub fn print_jobs(&mut self, jobs: &[Vec<u8>]) -> Result<(), PrinterError> {
+    for job in jobs {
+      self.write_vec(job)?;
+      self.flush_buf()?;
+      self.cut_paper()?;
+    }
+    Ok(())
+  }
+
+  /// # About
+  /// Simply puts the contents of the supplied vector into the buffer, the
+  /// printer's handle is buffered so nothing is sent until it's full or
+  /// flushed.
+  ///
+  /// Requires flushing.
+  fn write_vec(&mut self, bytes: &[u8]) -> Result<(), PrinterError> {
+    write_all_retrying(&mut self.file_handle, bytes)?;
+    Ok(())
+  }
+
+  /// # About
+  /// Sets how many characters fit in a single line, used by the text layout
+  /// helpers such as ``print_kv``.
+  ///
+  /// Defaults to 32, which is Font A on 58mm paper.
+  pub fn set_line_width(&mut self, chars: usize) {
+    self.line_width = chars;
+  }
+
+  pub fn get_line_width(&self) -> usize {
+    self.line_width
+  }
+
+  /// # About
+  /// Sets which optional features the printer supports, see ``Capabilities``.
+  pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+    self.capabilities = capabilities;
+  }
+
+  pub fn get_capabilities(&self) -> &Capabilities {
+    &self.capabilities
+  }
+
+  /// # About
+  /// Blocks until the printer has finished printing everything sent so far,
+  /// so that cutting or opening the drawer doesn't happen before the last
+  /// line is printed.
+  ///
+  /// With ``Capabilities::status_readback`` it sends a ``GS ( H`` process ID
+  /// and waits for the printer to answer it, which it only does once all the
+  /// previous data was processed. Returns ``false`` if no answer arrives
+  /// within ``timeout``.
+  ///
+  /// Write-only printers can't report anything, so it just sleeps for
+  /// ``timeout`` and returns ``false``.
+  /// # Example
+  /// ```
+  /// printer.print_image("receipt.png", 384, &Dither::default(), &ImageOptions::default())?;
+  /// printer.wait_complete(Duration::from_secs(10))?;
+  /// printer.cut_paper()?;
+  /// ```
+  pub fn wait_complete(&mut self, timeout: Duration) -> Result<bool, PrinterError> {
+    if !self.capabilities.status_readback {
+      std::thread::sleep(timeout);
+      return Ok(false)
+    }
+
+    self.process_id = (self.process_id + 1) % 10000;
+    let id: Vec<u8> = format!("{:04}", self.process_id).into_bytes();
+    let mut cmd: Vec<u8> = Vec::from([GS, b'(', b'H', 0x06, 0x00, 48, 48]);
+    cmd.extend_from_slice(&id);
+    self.print_bytes(&cmd)?;
+
+    let mut reader = self.file_handle.get_ref().reader(&self.path)?;
+    // the response is 0x37 0x22, the process ID and then a NUL
+    let mut expected: Vec<u8> = Vec::from([0x37, 0x22]);
+    expected.extend_from_slice(&id);
+    expected.push(0x00);
+
+    let deadline = Instant::now() + timeout;
+    let mut received: Vec<u8> = Vec::new();
+    let mut buf = [0u8; 64];
+    while Instant::now() < deadline {
+      let read = reader.read(&mut buf)?;
+      if read == 0 {
+        std::thread::sleep(Duration::from_millis(50));
+        continue;
+      }
+      received.extend_from_slice(&buf[..read]);
+      if received.windows(expected.len()).any(|w| w == expected.as_slice()) {
+        return Ok(true)
+      }
+    }
+    Ok(false)
+  }
+
+  /// # About
+  /// Lists the key codes of the graphics stored in the printer's NV memory
+  /// with ``GS ( L`` function 64, so new logos don't overwrite existing ones.
+  ///
+  /// Needs ``Capabilities::status_readback``, otherwise it returns an
+  /// ``Unsupported`` error. Fails with ``Timeout`` if the printer doesn't
+  /// send a block of the list within ``NV_RESPONSE_TIMEOUT``.
+  ///
+  /// ESC-POS doesn't report the dimensions of stored graphics, so only the
+  /// keys are returned. The response format follows Epson's, other
+  /// manufacturers often implement only some of ``GS ( L`` and may never
+  /// answer, or answer with an empty list.
+  /// # Example
+  /// ```
+  /// for logo in printer.list_nv_logos()? {
+  ///   println!("{}{}", logo.key.0 as char, logo.key.1 as char);
+  /// }
+  /// ```
+  pub fn list_nv_logos(&mut self) -> Result<Vec<KeyInfo>, PrinterError> {
+    if !self.capabilities.status_readback {
+      return Err(PrinterError::Unsupported(
+        "listing NV graphics needs a printer that sends responses back".to_string()
+      ))
+    }
+    let mut reader = self.file_handle.get_ref().reader(&self.path)?;
+    // m, fn, "KC" selects the key code list
+    self.print_bytes(&[GS, b'(', b'L', 0x04, 0x00, 48, 64, b'K', b'C'])?;
+
+    let mut deadline = Instant::now() + NV_RESPONSE_TIMEOUT;
+    let mut logos: Vec<KeyInfo> = Vec::new();
+    let mut received: Vec<u8> = Vec::new();
+    let mut buf = [0u8; 64];
+    loop {
+      if Instant::now() >= deadline {
+        return Err(PrinterError::Timeout(
+          "the printer didn't send the NV graphics list".to_string()
+        ))
+      }
+      let read = reader.read(&mut buf)?;
+      if read == 0 {
+        std::thread::sleep(Duration::from_millis(50));
+        continue;
+      }
+      received.extend_from_slice(&buf[..read]);
+
+      // every block is 0x37 0x72, a status byte, pairs of key codes and a NUL
+      let start = match received.windows(2).position(|w| w == [0x37, 0x72]) {
+        Some(o) => o,
+        None => continue
+      };
+      let end = match received[start..].iter().position(|b| *b == 0x00) {
+        Some(o) => start + o,
+        None => continue
+      };
+      if end < start + 3 {
+        continue;
+      }
+      let status = received[start + 2];
+      for key in received[start + 3..end].chunks_exact(2) {
+        logos.push(KeyInfo { key: (key[0], key[1]) });
+      }
+      received.drain(..=end);
+      // 0x41 means there are more blocks, which are sent after an ACK
+      if status == 0x41 {
+        self.print_bytes(&[0x06])?;
+        deadline = Instant::now() + NV_RESPONSE_TIMEOUT;
+      } else {
+        return Ok(logos)
+      }
+    }
+  }
+
+  /// # About
+  /// Enables automatic status back with ``GS a``, making the printer send an
+  /// ``AsbStatus`` packet whenever one of the statuses selected in ``flags``
+  /// changes, and once right away. ``AsbFlags::default()`` turns it off.
+  ///
+  /// The packets are read with ``asb_events``, which needs a printer with
+  /// ``Capabilities::status_readback``.
+  /// # Example
+  /// ```
+  /// printer.enable_asb(AsbFlags::all())?;
+  /// for status in printer.asb_events()? {
+  ///   match status {
+  ///     Ok(status) if status.paper_near_end => println!("the paper is running out"),
+  ///     // a quiet printer on a device file, keep waiting
+  ///     Ok(_) | Err(PrinterError::Timeout(_)) => (),
+  ///     Err(e) => return Err(e)
+  ///   }
+  /// }
+  /// ```
+  pub fn enable_asb(&mut self, flags: AsbFlags) -> Result<(), PrinterError> {
+    let n = flags.drawer as u8
+      | (flags.online as u8) << 1
+      | (flags.error as u8) << 2
+      | (flags.paper as u8) << 3;
+    self.print_bytes(&[GS, b'a', n])?;
+    Ok(())
+  }
+
+  /// # About
+  /// Opens the printer's path for reading and returns the ASB packets it
+  /// sends, see ``enable_asb``.
+  ///
+  /// Needs ``Capabilities::status_readback``, otherwise it returns an
+  /// ``Unsupported`` error. Bytes that don't form a packet, such as the
+  /// answers to ``wait_complete``, are skipped, so don't read the other
+  /// responses while iterating.
+  pub fn asb_events(&self) -> Result<AsbEvents, PrinterError> {
+    if !self.capabilities.status_readback {
+      return Err(PrinterError::Unsupported(
+        "reading status packets needs a printer that sends responses back".to_string()
+      ))
+    }
+    let connection = self.file_handle.get_ref();
+    Ok(AsbEvents {
+      reader: connection.reader(&self.path)?,
+      received: Vec::new(),
+      ends_at_eof: connection.reads_end_at_eof(),
+      idle_timeout: ASB_IDLE_TIMEOUT
+    })
+  }
+
+  /// # About
+  /// Fully cuts the paper using ``GS V 0``, on printers that have a cutter.
+  pub fn cut_paper(&mut self) -> Result<(), PrinterError> {
+    self.cut(CutMode::Full)?;
+    Ok(())
+  }
+
+  /// # About
+  /// Cuts the paper right where it is using ``GS V 0`` or ``GS V 1``, on
+  /// printers that have a cutter.
+  ///
+  /// Everything sent before is written and flushed first. The last printed
+  /// line is usually still above the cutter, see ``feed_and_cut``.
+  /// # Example
+  /// ```
+  /// printer.println("Thank you!")?;
+  /// printer.cut(CutMode::Partial)?;
+  /// ```
+  pub fn cut(&mut self, mode: CutMode) -> Result<(), PrinterError> {
+    let mode_byte = match mode {
+      CutMode::Full => 0x00,
+      CutMode::Partial => 0x01
+    };
+    self.flush_buf()?;
+    self.print_bytes(&[GS, b'V', mode_byte])?;
+    Ok(())
+  }
+
+  /// # About
+  /// Feeds the paper by ``dots`` and cuts it with a single ``GS V``
+  /// function 65 (full) or 66 (partial) command, so the cut can't fire before
+  /// the feed is done.
+  ///
+  /// Only on printers with ``Capabilities::feed_cut``, the others may print
+  /// the parameters as text, use ``cut`` on those.
+  pub fn cut_paper_with_feed(&mut self, mode: CutMode, dots: u8) -> Result<(), PrinterError> {
+    let function = match mode {
+      CutMode::Full => 65,
+      CutMode::Partial => 66
+    };
+    self.flush_buf()?;
+    self.print_bytes(&[GS, b'V', function, dots])?;
+    Ok(())
+  }
+
+  /// # About
+  /// Feeds ``Capabilities::cut_feed_lines`` lines so the last printed line
+  /// gets past the cutter, then cuts the paper.
+  ///
+  /// With ``Capabilities::feed_cut`` both happen in one command, see
+  /// ``cut_paper_with_feed``, using the default line spacing of 1/6 inch.
+  /// Otherwise the lines are fed with ``ESC d`` and cut with ``cut``.
+  pub fn feed_and_cut(&mut self, mode: CutMode) -> Result<(), PrinterError> {
+    if self.capabilities.feed_cut {
+      let dots = self.capabilities.cut_feed_lines as usize * self.capabilities.dpi as usize / 6;
+      self.cut_paper_with_feed(mode, dots.min(u8::MAX as usize) as u8)?;
+      return Ok(())
+    }
+    self.print_bytes(&[ESC, b'd', self.capabilities.cut_feed_lines])?;
+    self.cut(mode)?;
+    Ok(())
+  }
+
+  /// # About
+  /// Selects the kind of paper loaded with the paper layout function of
+  /// ``FS ( L`` (function 33), so the printer uses the matching sensor to
+  /// find the start of each label.
+  ///
+  /// The setting is stored by the printer and survives turning it off. Load
+  /// a new roll in this order:
+  /// 1. call ``set_paper_type`` with the kind of paper loaded
+  /// 2. call ``calibrate_label_sensor`` so the sensor learns how the gaps or
+  ///    marks look on this roll
+  /// 3. print each label followed by ``feed_to_next_label``
+  ///
+  /// Needs ``Capabilities::label_sensor``, otherwise it returns an
+  /// ``Unsupported`` error, since continuous-only printers can print the
+  /// parameters as text.
+  pub fn set_paper_type(&mut self, paper: PaperType) -> Result<(), PrinterError> {
+    self.check_label_sensor()?;
+    let layout = match paper {
+      PaperType::Continuous => b'0',
+      PaperType::BlackMark => b'1',
+      PaperType::Gap => b'2'
+    };
+    self.print_bytes(&[FS, b'(', b'L', 0x02, 0x00, 33, layout])?;
+    Ok(())
+  }
+
+  /// # About
+  /// Feeds two labels so the printer's sensor measures the gaps or marks of
+  /// the loaded roll, then leaves the paper at the start of the next label.
+  /// Run it after loading a new roll, see ``set_paper_type``.
+  ///
+  /// Some printers only calibrate from their panel, holding the feed button
+  /// while turning them on, on those this just skips two labels.
+  /// # Errors
+  /// - if the printer doesn't have ``Capabilities::label_sensor``
+  pub fn calibrate_label_sensor(&mut self) -> Result<(), PrinterError> {
+    self.check_label_sensor()?;
+    for _ in 0..2 {
+      self.feed_to_next_label()?;
+    }
+    Ok(())
+  }
+
+  /// # About
+  /// Finishes the current label and feeds the paper to the print starting
+  /// position of the next one with ``FS ( L`` function 67, instead of
+  /// printing over the gap. Everything sent before is flushed first.
+  /// # Example
+  /// ```
+  /// for name in ["Flour", "Sugar", "Rice"] {
+  ///   printer.println(name)?;
+  ///   printer.feed_to_next_label()?;
+  /// }
+  /// ```
+  /// # Errors
+  /// - if the printer doesn't have ``Capabilities::label_sensor``
+  pub fn feed_to_next_label(&mut self) -> Result<(), PrinterError> {
+    self.check_label_sensor()?;
+    self.flush_buf()?;
+    self.print_bytes(&[FS, b'(', b'L', 0x02, 0x00, 67, 49])?;
+    Ok(())
+  }
+
+  fn check_label_sensor(&self) -> Result<(), PrinterError> {
+    if !self.capabilities.label_sensor {
+      return Err(PrinterError::Unsupported(
+        "label paper needs a printer with a gap or black mark sensor".to_string()
+      ))
+    }
+    Ok(())
+  }
+
+  /// # About
+  /// Opens a cash drawer connected to the printer by sending a pulse on
+  /// ``pin`` of its drawer kick-out connector with ``ESC p``.
+  ///
+  /// The pulse stays on for ``on_ms`` and is followed by ``off_ms`` off. The
+  /// printer counts both in steps of 2 ms, so they're sent as ``ms / 2`` and
+  /// odd values are rounded down. Printers extend the off time to match the
+  /// on time when it's shorter.
+  /// # Example
+  /// ```
+  /// printer.open_drawer(DrawerPin::Pin2, 100, 200)?;
+  /// ```
+  /// # Errors
+  /// - if ``on_ms`` is shorter than 2 ms, which doesn't move the drawer
+  pub fn open_drawer(&mut self, pin: DrawerPin, on_ms: u8, off_ms: u8) -> Result<(), PrinterError> {
+    let (on, off) = (on_ms / 2, off_ms / 2);
+    if on == 0 {
+      return Err(PrinterError::InvalidArgument(
+        format!("the drawer pulse must be at least 2 ms long, got {} ms", on_ms)
+      ))
+    }
+    let pin_byte = match pin {
+      DrawerPin::Pin2 => 0x00,
+      DrawerPin::Pin5 => 0x01
+    };
+    self.print_bytes(&[ESC, b'p', pin_byte, on, off])?;
+    Ok(())
+  }
+
+  /// # About
+  /// Sends ``ESC @`` and flushes, initializing the printer to the state it
+  /// powers on in.
+  ///
+  /// ``ESC @`` clears the text mode, emphasis, double strike, underline,
+  /// upside-down and rotated printing, selects black, font A and the default
+  /// code page, restores the default line spacing, character spacing, tab
+  /// stops, justification and left margin, leaves page mode and discards
+  /// whatever is still in the print buffer, including a half-sent bitmap. The
+  /// tracked settings reported by ``snapshot`` are reset to match, and
+  /// ``get_raster_progress`` goes back to 0, since there's nothing left to
+  /// resume.
+  ///
+  /// Settings kept in non-volatile memory persist across both ``ESC @`` and
+  /// power cycles: the NV graphics listed by ``list_nv_logos``, the memory
+  /// switches (default code page, print density, cutter and buzzer
+  /// settings) and the paper type set with ``set_paper_type``. Settings of the
+  /// connection itself, like the baud rate, aren't touched either.
+  /// # Example
+  /// ```
+  /// printer.reset()?;
+  /// ```
+  pub fn reset(&mut self) -> Result<(), PrinterError> {
+    self.print_bytes(&[ESC, b'@'])?;
+    self.justification = 0;
+    self.text_mode = 0;
+    self.double_strike = false;
+    self.color = Color::Black;
+    self.page_mode = false;
+    self.line_spacing = None;
+    self.font = Font::A;
+    self.code_page = None;
+    self.encoding = None;
+    self.upside_down = false;
+    self.tab_stops = None;
+    self.raster_progress = 0;
+    Ok(())
+  }
+
+  /// # About
+  /// Sends ``CAN``, which discards text sent since the last line feed that
+  /// hasn't been printed yet, handy to recover after a partial write.
+  ///
+  /// Unlike ``reset`` no settings are touched. Support varies between printers,
+  /// some only honor it in page mode and others ignore it entirely, and
+  /// anything already printed obviously stays on the paper.
+  pub fn cancel_line(&mut self) -> Result<(), PrinterError> {
+    self.print_bytes(&[CAN])?;
+    Ok(())
+  }
+
+  /// # About
+  /// 0: left
+  ///
+  /// 1: center
+  ///
+  /// 2: right
+  /// # Example
+  /// ```
+  /// printer.set_justification(1)?;
+  /// ```
+  pub fn set_justification(&mut self, value: u8) -> Result<(), PrinterError> {
+    self.print_bytes(&[ESC, 0x61, value])?;
+    self.justification = value;
+    Ok(())
+  }
+
+  /// # About
+  /// Sets the distance between the tops of consecutive lines of text to
+  /// ``dots`` with ``ESC 3``, to pack dense receipts tighter or give headers
+  /// more room.
+  ///
+  /// On most printers a dot is the vertical motion unit, 1/180" or 1/203"
+  /// (about 0.125 mm). Lines taller than the spacing, like double height
+  /// text, still get their full height. The default spacing is usually 30
+  /// dots, go back to it with ``reset_line_spacing``.
+  /// # Example
+  /// ```
+  /// printer.set_line_spacing(24)?;
+  /// ```
+  pub fn set_line_spacing(&mut self, dots: u8) -> Result<(), PrinterError> {
+    self.print_bytes(&[ESC, b'3', dots])?;
+    self.line_spacing = Some(dots);
+    Ok(())
+  }
+
+  /// # About
+  /// Selects one of the built-in fonts with ``ESC M``.
+  ///
+  /// Font B fits about a third more characters per line, the line width used
+  /// by the text layout helpers isn't changed, call ``set_line_width`` to
+  /// match, see ``Font`` for the usual widths.
+  /// # Example
+  /// ```
+  /// printer.set_font(Font::B)?;
+  /// printer.set_line_width(42);
+  /// ```
+  pub fn set_font(&mut self, font: Font) -> Result<(), PrinterError> {
+    self.print_bytes(&[ESC, b'M', font as u8])?;
+    self.font = font;
+    Ok(())
+  }
+
+  /// # About
+  /// Selects the character code table used for bytes 128 to 255 with
+  /// ``ESC t``.
+  ///
+  /// ``println`` and the other text methods transcode their text to the
+  /// matching encoding for pages 0, 2 and 16, see ``set_encoding``. With any
+  /// other page they send the bytes of their UTF-8 strings as they are, so
+  /// anything non-ASCII comes out garbled unless it's encoded for the page
+  /// first and sent with ``print_bytes``. The page numbers differ between
+  /// manufacturers, on Epson printers the most common ones are:
+  /// - 0: CP437, USA and standard Europe, the usual default
+  /// - 1: Katakana
+  /// - 2: CP850, multilingual Latin-1
+  /// - 16: WPC1252, Windows Latin-1
+  ///
+  /// There's no command to go back to the default page other than ``reset``,
+  /// it's kept in the printer's memory switches.
+  /// # Example
+  /// ```
+  /// printer.set_code_page(16)?;
+  /// // sent as "caf\xe9" in WPC1252
+  /// printer.println("café")?;
+  /// ```
+  pub fn set_code_page(&mut self, page: u8) -> Result<(), PrinterError> {
+    self.print_bytes(&[ESC, b't', page])?;
+    self.code_page = Some(page);
+    self.encoding = Encoding::for_code_page(page);
+    Ok(())
+  }
+
+  /// # About
+  /// Turns upside-down printing on or off with ``ESC {``, rotating every line
+  /// 180° for printers mounted upside down.
+  ///
+  /// The printer only takes the command at the beginning of a line, so set it
+  /// before the content, turning it on halfway through a line does nothing
+  /// until the next one. Each line is rotated on its own and lines still come
+  /// out in the order they're sent, so multi-line text reads right from the
+  /// other side only when its lines are sent last to first. Raster images and
+  /// graphics aren't rotated, use ``BitImage::rotate`` with ``Rotation::Cw180``
+  /// or ``ImageOptions::rotation`` for those.
+  /// # Example
+  /// ```
+  /// printer.set_upside_down(true)?;
+  /// for line in ["Thank you!", "TOTAL: $12.50"] {
+  ///   printer.println(line)?;
+  /// }
+  /// ```
+  pub fn set_upside_down(&mut self, on: bool) -> Result<(), PrinterError> {
+    self.print_bytes(&[ESC, b'{', on as u8])?;
+    self.upside_down = on;
+    Ok(())
+  }
+
+  /// # About
+  /// Sets the horizontal tab stops with ``ESC D``, as columns counted in
+  /// characters of the active font from the left margin. Every ``HT``
+  /// (``'\t'``) in the text sent afterwards, see ``print_with_tabs``, moves
+  /// the print position to the next stop.
+  ///
+  /// The printer starts with a stop every 8 characters, an empty slice
+  /// clears them all so tabs are ignored, and ``reset`` brings the default
+  /// back. Stops are kept in stateless mode but aren't part of
+  /// ``PrinterState``. Tabs past the last stop, or past the end of the line,
+  /// are ignored by the printer.
+  /// # Errors
+  /// Returns ``PrinterError::InvalidArgument`` without sending anything:
+  /// - if a stop is 0, which would end the list early
+  /// - if the stops aren't strictly increasing
+  /// - if there are more than 32 stops, the most ``ESC D`` takes
+  /// # Example
+  /// ```
+  /// printer.set_tab_stops(&[20, 26])?;
+  /// printer.print_with_tabs(&["Coffee", "2", "$5.00"])?;
+  /// ```
+  pub fn set_tab_stops(&mut self, stops: &[u8]) -> Result<(), PrinterError> {
+    if stops.len() > 32 {
+      return Err(PrinterError::InvalidArgument(format!(
+        "{} tab stops given, at most 32 can be set", stops.len()
+      )));
+    }
+    if stops.contains(&0) {
+      return Err(PrinterError::InvalidArgument("tab stops start at column 1".to_string()));
+    }
+    if let Some(pair) = stops.windows(2).find(|pair| pair[0] >= pair[1]) {
+      return Err(PrinterError::InvalidArgument(format!(
+        "tab stops must be strictly increasing, got {} before {}", pair[0], pair[1]
+      )));
+    }
+    let mut cmd: Vec<u8> = Vec::from([ESC, b'D']);
+    cmd.extend_from_slice(stops);
+    cmd.push(0);
+    self.print_bytes(&cmd)?;
+    self.tab_stops = Some(stops.to_vec());
+    Ok(())
+  }
+
+  /// # About
+  /// Prints ``cells`` on one line separated by ``HT``, so each one after the
+  /// first starts at the next tab stop, see ``set_tab_stops``.
+  ///
+  /// Unlike ``print_columns`` the alignment is left to the printer and cells
+  /// aren't padded or truncated, a cell reaching past its stop pushes the
+  /// next one to the stop after that.
+  pub fn print_with_tabs(&mut self, cells: &[&str]) -> Result<(), PrinterError> {
+    self.println(&cells.join("\t"))
+  }
+
+  /// # About
+  /// Goes back to the printer's default line spacing with ``ESC 2``, see
+  /// ``set_line_spacing``.
+  pub fn reset_line_spacing(&mut self) -> Result<(), PrinterError> {
+    self.print_bytes(&[ESC, b'2'])?;
+    self.line_spacing = None;
+    Ok(())
+  }
+
+  /// # About
+  /// Selects the color used for the following text with ``ESC r``, on
+  /// printers loaded with two-color (black and red) paper.
+  ///
+  /// Supported by two-color models like the Epson TM-T88 and TM-U220 family.
+  /// # Example
+  /// ```
+  /// printer.set_color(Color::Red)?;
+  /// printer.println("TOTAL: $12.50")?;
+  /// printer.set_color(Color::Black)?;
+  /// ```
+  /// # Errors
+  /// - ``PrinterError::Unsupported`` without ``Capabilities::two_color``,
+  ///   sending nothing
+  pub fn set_color(&mut self, color: Color) -> Result<(), PrinterError> {
+    if !self.capabilities.two_color {
+      return Err(PrinterError::Unsupported(
+        "colors need a printer with two-color paper".to_string()
+      ))
+    }
+    let n = match color {
+      Color::Black => 0,
+      Color::Red => 1
+    };
+    self.print_bytes(&[ESC, b'r', n])?;
+    self.color = color;
+    Ok(())
+  }
+
+  /// # About
+  /// Selects the print speed using ``GS ( K`` function 50.
+  ///
+  /// ``level`` goes from 1 (slowest, darkest and least prone to smearing) to 9
+  /// (fastest), values out of that range are clamped.
+  /// # Example
+  /// ```
+  /// printer.set_print_speed(3)?;
+  /// ```
+  /// # Errors
+  /// - ``PrinterError::Unsupported`` without ``Capabilities::print_speed``,
+  ///   sending nothing
+  pub fn set_print_speed(&mut self, level: u8) -> Result<(), PrinterError> {
+    if !self.capabilities.print_speed {
+      return Err(PrinterError::Unsupported(
+        "this printer can't select the print speed".to_string()
+      ))
+    }
+    self.print_bytes(&[GS, b'(', b'K', 0x02, 0x00, 50, level.clamp(1, 9)])?;
+    Ok(())
+  }
+
+  /// # About
+  /// Turns double-strike mode on or off with ``ESC G``.
+  ///
+  /// Every line of dots is printed twice, darkening faint text without the
+  /// smearing that higher heat settings cause. Unlike bold (emphasized mode,
+  /// see ``set_text_mode``), it doesn't make the strokes any wider.
+  pub fn set_double_strike(&mut self, on: bool) -> Result<(), PrinterError> {
+    self.print_bytes(&[ESC, b'G', on as u8])?;
+    self.double_strike = on;
+    Ok(())
+  }
+
+  pub fn is_double_strike(&self) -> bool {
+    self.double_strike
+  }
+
+  /// # About
+  /// Forces every character to take the same width, so the column based
+  /// layouts (``print_kv``, ``print_grid`` and friends) line up.
+  ///
+  /// The ESC-POS fonts A and B are both fixed pitch, only vendor-specific
+  /// fonts selected with ``ESC M`` values above 1 (the proportional fonts of
+  /// some Star and Citizen models, or the special fonts 97 and 98) space
+  /// characters by their width. Turning it on selects font A with ``ESC M 0``
+  /// and clears any extra spacing set with ``ESC SP``. There's no standard
+  /// command to go back, turning it off only stops it from being restored in
+  /// stateless mode, select the proportional font again with ``print_bytes``.
+  pub fn set_fixed_pitch(&mut self, on: bool) -> Result<(), PrinterError> {
+    if on {
+      self.print_bytes(&[ESC, b'M', 0, ESC, b' ', 0])?;
+      self.font = Font::A;
+    }
+    self.fixed_pitch = on;
+    Ok(())
+  }
+
+  pub fn is_fixed_pitch(&self) -> bool {
+    self.fixed_pitch
+  }
+
+  pub fn set_text_mode(
+    &mut self,
+    double_width: bool,
+    double_height: bool,
+    bold: bool,
+    underline: bool
+  ) -> Result<(), PrinterError> {
+    let mut msg: Vec<u8> = Vec::from([ESC, b'!']);
+    let mut settings: u8 = 0;
+    if double_width {
+      settings |= 0b00100000;
+    }
+    if double_height {
+      settings |= 0b00010000;
+    }
+    if bold {
+      settings |= 0b00001000;
+    }
+    if underline {
+      settings |= 0b10000000;
+    }
+    msg.push(settings);
+    self.write_vec(&msg)?;
+    self.flush_buf()?;
+    self.text_mode = settings;
+    Ok(())
+  }
+
+  /// # About
+  /// The tracked settings, to put back later with ``restore``.
+  /// # Example
+  /// ```
+  /// let state = printer.snapshot();
+  /// printer.set_justification(1)?;
+  /// printer.set_text_mode(true, true, true, false)?;
+  /// printer.println("SALE")?;
+  /// printer.restore(&state)?;
+  /// ```
+  pub fn snapshot(&self) -> PrinterState {
+    PrinterState {
+      justification: self.justification,
+      text_mode: self.text_mode,
+      double_strike: self.double_strike,
+      color: self.color,
+      fixed_pitch: self.fixed_pitch,
+      line_spacing: self.line_spacing,
+      font: self.font,
+      code_page: self.code_page,
+      upside_down: self.upside_down
+    }
+  }
+
+  /// # About
+  /// Puts back the settings of a ``snapshot``, only sending the commands for
+  /// the ones that changed since.
+  ///
+  /// Turning ``fixed_pitch`` off can't be sent, see ``set_fixed_pitch``, and
+  /// neither can going back to the default code page, see ``set_code_page``.
+  pub fn restore(&mut self, state: &PrinterState) -> Result<(), PrinterError> {
+    if state.justification != self.justification {
+      self.set_justification(state.justification)?;
+    }
+    if state.text_mode != self.text_mode {
+      self.print_bytes(&[ESC, b'!', state.text_mode])?;
+      self.text_mode = state.text_mode;
+    }
+    if state.double_strike != self.double_strike {
+      self.set_double_strike(state.double_strike)?;
+    }
+    if state.color != self.color {
+      self.set_color(state.color)?;
+    }
+    if state.fixed_pitch != self.fixed_pitch {
+      self.set_fixed_pitch(state.fixed_pitch)?;
+    }
+    if state.line_spacing != self.line_spacing {
+      match state.line_spacing {
+        Some(dots) => self.set_line_spacing(dots),
+        None => self.reset_line_spacing()
+      }?;
+    }
+    if state.font != self.font {
+      self.set_font(state.font)?;
+    }
+    if let Some(page) = state.code_page.filter(|_| state.code_page != self.code_page) {
+      self.set_code_page(page)?;
+    }
+    if state.upside_down != self.upside_down {
+      self.set_upside_down(state.upside_down)?;
+    }
+    Ok(())
+  }
+
+  /// # About
+  /// In stateless mode every ``println`` starts with ``ESC @`` followed by the
+  /// justification, text mode, double-strike and color last set through this
+  /// struct, so each line prints the same no matter what other programs
+  /// sharing the printer sent in between. Fixed pitch, line spacing, font,
+  /// code page, upside-down printing and tab stops are restored too, see
+  /// ``set_fixed_pitch``, ``set_line_spacing``, ``set_font``, ``set_code_page``,
+  /// ``set_upside_down`` and ``set_tab_stops``.
+  ///
+  /// This costs 11 extra bytes per line, 3 more on two-color printers, 6
+  /// more with fixed pitch forced, 3 more for each of the line spacing,
+  /// font B, code page and upside-down printing when set and 3 more plus one
+  /// per stop when tab stops are set. Settings sent with
+  /// ``print_bytes`` aren't tracked and are lost on every line.
+  pub fn set_stateless(&mut self, stateless: bool) {
+    self.stateless = stateless;
+  }
+
+  /// # About
+  /// Initializes the printer and sends the tracked modes again, see
+  /// ``set_stateless``.
+  fn restore_modes(&mut self) -> Result<(), PrinterError> {
+    let mut cmd: Vec<u8> = Vec::from([
+      ESC, b'@',
+      ESC, b'a', self.justification,
+      ESC, b'!', self.text_mode,
+      ESC, b'G', self.double_strike as u8
+    ]);
+    if self.capabilities.two_color {
+      cmd.extend_from_slice(&[ESC, b'r', self.color as u8]);
+    }
+    if self.fixed_pitch {
+      cmd.extend_from_slice(&[ESC, b'M', 0, ESC, b' ', 0]);
+    }
+    if let Some(dots) = self.line_spacing {
+      cmd.extend_from_slice(&[ESC, b'3', dots]);
+    }
+    if self.font != Font::A {
+      cmd.extend_from_slice(&[ESC, b'M', self.font as u8]);
+    }
+    if let Some(page) = self.code_page {
+      cmd.extend_from_slice(&[ESC, b't', page]);
+    }
+    if self.upside_down {
+      cmd.extend_from_slice(&[ESC, b'{', 1]);
+    }
+    if let Some(stops) = &self.tab_stops {
+      cmd.extend_from_slice(&[ESC, b'D']);
+      cmd.extend_from_slice(stops);
+      cmd.push(0);
+    }
+    self.write_vec(&cmd)?;
+    Ok(())
+  }
+
+  /// # About
+  /// Prints a block of key-value pairs with all values aligned on a common
+  /// column, computed from the longest key.
+  ///
+  /// Values too long for the line are wrapped and indented to the same column.
+  /// # Example
+  /// ```
+  /// printer.print_kv(&[
+  ///   ("Speed".to_string(), "9600 baud".to_string()),
+  ///   ("Paper width".to_string(), "58mm".to_string()),
+  /// ])?;
+  /// // Speed:       9600 baud
+  /// // Paper width: 58mm
+  /// ```
+  pub fn print_kv(&mut self, pairs: &[(String, String)]) -> Result<(), PrinterError> {
+    let longest_key = pairs.iter().map(|(k, _)| k.chars().count()).max().unwrap_or(0);
+    // keys can take at most half of the line, the rest is left for the values
+    let column = (longest_key + 2).min(self.line_width / 2).max(1);
+    let value_width = self.line_width.saturating_sub(column).max(1);
+
+    for (key, value) in pairs {
+      let label = format!("{}:", key);
+      let mut values = wrap_text(value, value_width).into_iter();
+      if label.chars().count() >= column {
+        self.println(&label)?;
+      } else {
+        let first = values.next().unwrap_or_default();
+        self.println(&format!("{:<width$}{}", label, first, width = column))?;
+      }
+      for line in values {
+        self.println(&format!("{:width$}{}", "", line, width = column))?;
+      }
+    }
+    Ok(())
+  }
+
+  /// # About
+  /// Sets how ``print_total`` formats amounts, see ``TotalOptions``.
+  pub fn set_total_options(&mut self, options: TotalOptions) {
+    self.total_options = options;
+  }
+
+  /// # About
+  /// Prints the total line of a receipt, with ``label`` on the left and
+  /// ``amount`` rounded to cents and formatted with ``currency`` on the right,
+  /// see ``format_amount``. Labels too long to share the line get their own.
+  ///
+  /// The line is emphasized as set with ``set_total_options``, in bold by
+  /// default. ``currency`` goes through the same filtering as ``println``,
+  /// symbols like ``€`` only print right if the printer's code page has them.
+  /// Reverse printing isn't restored in stateless mode.
+  /// # Example
+  /// ```
+  /// printer.print_total("TOTAL", 1234.5, "$")?;
+  /// // TOTAL                  $1,234.50
+  /// ```
+  pub fn print_total(&mut self, label: &str, amount: f64, currency: &str) -> Result<(), PrinterError> {
+    let options = self.total_options;
+    let amount = format_amount(amount, currency, &options);
+    let (label_len, amount_len) = (label.chars().count(), amount.chars().count());
+
+    let text_mode = self.text_mode;
+    if options.bold {
+      self.print_bytes(&[ESC, b'!', text_mode | 0b00001000])?;
+      self.text_mode = text_mode | 0b00001000;
+    }
+    if options.reverse {
+      self.print_bytes(&[GS, b'B', 1])?;
+    }
+    if label_len + 1 + amount_len > self.line_width {
+      self.println(label)?;
+      self.println(&format!("{:>width$}", amount, width = self.line_width))?;
+    } else {
+      let padding = self.line_width - label_len - amount_len;
+      self.println(&format!("{}{:padding$}{}", label, "", amount, padding = padding))?;
+    }
+    if options.reverse {
+      self.print_bytes(&[GS, b'B', 0])?;
+    }
+    if options.bold {
+      self.print_bytes(&[ESC, b'!', text_mode])?;
+      self.text_mode = text_mode;
+    }
+    Ok(())
+  }
+
+  /// # About
+  /// Prints ``text`` word wrapped to the line width, with the spaces between
+  /// words widened so that every line but the last of each paragraph reaches
+  /// both edges of the paper.
+  ///
+  /// Paragraphs are separated by newlines.
+  /// # Examples
+  /// ```
+  /// printer.print_justified("Items can be returned within 30 days of purchase with this receipt.")?;
+  /// ```
+  pub fn print_justified(&mut self, text: &str) -> Result<(), PrinterError> {
+    for paragraph in text.split('\n') {
+      let lines = wrap_text(paragraph, self.line_width);
+      let last = lines.len() - 1;
+      for (i, line) in lines.iter().enumerate() {
+        if i == last {
+          self.println(line)?;
+        } else {
+          self.println(&justify_line(line, self.line_width))?;
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// # About
+  /// Prints the given lines centered inside a border drawn with the box
+  /// drawing characters from code page 437, the default on most printers.
+  ///
+  /// The box is as wide as the longest line, up to the line width, and lines
+  /// that don't fit inside it are wrapped.
+  /// # Example
+  /// ```
+  /// printer.print_boxed(&["KEEP THIS RECEIPT", "for returns"])?;
+  /// ```
+  pub fn print_boxed(&mut self, lines: &[&str]) -> Result<(), PrinterError> {
+    // CP437 double line box drawing characters
+    const TOP_LEFT: u8 = 0xc9;
+    const TOP_RIGHT: u8 = 0xbb;
+    const BOTTOM_LEFT: u8 = 0xc8;
+    const BOTTOM_RIGHT: u8 = 0xbc;
+    const HORIZONTAL: u8 = 0xcd;
+    const VERTICAL: u8 = 0xba;
+
+    // one border and one space of padding on each side
+    let max_inner = self.line_width.saturating_sub(4).max(1);
+    let lines: Vec<Cow<str>> = lines.iter().map(|l| self.filter_text(l)).collect();
+    let longest = lines.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+    let inner = longest.clamp(1, max_inner);
+    let wrapped: Vec<String> = lines.iter().flat_map(|l| wrap_text(l, inner)).collect();
+
+    let mut border: Vec<u8> = vec![HORIZONTAL; inner + 2];
+    border.insert(0, TOP_LEFT);
+    border.push(TOP_RIGHT);
+    self.write_vec(&border)?;
+    self.line_feed()?;
+
+    for line in wrapped {
+      let padding = inner - line.chars().count();
+      let left = padding / 2;
+      let mut row: Vec<u8> = Vec::with_capacity(inner + 4);
+      row.push(VERTICAL);
+      row.extend(std::iter::repeat_n(b' ', left + 1));
+      row.extend_from_slice(&self.encode_text(&line));
+      row.extend(std::iter::repeat_n(b' ', padding - left + 1));
+      row.push(VERTICAL);
+      self.write_vec(&row)?;
+      self.line_feed()?;
+    }
+
+    border[0] = BOTTOM_LEFT;
+    *border.last_mut().unwrap() = BOTTOM_RIGHT;
+    self.write_vec(&border)?;
+    self.line_feed()?;
+    self.flush_buf()?;
+    Ok(())
+  }
+
+  /// # About
+  /// Switches to page mode with ``ESC L``, where everything sent is laid out
+  /// on a page in memory instead of being printed right away, and positions
+  /// can be set freely with ``set_page_position``.
+  ///
+  /// Nothing is printed until ``print_page`` is called.
+  /// # Example
+  /// ```
+  /// printer.enter_page_mode()?;
+  /// printer.set_page_area(0, 0, 384, 200)?;
+  /// printer.set_page_position(200, 24)?;
+  /// printer.print_bytes(b"SEAT 12A")?;
+  /// printer.print_page()?;
+  /// ```
+  pub fn enter_page_mode(&mut self) -> Result<(), PrinterError> {
+    self.print_bytes(&[ESC, b'L'])?;
+    self.page_mode = true;
+    Ok(())
+  }
+
+  /// # About
+  /// Goes back to standard mode with ``ESC S``, discarding the page if it
+  /// wasn't printed.
+  pub fn exit_page_mode(&mut self) -> Result<(), PrinterError> {
+    self.print_bytes(&[ESC, b'S'])?;
+    self.page_mode = false;
+    Ok(())
+  }
+
+  /// # About
+  /// Prints the page laid out since ``enter_page_mode`` and goes back to
+  /// standard mode. Does nothing outside of page mode.
+  pub fn print_page(&mut self) -> Result<(), PrinterError> {
+    if !self.page_mode {
+      return Ok(())
+    }
+    self.form_feed()?;
+    self.page_mode = false;
+    Ok(())
+  }
+
+  /// # About
+  /// Sets the printable area of the page with ``ESC W``, in dots.
+  ///
+  /// # Errors
+  /// - if the area is empty or wider than ``Capabilities::print_width``
+  pub fn set_page_area(&mut self, x: u16, y: u16, width: u16, height: u16) -> Result<(), PrinterError> {
+    if width == 0 || height == 0 || x as u32 + width as u32 > self.capabilities.print_width as u32 {
+      return Err(PrinterError::OutOfBounds(
+        format!("page area {}x{} at {}, {} doesn't fit the printer", width, height, x, y)
+      ))
+    }
+    let mut cmd: Vec<u8> = Vec::from([ESC, b'W']);
+    for value in [x, y, width, height] {
+      cmd.extend_from_slice(&self.to_two_byte(value));
+    }
+    self.print_bytes(&cmd)?;
+    Ok(())
+  }
+
+  /// # About
+  /// Moves the print position to ``x``, ``y`` dots from the top-left of the
+  /// page area using ``ESC $`` and ``GS $``. Only works in page mode.
+  pub fn set_page_position(&mut self, x: u16, y: u16) -> Result<(), PrinterError> {
+    let mut cmd: Vec<u8> = Vec::from([ESC, b'$']);
+    cmd.extend_from_slice(&self.to_two_byte(x));
+    cmd.extend_from_slice(&[GS, b'$']);
+    cmd.extend_from_slice(&self.to_two_byte(y));
+    self.print_bytes(&cmd)?;
+    Ok(())
+  }
+
+  /// # About
+  /// Prints one row of fixed width cells, each padded to its width in
+  /// characters and aligned on its own, so the row is always exactly as wide
+  /// as the sum of ``widths``.
+  ///
+  /// Cells longer than their width are wrapped, making the row take more
+  /// than one line. Missing alignments default to ``Align::Left``.
+  /// # Example
+  /// ```
+  /// let widths = [4, 20, 8];
+  /// let aligns = [Align::Right, Align::Left, Align::Right];
+  /// printer.print_grid(&["QTY", "ITEM", "PRICE"], &widths, &aligns)?;
+  /// printer.print_grid(&["2", "Coffee", "$5.00"], &widths, &aligns)?;
+  /// ```
+  pub fn print_grid(&mut self, cells: &[&str], widths: &[usize], aligns: &[Align]) -> Result<(), PrinterError> {
+    let wrapped: Vec<Vec<String>> = widths.iter()
+      .enumerate()
+      .map(|(i, width)| wrap_text(cells.get(i).copied().unwrap_or(""), *width))
+      .collect();
+    let lines = wrapped.iter().map(|cell| cell.len()).max().unwrap_or(0);
+
+    for line in 0..lines {
+      let mut row = String::new();
+      for (i, width) in widths.iter().enumerate() {
+        let text = wrapped[i].get(line).map(|t| t.as_str()).unwrap_or("");
+        let align = aligns.get(i).copied().unwrap_or_default();
+        row.push_str(&align_text(text, *width, align));
+      }
+      self.println(&row)?;
+    }
+    Ok(())
+  }
+
+  /// # About
+  /// How many characters of the active font fit in ``Capabilities::print_width``,
+  /// halved when double width is on, see ``Font::char_width``.
+  ///
+  /// Unlike ``get_line_width`` this follows ``set_font`` and ``set_text_mode``
+  /// on its own.
+  pub fn columns_per_line(&self) -> usize {
+    let mut char_width = self.font.char_width();
+    if self.text_mode & 0b00100000 != 0 {
+      char_width *= 2;
+    }
+    (self.capabilities.print_width / char_width).max(1) as usize
+  }
+
+  /// # About
+  /// Prints a single line of cells, each padded or truncated to its width in
+  /// characters and aligned on its own, like a ``print_grid`` row that never
+  /// wraps.
+  ///
+  /// The line is cut at ``columns_per_line`` for the active font, so columns
+  /// past the edge of the paper are dropped instead of spilling into the next
+  /// line. Missing cells are left blank and cells without a width are ignored.
+  /// # Example
+  /// ```
+  /// let widths = [24, 8];
+  /// printer.print_columns(&[
+  ///   ("Coffee beans 1kg".to_string(), Align::Left),
+  ///   ("$18.00".to_string(), Align::Right),
+  /// ], &widths)?;
+  /// // Coffee beans 1kg          $18.00
+  /// ```
+  pub fn print_columns(&mut self, columns: &[(String, Align)], widths: &[usize]) -> Result<(), PrinterError> {
+    let mut row = String::new();
+    for (i, width) in widths.iter().enumerate() {
+      let (text, align) = columns.get(i)
+        .map(|(text, align)| (text.as_str(), *align))
+        .unwrap_or_default();
+      row.push_str(&align_text(text, *width, align));
+    }
+    let row: String = row.chars().take(self.columns_per_line()).collect();
+    self.println(&row)?;
+    Ok(())
+  }
+
+  /// # About
+  /// Checks whether a QR code holding ``data_len`` bytes, printed with module
+  /// size ``size``, fits in ``Capabilities::print_width``.
+  ///
+  /// Assumes byte mode and error correction level L, the printer's default,
+  /// and doesn't count the quiet zone on the sides, which is left to the paper
+  /// margins, see ``set_qr_quiet_zone``.
+  /// Returns ``false`` if the data doesn't fit in a QR code at all.
+  /// # Example
+  /// ```
+  /// let url = b"https://example.com/receipts/000123";
+  /// if printer.qr_fits(url.len(), 8) {
+  ///   printer.print_qr_code(8, QrEcc::L, url)?;
+  /// }
+  /// ```
+  pub fn qr_fits(&self, data_len: usize, size: u8) -> bool {
+    match qr_modules(data_len) {
+      Some(modules) => modules * size as usize <= self.capabilities.print_width as usize,
+      None => false
+    }
+  }
+
+  /// # About
+  /// The biggest module size, up to 16, with which a QR code holding
+  /// ``data_len`` bytes still fits in the printer, see ``qr_fits``.
+  pub fn qr_max_size(&self, data_len: usize) -> Option<u8> {
+    (1..=16).rev().find(|size| self.qr_fits(data_len, *size))
+  }
+
+  /// # About
+  /// By default ``print_qr_code`` refuses module sizes that would print
+  /// modules smaller than ``MIN_QR_MODULE_MM`` at the printer's dpi, since
+  /// most scanners can't read them. This allows them anyway.
+  pub fn set_allow_small_qr(&mut self, allow: bool) {
+    self.allow_small_qr = allow;
+  }
+
+  pub fn get_allow_small_qr(&self) -> bool {
+    self.allow_small_qr
+  }
+
+  /// # About
+  /// Sets how many modules of blank paper ``print_qr_code`` feeds above and
+  /// below QR codes, ``QR_QUIET_ZONE`` by default as the specification asks.
+  /// Less saves paper but some scanners need the full quiet zone, so values
+  /// below it log a warning, more helps finicky scanners.
+  ///
+  /// The printer doesn't leave any margin around the symbol itself. The
+  /// sides are left to the justification and the paper margins, center QR
+  /// codes that are nearly as wide as the paper.
+  pub fn set_qr_quiet_zone(&mut self, modules: u8) {
+    if modules < QR_QUIET_ZONE {
+      log::warn!(
+        "QR quiet zone of {} modules is below the recommended {}, some scanners may not read the codes",
+        modules, QR_QUIET_ZONE
+      );
+    }
+    self.qr_quiet_zone = modules;
+  }
+
+  pub fn get_qr_quiet_zone(&self) -> u8 {
+    self.qr_quiet_zone
+  }
+
+  /// # About
+  /// Prints a QR code as close as possible to ``size_mm`` millimeters wide,
+  /// not counting the quiet zone, picking the module size from the amount of
+  /// data and ``Capabilities::dpi``. Returns the width that was printed, in
+  /// millimeters.
+  ///
+  /// Only whole dots can be used for each module, so the result is rounded.
+  /// The size is also limited to what fits in ``Capabilities::print_width``
+  /// and raised like in ``print_qr_code`` when the modules would be too small
+  /// to scan.
+  /// # Example
+  /// ```
+  /// let printed = printer.print_qr_mm(b"https://example.com", 30.0)?;
+  /// ```
+  /// # Errors
+  /// - if ``data`` doesn't fit in a QR code
+  pub fn print_qr_mm(&mut self, data: &[u8], size_mm: f32) -> Result<f32, PrinterError> {
+    let modules = match qr_modules(data.len()) {
+      Some(o) => o,
+      None => return Err(PrinterError::InvalidQrSize(format!("{} bytes don't fit in a QR code", data.len())))
+    };
+    let max_size = self.qr_max_size(data.len()).unwrap_or(1);
+    let mut size = qr_module_dots(size_mm, modules, self.capabilities.dpi).min(max_size);
+    if !self.allow_small_qr {
+      size = size.max(self.min_qr_size());
+    }
+    self.print_qr_code(size, QrEcc::L, data)?;
+    Ok((modules * size as usize) as f32 / self.capabilities.dpi as f32 * 25.4)
+  }
+
+  /// # About
+  /// Smallest module size that prints modules of at least
+  /// ``MIN_QR_MODULE_MM`` at the printer's dpi.
+  pub fn min_qr_size(&self) -> u8 {
+    let dots = MIN_QR_MODULE_MM * self.capabilities.dpi as f32 / 25.4;
+    (dots.ceil() as u8).max(1)
+  }
+
+  /// # About
+  /// Prints a QR code with the given module size in dots, 1 to 16, and error
+  /// correction level, see ``QrEcc``.
+  ///
+  /// Sizes below ``min_qr_size()`` are refused unless
+  /// ``set_allow_small_qr(true)`` was called, compare with it first to raise
+  /// them instead. The quiet zone set with ``set_qr_quiet_zone`` is fed
+  /// before and after the code with ``ESC J``. ``qr_fits`` and ``print_qr_mm``
+  /// assume level L, codes with higher levels need more modules for the same
+  /// data.
+  /// # Example
+  /// ```
+  /// printer.print_qr_code(8, QrEcc::Q, b"https://example.com/returns")?;
+  /// ```
+  /// # Errors
+  /// Returns an error without sending anything:
+  /// - ``PrinterError::InvalidQrSize`` if ``size`` is outside of 1 to 16, or
+  ///   below ``min_qr_size()`` when small codes aren't allowed
+  /// - ``PrinterError::TooLarge`` if ``data`` is too long for the two-byte
+  ///   length of ``GS ( k``, more than ``QR_MAX_DATA_LEN`` bytes
+  pub fn print_qr_code(&mut self, size: u8, ecc: QrEcc, data: &[u8]) -> Result<(), PrinterError> {
+    if !(1..=16).contains(&size) {
+      return Err(PrinterError::InvalidQrSize(format!("module size {} is outside of 1 to 16", size)));
+    }
+    let len = checked_u16(data.len() + 3, "QR code data length")?;
+    if !self.allow_small_qr && size < self.min_qr_size() {
+      return Err(PrinterError::InvalidQrSize(format!(
+        "modules of {} dots are too small to scan reliably, use at least {} or allow small QR codes",
+        size, self.min_qr_size()
+      )));
+    }
+
+    // ESC J feeds at most 255 dots at a time
+    let mut quiet_zone: Vec<u8> = Vec::new();
+    let mut dots = self.qr_quiet_zone as usize * size as usize;
+    while dots > 0 {
+      let feed = dots.min(255);
+      quiet_zone.extend_from_slice(&[ESC, b'J', feed as u8]);
+      dots -= feed;
+    }
+
+    let mut cmd: Vec<u8> = quiet_zone.clone();
+    cmd.extend_from_slice(&[
+      GS, 0x28, 0x6b, 0x03, 0x00, 0x31, 0x43, size,
+      GS, 0x28, 0x6b, 0x03, 0x00, 0x31, 0x45, ecc as u8
+    ]);
+    self.print_bytes(&cmd)?;
+
+    let mut cmd: Vec<u8> = Vec::from([GS, 0x28, 0x6b]);
+    cmd.extend_from_slice(&self.to_two_byte(len));
+    cmd.extend_from_slice(&[0x31, 0x50, 0x30]);
+    cmd.extend_from_slice(data);
+    // prints the stored symbol
+    cmd.extend_from_slice(&[GS, 0x28, 0x6b, 0x03, 0x00, 0x31, 0x51, 0x30]);
+    cmd.extend_from_slice(&quiet_zone);
+
+    self.write_vec(&cmd)?;
+    self.flush_buf()?;
+    Ok(())
+  }
+
+  /// # About
+  /// Prints a QR code that joins the given WiFi network when scanned.
+  ///
+  /// ``password`` is ignored for ``WifiSecurity::Open`` networks.
+  /// # Examples
+  /// ```
+  /// printer.print_qr_wifi(8, "Cafe Guest", "espresso123", WifiSecurity::Wpa)?;
+  /// ```
+  pub fn print_qr_wifi(&mut self, size: u8, ssid: &str, password: &str, security: WifiSecurity) -> Result<(), PrinterError> {
+    self.print_qr_code(size, QrEcc::default(), wifi_payload(ssid, password, security).as_bytes())?;
+    Ok(())
+  }
+
+  /// # About
+  /// Prints a QR code holding a vCard contact, fields that are ``None`` are
+  /// left out.
+  /// # Examples
+  /// ```
+  /// printer.print_qr_vcard(6, "Julia", Some("+55 11 5555-5555"), None)?;
+  /// ```
+  pub fn print_qr_vcard(&mut self, size: u8, name: &str, phone: Option<&str>, email: Option<&str>) -> Result<(), PrinterError> {
+    self.print_qr_code(size, QrEcc::default(), vcard_payload(name, phone, email).as_bytes())?;
+    Ok(())
+  }
+
+  /// # About
+  /// Prints a QR code that opens the given coordinates in a maps app.
+  /// # Examples
+  /// ```
+  /// printer.print_qr_geo(8, -23.5505, -46.6333)?;
+  /// ```
+  pub fn print_qr_geo(&mut self, size: u8, lat: f64, lon: f64) -> Result<(), PrinterError> {
+    self.print_qr_code(size, QrEcc::default(), geo_payload(lat, lon).as_bytes())?;
+    Ok(())
+  }
+
+  /// # About
+  /// Prints a PDF417 code, the stacked 2D barcode used on shipping labels and
+  /// boarding passes, with ``GS ( k`` functions 65 to 81 of symbol type 48.
+  ///
+  /// ``columns`` is the number of data columns, 1 to 30, or 0 to let the
+  /// printer pick it from the amount of data. ``ecc_level`` is the error
+  /// correction level, 0 to 8, each level doubling the codewords spent on
+  /// recovery. ``PDF417_DEFAULT_COLUMNS`` and ``PDF417_DEFAULT_ECC`` suit most
+  /// labels. Modules are kept at the printer's default of 3 dots wide and 3
+  /// times as tall.
+  /// # Example
+  /// ```
+  /// printer.print_pdf417(PDF417_DEFAULT_COLUMNS, PDF417_DEFAULT_ECC, b"1Z999AA10123456784")?;
+  /// ```
+  /// # Errors
+  /// Returns an error without sending anything:
+  /// - ``PrinterError::InvalidArgument`` if ``columns`` or ``ecc_level`` is out
+  ///   of range, or ``data`` is empty
+  /// - ``PrinterError::TooLarge`` if ``data`` is too long for the two-byte
+  ///   length of ``GS ( k``
+  pub fn print_pdf417(&mut self, columns: u8, ecc_level: u8, data: &[u8]) -> Result<(), PrinterError> {
+    if columns > 30 {
+      return Err(PrinterError::InvalidArgument(format!("{} PDF417 columns is outside of 0 to 30", columns)));
+    }
+    if ecc_level > 8 {
+      return Err(PrinterError::InvalidArgument(format!("PDF417 error correction level {} is outside of 0 to 8", ecc_level)));
+    }
+    if data.is_empty() {
+      return Err(PrinterError::InvalidArgument("a PDF417 code needs some data".to_string()));
+    }
+    let len = checked_u16(data.len() + 3, "PDF417 data length")?;
+
+    let mut cmd: Vec<u8> = Vec::from([
+      GS, 0x28, 0x6b, 0x03, 0x00, 0x30, 0x41, columns,
+      // error correction by level rather than by ratio
+      GS, 0x28, 0x6b, 0x04, 0x00, 0x30, 0x45, 0x30, 0x30 + ecc_level,
+      GS, 0x28, 0x6b
+    ]);
+    cmd.extend_from_slice(&self.to_two_byte(len));
+    cmd.extend_from_slice(&[0x30, 0x50, 0x30]);
+    cmd.extend_from_slice(data);
+    // prints the stored symbol
+    cmd.extend_from_slice(&[GS, 0x28, 0x6b, 0x03, 0x00, 0x30, 0x51, 0x30]);
+
+    self.write_vec(&cmd)?;
+    self.flush_buf()?;
+    Ok(())
+  }
+
+  /// # About
+  /// Sets the size of the barcodes printed with ``print_barcode``, ``width``
+  /// being the width of the narrowest bar in dots (2-6) and ``height`` the
+  /// height of the bars in dots (1-255).
+  /// # Errors
+  /// - if either value is out of range
+  pub fn set_barcode_size(&mut self, width: u8, height: u8) -> Result<(), PrinterError> {
+    if !(2..=6).contains(&width) || height == 0 {
+      return Err(PrinterError::InvalidArgument(
+        format!("barcode size {}x{} is out of range", width, height)
+      ))
+    }
+    self.barcode_width = width;
+    self.barcode_height = height;
+    Ok(())
+  }
+
+  /// # About
+  /// Prints a 1D barcode using the "GS k" ESC-POS command, with the encoded
+  /// text printed below it.
+  ///
+  /// For the numeric symbologies the check digit can be left out, the
+  /// printer adds it. Code128 data is printed as written, see
+  /// ``Barcode::Code128`` for picking the code sets by hand.
+  /// # Examples
+  /// ```
+  /// printer.print_barcode(Barcode::Ean13, b"400638133393")?;
+  /// printer.print_barcode(Barcode::Code128, b"ORDER-1234")?;
+  /// ```
+  /// # Errors
+  /// - if ``data`` can't be encoded with ``symbology``
+  /// - if the check digit is wrong
+  pub fn print_barcode(&mut self, symbology: Barcode, data: &[u8]) -> Result<(), PrinterError> {
+    let data = barcode_data(symbology, data)?;
+    let kind = match symbology {
+      Barcode::UpcA => 65,
+      Barcode::Ean13 => 67,
+      Barcode::Ean8 => 68,
+      Barcode::Code39 => 69,
+      Barcode::Code128 => 73
+    };
+
+    let mut cmd: Vec<u8> = Vec::from([
+      GS, 0x68, self.barcode_height,
+      GS, 0x77, self.barcode_width,
+      GS, 0x48, 0x02,
+      GS, 0x6b, kind, data.len() as u8
+    ]);
+    cmd.extend_from_slice(&data);
+    cmd.push(LF);
+
+    self.write_vec(&cmd)?;
+    self.flush_buf()?;
+    Ok(())
+  }
+
+  /// # About
+  /// Prints a 1D barcode as a bitmap drawn by ``barcode::render``, for
+  /// printers without "GS k" or with a buggy one. Takes the same ``data`` as
+  /// ``print_barcode``, but the check digit of the numeric symbologies is
+  /// drawn here instead of by the printer.
+  /// # Examples
+  /// ```
+  /// printer.print_barcode_raster(Barcode::Code39, b"A-123", &BarcodeOptions::default())?;
+  /// ```
+  /// # Errors
+  /// - ``PrinterError::InvalidArgument`` if ``data`` can't be encoded with
+  ///   ``symbology`` or the check digit is wrong
+  /// - ``PrinterError::OutOfBounds`` if the barcode is wider than the print
+  ///   width, a smaller ``module_width`` or ``vertical`` may fit it
+  /// - ``PrinterError::TooLarge`` if a vertical barcode is longer than
+  ///   ``MAX_BARCODE_MM``
+  pub fn print_barcode_raster(
+    &mut self,
+    symbology: Barcode,
+    data: &[u8],
+    options: &BarcodeOptions
+  ) -> Result<(), PrinterError> {
+    let mut bitmap = barcode::render(symbology, data, options)?;
+    if options.vertical {
+      let length = self.barcode_length_mm(symbology, data, options)?;
+      if length > MAX_BARCODE_MM {
+        return Err(PrinterError::TooLarge(format!(
+          "{:?} barcode would be {:.0}mm long, more than {:.0}mm", symbology, length, MAX_BARCODE_MM
+        )))
+      }
+      bitmap = bitmap.rotate(Rotation::Cw90);
+    }
+    if bitmap.get_width() > self.capabilities.print_width as usize {
+      return Err(PrinterError::OutOfBounds(format!(
+        "{:?} barcode is {} dots wide, more than the print width of {} dots",
+        symbology, bitmap.get_width(), self.capabilities.print_width
+      )))
+    }
+    self.print_bitmap(
+      checked_u16(bitmap.get_width(), "barcode width")?,
+      checked_u16(bitmap.get_height(), "barcode height")?,
+      bitmap.get_width_in_bytes(),
+      bitmap.as_slice()
+    )
+  }
+
+  /// # About
+  /// Length in millimeters of the bars ``print_barcode_raster`` would print
+  /// for ``data`` at the printer's dpi, across the paper or down it with
+  /// ``vertical``. Codes longer than ``LONG_BARCODE_MM`` are worth a warning.
+  /// # Errors
+  /// - ``PrinterError::InvalidArgument`` if ``data`` can't be encoded with
+  ///   ``symbology``
+  pub fn barcode_length_mm(
+    &self,
+    symbology: Barcode,
+    data: &[u8],
+    options: &BarcodeOptions
+  ) -> Result<f32, PrinterError> {
+    let (modules, _) = barcode::encode(symbology, data)?;
+    let dots = modules.len() * options.module_width as usize;
+    Ok(dots as f32 * 25.4 / self.capabilities.dpi.max(1) as f32)
+  }
+
+  /// # About
+  /// Prints bitmaps using the "GS v 0" ESC-POS command
+  ///
+  /// Bitmaps are read left-to-right, top-to-bottom, with every bit being a dot in the printer
+  /// # Examples
+  /// ```
+  /// // A 16x8 frame
+  /// let bitmap: [u8; 8] = [
+  ///   0b11111111, 0b11111111,
+  ///   0b10000000, 0b00000001,
+  ///   0b10000000, 0b00000001,
+  ///   0b10000000, 0b00000001,
+  ///   0b10000000, 0b00000001,
+  ///   0b10000000, 0b00000001,
+  ///   0b10000000, 0b00000001,
+  ///   0b11111111, 0b11111111,
+  /// ];
+  /// printer.print_bitmap(16, 8, 2, &bitmap)?;
+  /// ```
+  /// # Errors
+  /// - ``PrinterError::InvalidArgument`` if ``bitmap`` is shorter than
+  ///   ``w_bytes * height``
+  /// - ``PrinterError::Interrupted`` if a band fails to be sent, holding the
+  ///   rows sent before it and the error, see ``resume_bitmap``
+  pub fn print_bitmap(
+    &mut self,
+    width: u16,
+    height: u16,
+    w_bytes: usize,
+    bitmap: &[u8]
+  ) -> Result<(), PrinterError> {
+    self.print_bitmap_from(width, height, w_bytes, bitmap, 0)?;
+    Ok(())
+  }
+
+  /// # About
+  /// Sets how many rows ``print_bitmap`` sends to the printer at a time,
+  /// 64 by default.
+  ///
+  /// Bigger bands print faster but can overflow the printer's buffer, which
+  /// corrupts the image. If ``Capabilities::max_raster_bytes`` is set, bands
+  /// are also kept small enough to fit in it. Values below 1 are clamped.
+  pub fn set_raster_band_height(&mut self, rows: u16) {
+    self.raster_band_height = rows.max(1);
+  }
+
+  /// # About
+  /// Sets how long ``print_bitmap`` pauses after each band of rows before
+  /// sending the next one, no pause by default.
+  ///
+  /// Every band is flushed before the next one is sent, which is enough when
+  /// writes block until the printer takes the data, like on USB or serial
+  /// ports. Print servers and spoolers, such as shared printers on Windows,
+  /// accept the data right away instead and can send it faster than the
+  /// head prints, overflowing the printer's buffer and corrupting the image.
+  /// The pause gives the printer time to drain it.
+  ///
+  /// ``delay`` is the pause for a full band of ``set_raster_band_height``
+  /// rows, smaller bands pause proportionally less. About 1.5s per 64 rows
+  /// works on slow printers.
+  /// # Example
+  /// ```
+  /// printer.set_chunk_delay(Duration::from_millis(1500));
+  /// ```
+  pub fn set_chunk_delay(&mut self, delay: Duration) {
+    self.chunk_delay = delay;
+  }
+
+  /// # About
+  /// Continues printing a ``BitImage`` starting at row ``from_row``, skipping
+  /// the rows that were already printed.
+  ///
+  /// Meant for recovering from a long image that stopped printing partway
+  /// through (disconnect, out of paper), using the ``row`` of the
+  /// ``PrinterError::Interrupted`` returned by ``print_bitmap`` as ``from_row``
+  /// so the image doesn't have to be reprinted from scratch.
+  ///
+  /// That row counts the rows sent to the connection, not the rows printed.
+  /// The printer's buffer, and any spooler or network buffer on the way,
+  /// can still hold rows that were never printed when the paper runs out,
+  /// and those are lost. Resuming a band or two earlier, at the cost of
+  /// printing a few rows twice, avoids a gap: a band is at most
+  /// ``set_raster_band_height`` rows, and fewer with
+  /// ``Capabilities::max_raster_bytes`` set.
+  /// # Examples
+  /// ```
+  /// let bitmap = BitImage::new(384, 2000);
+  /// if let Err(PrinterError::Interrupted { row, .. }) = printer.print_bitmap(384, 2000, 48, bitmap.as_slice()) {
+  ///   // once the paper is replaced
+  ///   printer.resume_bitmap(&bitmap, row.saturating_sub(64))?;
+  /// }
+  /// ```
+  pub fn resume_bitmap(&mut self, img: &BitImage, from_row: u16) -> Result<(), PrinterError> {
+    self.print_bitmap_from(
+      checked_u16(img.get_width(), "image width")?,
+      checked_u16(img.get_height(), "image height")?,
+      img.get_width_in_bytes(),
+      img.as_slice(),
+      from_row
+    )?;
+    Ok(())
+  }
+
+  /// # About
+  /// How many rows of the last bitmap were fully sent to the printer, the same
+  /// as the ``row`` of a ``PrinterError::Interrupted``. Rows sent aren't
+  /// necessarily printed yet, see ``resume_bitmap``.
+  pub fn get_raster_progress(&self) -> u16 {
+    self.raster_progress
+  }
+
+  fn print_bitmap_from(
+    &mut self,
+    width: u16,
+    height: u16,
+    w_bytes: usize,
+    bitmap: &[u8],
+    from_row: u16
+  ) -> Result<(), PrinterError> {
+    if bitmap.len() < w_bytes * height as usize {
+      return Err(PrinterError::InvalidArgument(format!(
+        "a {} byte bitmap is too short for {} rows of {} bytes",
+        bitmap.len(), height, w_bytes
+      )))
+    }
+    let row_bytes = checked_u16(w_bytes, "raster row length")?;
+    self.raster_progress = from_row;
+    if from_row >= height {
+      return Ok(())
+    }
+    let mut flush_height: u16 = self.raster_band_height;
+    if self.capabilities.max_raster_bytes > 0 {
+      let max_rows = self.capabilities.max_raster_bytes / w_bytes.max(1);
+      flush_height = flush_height.min(max_rows.clamp(1, u16::MAX as usize) as u16);
+    }
+    let mut cmd: Vec<u8> = Vec::with_capacity(4 + (w_bytes * flush_height as usize));
+    // self.print_bytes(&[GS, 0x76, 0x30, 0x00]);
+    // if width > 382 { return };
+    // self.print_bytes(self.to_two_byte(w_bytes as u16).as_ref());
+    // self.print_bytes(self.to_two_byte(height as u16).as_ref());
+
+    let mut last_pos: usize = from_row as usize * w_bytes;
+    let mut last_height: u16 = from_row;
+    while last_height < height {
+      debug_assert_eq!(last_pos, last_height as usize * w_bytes);
+      let next_height = last_height.saturating_add(flush_height).min(height);
+      let part_height: u16 = next_height - last_height;
+      let range_end = next_height as usize * w_bytes;
+
+      cmd.extend_from_slice(&[GS, b'v', b'0', 0x00]);
+      cmd.extend_from_slice(&self.to_two_byte(row_bytes));
+      cmd.extend_from_slice(&self.to_two_byte(part_height));
+      self.extend_raster(&mut cmd, &bitmap[last_pos..range_end], width as usize, w_bytes);
+
+      if let Err(e) = self.write_timed(&cmd) {
+        return Err(PrinterError::Interrupted { row: last_height, source: Box::new(e) })
+      }
+      cmd.clear();
+      self.raster_progress = next_height;
+
+      // self.print_bytes(&bitmap[last_pos..range_end]);
+      // each band advances by exactly the rows it sent
+      last_height = next_height;
+      last_pos = range_end;
+      if last_height < height && !self.chunk_delay.is_zero() {
+        std::thread::sleep(self.chunk_delay * part_height as u32 / self.raster_band_height as u32);
+      }
+    }
+    // cmd.extend_from_slice(bitmap);
+    // cmd.extend_from_slice("\r\n".as_bytes());
+
+    // cmd.append(&mut Vec::from(bitmap));
+    // self.write_vec(&cmd);
+    // self.flush_buf();
+    Ok(())
+  }
+
+  /// # About
+  /// Prints a ``BitImage`` by storing it in the printer's graphics buffer with
+  /// ``GS ( L`` function 112 and then printing it with function 50.
+  ///
+  /// The printer buffers the whole image internally, so unlike ``print_bitmap``
+  /// it doesn't need to be split into bands, which also saves the pauses
+  /// between them set with ``set_chunk_delay``.
+  ///
+  /// Images bigger than ``Capabilities::graphics_buffer_size`` (or any image if
+  /// it's 0) fall back to ``print_bitmap``.
+  /// # Examples
+  /// ```
+  /// let bitmap = BitImage::new(384, 512);
+  /// printer.print_graphics(&bitmap)?;
+  /// ```
+  pub fn print_graphics(&mut self, img: &BitImage) -> Result<(), PrinterError> {
+    let data = img.as_slice();
+    if data.is_empty() || data.len() > self.capabilities.graphics_buffer_size {
+      self.print_bitmap(
+        checked_u16(img.get_width(), "image width")?,
+        checked_u16(img.get_height(), "image height")?,
+        img.get_width_in_bytes(),
+        data
+      )?;
+      return Ok(())
+    }
+
+    self.store_graphics(img, Color::Black)?;
+    self.print_bytes(&[GS, b'(', b'L', 0x02, 0x00, 48, 50])?;
+    Ok(())
+  }
+
+  /// # About
+  /// Prints a ``BitImage`` of any height with the graphics commands, in
+  /// bands that each fit in ``Capabilities::graphics_buffer_size``, instead of
+  /// the legacy ``GS v 0`` used by ``print_bitmap``, which newer printers
+  /// limit to 255 or 2047 rows per command.
+  ///
+  /// Every band is stored with ``GS ( L pL pH 48 112 48 1 1 49 xL xH yL yH``
+  /// followed by its rows and then printed with ``GS ( L 2 0 48 50``. ``pL pH``
+  /// is the number of bytes after them as a little endian u16, the 10
+  /// parameter bytes plus the raster data, and ``xL xH`` and ``yL yH`` are the
+  /// band's width and height in dots. Bands longer than 65535 bytes are sent
+  /// with ``GS 8 L p1 p2 p3 p4`` instead, the same command with a four byte
+  /// length. Bands are at most ``GRAPHICS_MAX_ROWS`` rows.
+  ///
+  /// Without ``graphics_buffer_size`` it falls back to ``print_bitmap``.
+  /// # Examples
+  /// ```
+  /// let bitmap = BitImage::new(384, 4000);
+  /// printer.print_raster(&bitmap)?;
+  /// ```
+  pub fn print_raster(&mut self, img: &BitImage) -> Result<(), PrinterError> {
+    let w_bytes = img.get_width_in_bytes();
+    if self.capabilities.graphics_buffer_size == 0 || w_bytes == 0 {
+      self.print_bitmap(
+        checked_u16(img.get_width(), "image width")?,
+        checked_u16(img.get_height(), "image height")?,
+        w_bytes,
+        img.as_slice()
+      )?;
+      return Ok(())
+    }
+    let band_rows = (self.capabilities.graphics_buffer_size / w_bytes).clamp(1, GRAPHICS_MAX_ROWS);
+    for y in (0..img.get_height()).step_by(band_rows) {
+      let band = img.crop(0, y, img.get_width(), band_rows);
+      self.store_graphics(&band, Color::Black)?;
+      self.print_bytes(&[GS, b'(', b'L', 0x02, 0x00, 48, 50])?;
+    }
+    Ok(())
+  }
+
+  /// # About
+  /// Prints a two-color image made of a black and a red plane of the same
+  /// size, on printers loaded with two-color paper.
+  ///
+  /// Both planes are stored with ``GS ( L`` function 112 and printed
+  /// together. Without ``Capabilities::two_color``, or if the planes don't fit
+  /// in the graphics buffer, only the black plane is printed.
+  ///
+  /// # Errors
+  /// - if the planes have different dimensions
+  pub fn print_graphics_two_color(&mut self, black: &BitImage, red: &BitImage) -> Result<(), PrinterError> {
+    if black.get_width() != red.get_width() || black.get_height() != red.get_height() {
+      return Err(PrinterError::InvalidArgument(format!(
+        "color planes have different dimensions: {}x{} and {}x{}",
+        black.get_width(), black.get_height(), red.get_width(), red.get_height()
+      )))
+    }
+    let size = black.as_slice().len() * 2;
+    if !self.capabilities.two_color || size == 0 || size > self.capabilities.graphics_buffer_size {
+      self.print_graphics(black)?;
+      return Ok(())
+    }
+    self.store_graphics(black, Color::Black)?;
+    self.store_graphics(red, Color::Red)?;
+    self.print_bytes(&[GS, b'(', b'L', 0x02, 0x00, 48, 50])?;
+    Ok(())
+  }
+
+  /// # About
+  /// Stores ``img`` in the graphics buffer as the given color plane using
+  /// ``GS ( L`` function 112, or ``GS 8 L`` when it's too big for a two byte
+  /// length.
+  fn store_graphics(&mut self, img: &BitImage, color: Color) -> Result<(), PrinterError> {
+    let color_byte = match color {
+      Color::Black => 49,
+      Color::Red => 50
+    };
+    self.store_graphics_plane(img, 48, color_byte)?;
+    Ok(())
+  }
+
+  /// # About
+  /// Stores ``img`` as plane ``plane`` (49-52) of monochrome (``tone`` 48) or
+  /// multiple tone (``tone`` 52) graphics, see ``store_graphics``.
+  fn store_graphics_plane(&mut self, img: &BitImage, tone: u8, plane: u8) -> Result<(), PrinterError> {
+    let data = img.as_slice();
+    // m, fn, tone, 1x horizontal and vertical scale, plane
+    let mut params: Vec<u8> = Vec::from([48, 112, tone, 1, 1, plane]);
+    params.extend_from_slice(&self.to_two_byte(checked_u16(img.get_width(), "image width")?));
+    params.extend_from_slice(&self.to_two_byte(checked_u16(img.get_height(), "image height")?));
+
+    let len = params.len() + data.len();
+    let mut cmd: Vec<u8> = Vec::with_capacity(8 + len);
+    match u16::try_from(len) {
+      Ok(len) => {
+        cmd.extend_from_slice(&[GS, b'(', b'L']);
+        cmd.extend_from_slice(&self.to_two_byte(len));
+      }
+      Err(_) => {
+        // GS 8 L takes the same parameters with a 4 byte length
+        cmd.extend_from_slice(&[GS, b'8', b'L']);
+        cmd.extend_from_slice(&(len as u32).to_le_bytes());
+      }
+    }
+    cmd.extend_from_slice(&params);
+    self.extend_raster(&mut cmd, data, img.get_width(), img.get_width_in_bytes());
+    self.write_vec(&cmd)?;
+    self.flush_buf()?;
+    Ok(())
+  }
+
+  /// # About
+  /// Prints two independent blocks of text side by side, separated by a
+  /// ``gutter`` dots wide, to save paper on wide printers.
+  ///
+  /// The text is rendered to a raster with ``font::render_text`` at twice the
+  /// font's size, each column taking half of ``Capabilities::print_width``
+  /// minus the gutter. The shorter column is padded with blank space.
+  /// # Example
+  /// ```
+  /// printer.print_two_columns("Table 4\n2x Burger", "Table 7\n1x Salad", 16)?;
+  /// ```
+  pub fn print_two_columns(&mut self, left: &str, right: &str, gutter: usize) -> Result<(), PrinterError> {
+    let print_width = self.capabilities.print_width as usize;
+    let column_width = print_width.saturating_sub(gutter) / 2;
+    let left = font::render_text(&self.filter_text(left), column_width, 2);
+    let right = font::render_text(&self.filter_text(right), column_width, 2);
+
+    let mut strip = BitImage::new(print_width, left.get_height().max(right.get_height()));
+    strip.paste(&left, 0, 0);
+    strip.paste(&right, (column_width + gutter) as isize, 0);
+    self.print_graphics(&strip)?;
+    Ok(())
+  }
+
+  /// # About
+  /// Prints everything drawn on ``canvas`` as a single raster with
+  /// ``print_graphics``.
+  pub fn print_canvas(&mut self, canvas: &Canvas) -> Result<(), PrinterError> {
+    self.print_graphics(canvas.as_image())?;
+    Ok(())
+  }
+
+  /// # About
+  /// Prints a ``BitImage`` rotated clockwise by ``rotation``.
+  ///
+  /// The rotation is done on the bitmap itself before sending it, so it works
+  /// on printers without rotation commands. Useful for label printers where
+  /// the content has to run along the roll.
+  /// # Examples
+  /// ```
+  /// let bitmap = BitImage::new(128, 64);
+  /// printer.print_bit_image_rotated(&bitmap, Rotation::Cw90)?;
+  /// ```
+  pub fn print_bit_image_rotated(&mut self, img: &BitImage, rotation: Rotation) -> Result<(), PrinterError> {
+    let rotated = img.rotate(rotation);
+    self.print_bitmap(
+      checked_u16(rotated.get_width(), "image width")?,
+      checked_u16(rotated.get_height(), "image height")?,
+      rotated.get_width_in_bytes(),
+      rotated.as_slice()
+    )?;
+    Ok(())
+  }
+
+  /// # About
+  /// Prints the markdown read from ``md``, using the printer's own text modes
+  /// for the formatting. Each line of the markdown is printed as its own line.
+  ///
+  /// Supported syntax:
+  /// - ``#`` headings in double width and height, ``##`` in double height and
+  ///   ``###`` and deeper in bold
+  /// - ``**bold**`` and ``__bold__`` spans in bold, ``*italic*`` and
+  ///   ``_italic_`` ones underlined, ESC-POS has no italics
+  /// - ``-``, ``*`` and ``+`` list items behind a bullet, indented by two
+  ///   spaces per level of nesting, task list items keep their ``[ ]`` or
+  ///   ``[x]`` instead of the bullet
+  /// - ``---``, ``***`` and ``___`` as a rule across the whole print width
+  /// - ``![alt](path)`` on a line of its own, printed with ``print_image``
+  ///   using the default dithering and options, at its own width or the print
+  ///   width if it's wider. Relative paths are resolved against ``base_dir``,
+  ///   and missing images are skipped with a warning
+  ///
+  /// Anything else is printed as written: tables keep their pipes and dashes,
+  /// links and images in the middle of text keep their brackets with the URL
+  /// after the text, and code spans and blocks keep their backticks.
+  /// # Example
+  /// ```
+  /// let notes = BufReader::new(File::open("notes/today.md")?);
+  /// printer.print_markdown(notes, Path::new("notes"))?;
+  /// ```
+  /// # Errors
+  /// - if an image exists but can't be decoded
+  pub fn print_markdown<R: BufRead>(&mut self, md: R, base_dir: &Path) -> Result<(), PrinterError> {
+    // code page 437 bullet
+    const BULLET: u8 = 0xf9;
+    const RULE_HEIGHT: usize = 2;
+
+    let reg_heading = Regex::new(r"^(#{1,6}) +(.*)").unwrap();
+    let reg_rule = Regex::new(r"^ {0,3}(?:(?:- *){3,}|(?:\* *){3,}|(?:_ *){3,})$").unwrap();
+    let reg_item = Regex::new(r"^( *)[-*+] +(.*)").unwrap();
+    let reg_task = Regex::new(r"^\[[ xX]\] ").unwrap();
+    let reg_image = Regex::new(r#"^ *!\[[^\]]*\]\(([^)\s]+)(?: +"[^"]*")?\) *$"#).unwrap();
+    for line_res in md.lines() {
+      let line = line_res?;
+      let line = self.filter_text(&line).into_owned();
+
+      if reg_rule.is_match(&line) {
+        let width = self.capabilities.print_width;
+        let rule = BitImage::from_fn(width as usize, RULE_HEIGHT, |_, _| true);
+        self.print_bitmap(width, RULE_HEIGHT as u16, rule.get_width_in_bytes(), rule.as_slice())?;
+        continue;
+      }
+
+      if let Some(capture) = reg_image.captures(&line) {
+        self.print_markdown_image(&base_dir.join(&capture[1]))?;
+        continue;
+      }
+
+      let (mut dwidth, mut dheight, mut bold) = (false, false, false);
+      let mut prefix: Vec<u8> = Vec::new();
+      let mut text: &str = &line;
+      if let Some(capture) = reg_heading.captures(&line) {
+        match capture[1].len() {
+          1 => (dwidth, dheight) = (true, true),
+          2 => dheight = true,
+          _ => bold = true
+        }
+        text = capture.get(2).unwrap().as_str();
+      } else if let Some(capture) = reg_item.captures(&line) {
+        let level = capture[1].len() / 2;
+        text = capture.get(2).unwrap().as_str();
+        prefix.extend(std::iter::repeat_n(b' ', level * 2));
+        if !reg_task.is_match(text) {
+          prefix.extend_from_slice(&[BULLET, b' ']);
+        }
+      }
+
+      self.set_text_mode(dwidth, dheight, bold, false)?;
+      self.write_vec(&prefix)?;
+      self.print_markdown_spans(text, dwidth, dheight, bold)?;
+      self.line_feed()?;
+      self.flush_buf()?;
+    }
+    self.set_text_mode(false, false, false, false)?;
+    Ok(())
+  }
+
+  /// # About
+  /// Opens the markdown file at ``path`` and prints it with ``print_markdown``,
+  /// resolving the images in it against the file's directory.
+  /// # Example
+  /// ```
+  /// printer.print_markdown_file(Path::new("notes/today.md"))?;
+  /// ```
+  /// # Errors
+  /// - if the file cannot be opened
+  /// - if an image exists but can't be decoded
+  pub fn print_markdown_file(&mut self, path: &Path) -> Result<(), PrinterError> {
+    let md = BufReader::new(File::open(path)?);
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+    self.print_markdown(md, base_dir)?;
+    Ok(())
+  }
+
+  /// # About
+  /// Prints an image referenced by ``print_markdown``, at most as wide as the
+  /// paper, skipping it with a warning if the file doesn't exist.
+  fn print_markdown_image(&mut self, path: &Path) -> Result<(), PrinterError> {
+    let path_str = match path.to_str() {
+      Some(path_str) if path.is_file() => path_str,
+      _ => {
+        eprintln!("warning: skipping missing markdown image {}", path.display());
+        return Ok(())
+      }
+    };
+    let (width, _) = image::image_dimensions(path)?;
+    let width = width.clamp(1, self.capabilities.print_width.max(1) as u32);
+    self.print_image(path_str, width, &Dither::default(), &ImageOptions::default())?;
+    Ok(())
+  }
+
+  /// # About
+  /// Sends ``text`` with its inline bold and italic spans, see
+  /// ``print_markdown``, going back to the line's own mode after each span.
+  fn print_markdown_spans(&mut self, text: &str, dwidth: bool, dheight: bool, bold: bool) -> Result<(), PrinterError> {
+    let reg_span = Regex::new(
+      r"\*\*(.+?)\*\*|__(.+?)__|\*([^*\s](?:[^*]*[^*\s])?)\*|\b_([^_\s](?:[^_]*[^_\s])?)_\b"
+    ).unwrap();
+    let mut plain_start = 0;
+    for capture in reg_span.captures_iter(text) {
+      let whole = capture.get(0).unwrap();
+      self.write_vec(&self.encode_text(&text[plain_start..whole.start()]))?;
+      let (span, span_bold, span_underline) = match (capture.get(1).or(capture.get(2)), capture.get(3).or(capture.get(4))) {
+        (Some(span), _) => (span, true, false),
+        (_, Some(span)) => (span, bold, true),
+        _ => unreachable!()
+      };
+      self.set_text_mode(dwidth, dheight, span_bold, span_underline)?;
+      self.write_vec(&self.encode_text(span.as_str()))?;
+      self.set_text_mode(dwidth, dheight, bold, false)?;
+      plain_start = whole.end();
+    }
+    self.write_vec(&self.encode_text(&text[plain_start..]))?;
+    Ok(())
+  }
+
+  /// # About
+  /// Takes in the path to an image file, scales the image to the width
+  /// provided and turns it into a black & white image.
+  ///
+  /// ``dithering`` selects how grays are turned into dots, see ``Dither``.
+  ///
+  /// ``options.fit`` selects between scaling the image to the width and
+  /// cropping its sides, see ``ImageFit``, ``options.background`` what
+  /// transparent areas turn into, see ``BgMode``, and ``options.sharpen`` the
+  /// optional sharpening done before dithering, see ``Sharpen``.
+  ///
+  /// The height is scaled to keep the image's proportions on printers with
+  /// non-square dots, see ``Capabilities::dot_aspect``.
+  ///
+  /// Images that are already ``width`` dots wide aren't resized when the dots
+  /// are square, so pre-dithered art made for the printer's native resolution
+  /// prints dot for dot.
+  ///
+  /// Images taller than ``RASTER_HEIGHT_THRESHOLD`` are sent with
+  /// ``print_raster`` on printers with a ``Capabilities::graphics_buffer_size``,
+  /// the rest with ``print_bitmap``.
+  ///
+  /// # Errors
+  /// - if the file cannot be found
+  /// - if ``width`` is 0
+  /// - if a ``Dither::Custom`` map is empty or not rectangular
+  /// - if a ``Dither::Kernel`` has a divisor below 1 or diffuses to earlier pixels
+  pub fn print_image(&mut self, path: &str, width:u32, dithering: &Dither, options: &ImageOptions) -> Result<(), PrinterError> {
+    if *dithering == Dither::Printer && self.capabilities.multi_tone {
+      let (img, mask) = gray_image_for(path, width, options, self.capabilities.dot_aspect)?;
+      let planes = tone_planes(&img, mask.get_width(), mask.get_height());
+      let size = planes[0].as_slice().len() * planes.len();
+      if size > 0 && size <= self.capabilities.graphics_buffer_size {
+        for (plane, plane_byte) in planes.iter().zip(49..) {
+          self.store_graphics_plane(plane, 52, plane_byte)?;
+        }
+        self.print_bytes(&[GS, b'(', b'L', 0x02, 0x00, 48, 50])?;
+        return Ok(())
+      }
+    }
+    let (bitmap, _) = dither_image_for(path, width, dithering, options, self.capabilities.dot_aspect)?;
+    if bitmap.get_height() > RASTER_HEIGHT_THRESHOLD && self.capabilities.graphics_buffer_size > 0 {
+      self.print_raster(&bitmap)?;
+      return Ok(())
+    }
+    self.print_bitmap(
+      checked_u16(bitmap.get_width(), "image width")?,
+      checked_u16(bitmap.get_height(), "image height")?,
+      bitmap.get_width_in_bytes(),
+      bitmap.as_slice()
+    )?;
+    Ok(())
+  }
+
+  /// # About
+  /// Prints the image at ``path`` mirrored left to right, for iron-on
+  /// transfer paper, where the print is flipped again when it's pressed onto
+  /// the fabric. The image is turned into dots like in ``print_image``, with
+  /// ``Dither::Printer`` falling back to ``TwoRowSierra``.
+  /// # Errors
+  /// - if the file cannot be found
+  /// - if ``width`` is 0
+  /// - if a ``Dither::Custom`` map is empty or not rectangular
+  /// - if a ``Dither::Kernel`` has a divisor below 1 or diffuses to earlier pixels
+  pub fn print_image_mirrored(&mut self, path: &str, width:u32, dithering: &Dither, options: &ImageOptions) -> Result<(), PrinterError> {
+    let (bitmap, _) = dither_image_for(path, width, dithering, options, self.capabilities.dot_aspect)?;
+    let mirrored = bitmap.flip_horizontal();
+    self.print_bitmap(
+      checked_u16(mirrored.get_width(), "image width")?,
+      checked_u16(mirrored.get_height(), "image height")?,
+      mirrored.get_width_in_bytes(),
+      mirrored.as_slice()
+    )?;
+    Ok(())
+  }
+
+  /// # About
+  /// Prints ``base`` with the image at ``path`` composited over it, with its
+  /// top-left corner at ``at``, for filling in pre-made forms and overlaying
+  /// stamps. The image is turned into dots like in ``print_image``.
+  ///
+  /// With ``BgMode::Transparent`` the transparent areas of the image are left
+  /// unset so ``base`` shows through them, with the other modes the image
+  /// covers its whole area, see ``dither_image_masked``.
+  /// # Errors
+  /// - if the file cannot be found
+  /// - if ``width`` is 0
+  /// - if a ``Dither::Custom`` map is empty or not rectangular
+  /// - if a ``Dither::Kernel`` has a divisor below 1 or diffuses to earlier pixels
+  pub fn print_image_over(
+    &mut self,
+    base: &BitImage,
+    at: (isize, isize),
+    path: &str,
+    width: u32,
+    dithering: &Dither,
+    options: &ImageOptions
+  ) -> Result<(), PrinterError> {
+    let (img, mask) = dither_image_for(path, width, dithering, options, self.capabilities.dot_aspect)?;
+    let mut composite = base.clone();
+    composite.paste_masked(&img, &mask, at.0, at.1);
+    self.print_graphics(&composite)?;
+    Ok(())
+  }
+
+  /// # About
+  /// Prints the images at ``paths`` as a grid of thumbnails ``cell_width`` dots
+  /// wide, ``cols`` per row, with ``gap`` dots between them. Useful for photo
+  /// booth strips and sticker sheets.
+  ///
+  /// Every row is as tall as its tallest thumbnail, thumbnails are aligned to
+  /// the top of their cell and the last row may be left partially empty.
+  /// # Examples
+  /// ```
+  /// printer.print_image_grid(&["a.png", "b.png", "c.png"], 2, 184, 16)?;
+  /// ```
+  /// # Errors
+  /// - if ``cols`` or ``cell_width`` is 0
+  /// - if the grid is wider than ``Capabilities::print_width``
+  /// - if any of the files cannot be found
+  pub fn print_image_grid(&mut self, paths: &[&str], cols: usize, cell_width: u32, gap: u32) -> Result<(), PrinterError> {
+    if cols == 0 || cell_width == 0 {
+      return Err(PrinterError::InvalidArgument(
+        "image grids need at least one column of non-zero width".to_string()
+      ))
+    }
+    let pitch = (cell_width + gap) as usize;
+    let grid_width = pitch * cols - gap as usize;
+    if grid_width > self.capabilities.print_width as usize {
+      return Err(PrinterError::OutOfBounds(format!(
+        "image grid is {} dots wide, but the printer only prints {}",
+        grid_width, self.capabilities.print_width
+      )))
+    }
+
+    let rows = paths.chunks(cols);
+    let row_count = rows.len();
+    for (i, row) in rows.enumerate() {
+      let thumbnails: Vec<BitImage> = row.iter()
+        .map(|path| Ok(dither_image_for(
+          path, cell_width, &Dither::default(), &ImageOptions::default(), self.capabilities.dot_aspect
+        )?.0))
+        .collect::<Result<_, PrinterError>>()?;
+      let row_height = thumbnails.iter().map(|t| t.get_height()).max().unwrap_or(0);
+      // the gap between rows is left at the bottom of every row but the last
+      let gap_below = if i + 1 < row_count { gap as usize } else { 0 };
+
+      let mut strip = BitImage::new(grid_width, row_height + gap_below);
+      for (col, thumbnail) in thumbnails.iter().enumerate() {
+        strip.paste(thumbnail, (col * pitch) as isize, 0);
+      }
+      self.print_graphics(&strip)?;
+    }
+    Ok(())
+  }
+}
 
 /// # About
-/// Base struct used for printing
-/// # Creating
-/// On Windows:
+/// Loads the image at ``path`` and turns it into a ``BitImage`` the same way
+/// ``Printer::print_image`` does, without printing it, assuming square dots.
+///
+/// # Errors
+/// - if the file cannot be found
+/// - if ``width`` is 0
+/// - if a ``Dither::Custom`` map is empty or not rectangular
+/// - if a ``Dither::Kernel`` has a divisor below 1 or diffuses to earlier pixels
+pub fn dither_image(
+  path: &str,
+  width:u32,
+  dithering: &Dither,
+  options: &ImageOptions
+) -> Result<BitImage, PrinterError> {
+  Ok(dither_image_for(path, width, dithering, options, (1, 1))?.0)
+}
+
+/// # About
+/// Turns the image at ``path`` into an exactly ``size`` by ``size`` dots
+/// ``BitImage``, to line it up with a QR code or other square elements. Non
+/// square images are cropped to their center square first, ``options.fit``
+/// is ignored.
+/// # Examples
 /// ```
-/// let mut printer = Printer::new("\\\\MACHINE NAME\\SHARED_PRINTER_NAME");
+/// let logo = dither_image_square("logo.png", 200, &Dither::default(), &ImageOptions::default())?;
+/// let mut strip = BitImage::new(400, 200);
+/// strip.paste(&logo, 0, 0);
 /// ```
-pub struct Printer {
-  path: String,
-  file_handle: File,
+/// # Errors
+/// - if the file cannot be found
+/// - if ``size`` is 0
+/// - if a ``Dither::Custom`` map is empty or not rectangular
+/// - if a ``Dither::Kernel`` has a divisor below 1 or diffuses to earlier pixels
+pub fn dither_image_square(
+  path: &str,
+  size: u32,
+  dithering: &Dither,
+  options: &ImageOptions
+) -> Result<BitImage, PrinterError> {
+  check_dither(dithering)?;
+  let img = image::open(path)?;
+  if size == 0 {
+    return Err(PrinterError::InvalidArgument("image size must be greater than 0".to_string()))
+  }
+  let side = img.width().min(img.height());
+  let img = img
+    .crop_imm((img.width() - side) / 2, (img.height() - side) / 2, side, side)
+    .resize_exact(size, size, imageops::Triangle);
+  let (gray, mask) = gray_image(&img, size, size, options);
+  Ok(dither_gray(gray, &mask, dithering, options))
 }
 
+/// # About
+/// ``dither_image`` that also returns a mask with the dots that belong to the
+/// image set, to composite it over another ``BitImage`` with
+/// ``BitImage::paste_masked``.
+///
+/// With ``BgMode::Transparent`` the pixels below the alpha threshold are left
+/// out of the mask and are never printed, not even by the error diffused from
+/// their neighbors. With every other mode the whole image is opaque.
+/// # Examples
+/// ```
+/// let options = ImageOptions { background: BgMode::Transparent(128), ..Default::default() };
+/// let (stamp, mask) = dither_image_masked("stamp.png", 128, &Dither::default(), &options);
+/// form.paste_masked(&stamp, &mask, 256, 0);
+/// ```
+/// # Errors
+/// - if the file cannot be found
+/// - if ``width`` is 0
+/// - if a ``Dither::Custom`` map is empty or not rectangular
+/// - if a ``Dither::Kernel`` has a divisor below 1 or diffuses to earlier pixels
+pub fn dither_image_masked(
+  path: &str,
+  width: u32,
+  dithering: &Dither,
+  options: &ImageOptions
+) -> Result<(BitImage, BitImage), PrinterError> {
+  dither_image_for(path, width, dithering, options, (1, 1))
+}
 
-pub const GS: u8 = 0x1d;
-pub const ESC: u8 = 0x1b;
+/// # About
+/// ``dither_image`` for dots with the given width to height ratio, see
+/// ``Capabilities::dot_aspect``.
+fn dither_image_for(
+  path: &str,
+  width: u32,
+  dithering: &Dither,
+  options: &ImageOptions,
+  dot_aspect: (u16, u16)
+) -> Result<(BitImage, BitImage), PrinterError> {
+  check_dither(dithering)?;
+  let (img, mask) = gray_image_for(path, width, options, dot_aspect)?;
+  let bitmap = dither_gray(img, &mask, dithering, options);
+  Ok((bitmap, mask))
+}
 
-impl Printer {
+/// # About
+/// Fails if ``dithering`` is a ``Dither::Custom`` map or ``Dither::Kernel``
+/// that can't be used.
+fn check_dither(dithering: &Dither) -> Result<(), PrinterError> {
+  if let Dither::Custom(map) = dithering {
+    if map.is_empty() || map[0].is_empty() || map.iter().any(|row| row.len() != map[0].len()) {
+      return Err(PrinterError::InvalidArgument(
+        "custom threshold maps must be non-empty and rectangular".to_string()
+      ))
+    }
+  }
+  if let Dither::Kernel { weights, divisor } = dithering {
+    if *divisor <= 0 {
+      return Err(PrinterError::InvalidArgument("kernel divisors must be greater than 0".to_string()))
+    }
+    if weights.iter().any(|&(dx, dy, _)| dy < 0 || (dy == 0 && dx <= 0)) {
+      return Err(PrinterError::InvalidArgument(
+        "kernels can only diffuse the error to pixels that weren't thresholded yet".to_string()
+      ))
+    }
+  }
+  Ok(())
+}
 
-  /// # Examples
-  /// On Windows:
-  /// ```
-  /// let mut printer = Printer::new("\\\\MACHINE NAME\\SHARED_PRINTER_NAME");
-  /// ```
-  pub fn new(printer_path: &str) -> Self {
-    Printer {
-      file_handle: {
-        let path = Path::new(printer_path);
-        match File::create(path) {
-          Ok(handle) => handle,
-          Err(e) => panic!("FAILED TO CREATE FILE HANDLE FOR PRINTER {:?}", e)
+/// # About
+/// Dithers the grayscale image returned by ``gray_image``, leaving the pixels
+/// outside of ``mask`` unset with ``BgMode::Transparent``.
+fn dither_gray(img: image::GrayImage, mask: &BitImage, dithering: &Dither, options: &ImageOptions) -> BitImage {
+  let (width, height) = (mask.get_width(), mask.get_height());
+  let mut bitmap = threshold_image(img, width, height, dithering);
+  if let Some(max_coverage) = options.max_coverage {
+    limit_coverage(&mut bitmap, mask, max_coverage as f32 / 100.0);
+  }
+  if options.invert {
+    bitmap.invert();
+  }
+  if let BgMode::Transparent(_) = options.background {
+    // error diffused from opaque neighbors can't leave dots in transparent areas
+    for y in 0..bitmap.get_height() as isize {
+      for x in 0..bitmap.get_width() as isize {
+        if !mask.get_pixel(x, y) {
+          bitmap.set_pixel(x, y, false);
         }
-      },
-      path: printer_path.to_string()
+      }
     }
   }
+  bitmap
+}
 
-  fn write_byte(&mut self, byte: u8) {
-    match self.file_handle.write(&[byte]) {
-      Ok(_) => (),
-      Err(e) => panic!("error: {}", e)
-    };
-  }
+/// # About
+/// Opens the image at ``path`` and scales it like ``dither_image_for``, then
+/// composites it over ``options.background`` and sharpens it, returning the
+/// grayscale image and a mask of its opaque pixels.
+fn gray_image_for(
+  path: &str,
+  width: u32,
+  options: &ImageOptions,
+  dot_aspect: (u16, u16)
+) -> Result<(image::GrayImage, BitImage), PrinterError> {
+  let (img, width, height) = scale_image(path, width, options, dot_aspect)?;
+  Ok(gray_image(&img, width, height, options))
+}
 
-  fn flush_buf(&mut self) {
-    match self.file_handle.flush() {
-      Ok(_) => (),
-      Err(e) => panic!("error: {}", e)
-    };
+/// # About
+/// Composites an already scaled image over ``options.background`` and
+/// sharpens it, see ``gray_image_for``. The mask is ``width`` by ``height``.
+fn gray_image(
+  img: &image::DynamicImage,
+  width: u32,
+  height: u32,
+  options: &ImageOptions
+) -> (image::GrayImage, BitImage) {
+  let mut alphaimg = img.to_rgba32f();
+  let mut img: image::ImageBuffer<Luma<u8>, Vec<u8>> = image::ImageBuffer::new(img.width(), img.height());
+  let mut mask = BitImage::new(width as usize, height as usize);
+  for pix in alphaimg.enumerate_pixels_mut() {
+    // composites transparent images over the selected background
+    let alpha = pix.2.channels()[3];
+    let transparent = matches!(options.background, BgMode::Transparent(threshold) if alpha * 255.0 < threshold as f32);
+    mask.set_pixel(pix.0 as isize, pix.1 as isize, !transparent);
+    for channel in 0..=2 {
+      pix.2.channels_mut()[channel] = match options.background {
+        BgMode::White => pix.2.channels()[channel] * alpha + (1.0 * (1.0 - alpha)),
+        BgMode::Black => pix.2.channels()[channel] * alpha,
+        BgMode::Transparent(threshold) if alpha * 255.0 < threshold as f32 => 1.0,
+        BgMode::Transparent(_) => pix.2.channels()[channel]
+      };
+    }
+    // uses the ITU BT.709 formula for Luma calculation
+    let lightness: u8 = ((pix.2.channels()[0] * 0.2126 + pix.2.channels()[1] * 0.7152 + pix.2.channels()[2] * 0.0722) * 255.0).clamp(0.0, 255.0).round() as u8;
+    img.put_pixel(pix.0, pix.1, Luma([lightness]));
   }
 
-  fn print_buffer(&mut self) {
-    match self.file_handle.write(&[0x0c]) {
-      Ok(_) => (),
-      Err(e) => panic!("error: {}", e)
-    };
+  if let Some(sharpen) = options.sharpen {
+    let blurred = imageops::blur(&img, sharpen.radius.max(1) as f32);
+    let amount = sharpen.amount as i32;
+    for (pixel, blurred) in img.pixels_mut().zip(blurred.pixels()) {
+      let value = pixel.channels()[0] as i32;
+      let difference = value - blurred.channels()[0] as i32;
+      pixel.channels_mut()[0] = (value + difference * amount / 100).clamp(0, 255) as u8;
+    }
   }
 
-  /// # About
-  /// Turns a u16 into a format that can be sent to the printer
-  fn to_two_byte(&self, num: u16) -> [u8;2] {
-    let mut bytes: [u8;2] = num.to_be_bytes();
-    bytes.reverse();
-    bytes
-  }
+  (img, mask)
+}
 
-  /// # Examples
-  /// ```
-  /// printer.println("Hello World!");
-  /// ```
-  pub fn println(&mut self, message: &str) {
-    match self.file_handle.write_all(message.as_bytes()) {
-      Ok(_) => (),
-      Err(e) => panic!("error: {}", e)
-    };
-    self.write_byte(0x0c);
-    self.flush_buf();
+/// # About
+/// Opens the image at ``path`` and resizes it to ``width`` following
+/// ``options.fit`` and ``dot_aspect``, returning it with its final size.
+fn scale_image(
+  path: &str,
+  width: u32,
+  options: &ImageOptions,
+  dot_aspect: (u16, u16)
+) -> Result<(image::DynamicImage, u32, u32), PrinterError> {
+  let mut img = image::open(path)?;
+  img = match options.rotation {
+    Some(Rotation::Cw90) => img.rotate90(),
+    Some(Rotation::Cw180) => img.rotate180(),
+    Some(Rotation::Cw270) => img.rotate270(),
+    None => img
+  };
+  if width == 0 {
+    return Err(PrinterError::InvalidArgument("image width must be greater than 0".to_string()))
+  }
+  // rows per dot of width, wide dots need more rows to keep the proportions
+  let square_dots = dot_aspect.0 == dot_aspect.1;
+  let stretch = dot_aspect.0.max(1) as f32 / dot_aspect.1.max(1) as f32;
+  let height: u32;
+  if img.width() == width && square_dots {
+    // images already at the target width are left untouched, resampling them
+    // would blur hand-tuned 1-bit art, which then passes through the dithering
+    // unchanged since pure black and white pixels carry no error
+    height = img.height();
+  } else if options.fit == ImageFit::Crop && img.width() > width {
+    img = img.crop_imm((img.width() - width) / 2, 0, width, img.height());
+    height = (img.height() as f32 * stretch) as u32;
+    if !square_dots {
+      img = img.resize_exact(width, height, imageops::Triangle);
+    }
+  } else if square_dots {
+    height = (img.height() as f32 * (width as f32/ img.width() as f32)) as u32;
+    img = img.resize(width, height, imageops::Triangle);
+  } else {
+    height = (img.height() as f32 * (width as f32/ img.width() as f32) * stretch) as u32;
+    img = img.resize_exact(width, height, imageops::Triangle);
   }
+  Ok((img, width, height))
+}
 
-  /// # About
-  /// Funcion used to send an array of bytes to the printer and flush its buffer.
-  /// # Warning
-  /// Sending a sequence of bytes might cause the printer to stop working,
-  /// requiring a reboot.
-  ///
-  /// Only use this if you know what you're doing.
-  ///
-  /// # Tip
-  /// use the constants ``printing::GS`` and ``printing::ESC`` as escape characters.
-  pub fn print_bytes(&mut self, message: &[u8]) {
-    match self.file_handle.write_all(message) {
-      Ok(_) => (),
-      Err(e) => panic!("error: {}", e)
-    };
-    self.flush_buf();
+/// # About
+/// Inverts the darkest ``LOW_INK_TILE`` tiles of ``bitmap`` until its coverage
+/// is at most ``max_coverage``, see ``ImageOptions::max_coverage``. Only the
+/// pixels set in ``mask`` are inverted.
+fn limit_coverage(bitmap: &mut BitImage, mask: &BitImage, max_coverage: f32) {
+  let (width, height) = (bitmap.get_width(), bitmap.get_height());
+  let total = (width * height) as f32;
+  let mut set = bitmap.coverage() * total;
+
+  let mut tiles: Vec<(usize, usize, f32)> = Vec::new();
+  for y in (0..height).step_by(LOW_INK_TILE) {
+    for x in (0..width).step_by(LOW_INK_TILE) {
+      tiles.push((x, y, bitmap.coverage_in(x, y, LOW_INK_TILE, LOW_INK_TILE)));
+    }
   }
+  tiles.sort_by(|a, b| b.2.total_cmp(&a.2));
 
-  /// # About
-  /// Simply puts the contents of the supplied vector into the buffer.
-  ///
-  /// Requires flushing.
-  fn write_vec(&mut self, bytes: &Vec<u8>) {
-    for byte in bytes {
-      self.write_byte(*byte);
+  for (tile_x, tile_y, _) in tiles {
+    if set <= max_coverage * total {
+      break
     }
+    let mut change: isize = 0;
+    for y in tile_y..(tile_y + LOW_INK_TILE).min(height) {
+      for x in tile_x..(tile_x + LOW_INK_TILE).min(width) {
+        if mask.get_pixel(x as isize, y as isize) {
+          change += if bitmap.get_pixel(x as isize, y as isize) { -1 } else { 1 };
+        }
+      }
+    }
+    // mostly light or transparent tiles would only get darker
+    if change >= 0 {
+      continue;
+    }
+    for y in tile_y..(tile_y + LOW_INK_TILE).min(height) {
+      for x in tile_x..(tile_x + LOW_INK_TILE).min(width) {
+        if mask.get_pixel(x as isize, y as isize) {
+          let pixel = bitmap.get_pixel(x as isize, y as isize);
+          bitmap.set_pixel(x as isize, y as isize, !pixel);
+        }
+      }
+    }
+    set += change as f32;
   }
+}
 
-  pub fn reset(&mut self) {
-    self.print_bytes(&[ESC, b'@']);
+/// # About
+/// Splits a grayscale image into the four bit planes of its darkness in 16
+/// levels, the most significant bit first, as sent to the printer for
+/// ``Dither::Printer``.
+fn tone_planes(img: &image::GrayImage, width: usize, height: usize) -> [BitImage; 4] {
+  let mut planes = [
+    BitImage::new(width, height),
+    BitImage::new(width, height),
+    BitImage::new(width, height),
+    BitImage::new(width, height)
+  ];
+  for (x, y, pixel) in img.enumerate_pixels() {
+    if x as usize >= width || y as usize >= height {
+      continue;
+    }
+    let tone = (255 - pixel.channels()[0]) >> 4;
+    for (bit, plane) in planes.iter_mut().enumerate() {
+      plane.set_pixel(x as isize, y as isize, tone & (8 >> bit) != 0);
+    }
   }
+  planes
+}
 
-  /// # About
-  /// 0: left
-  ///
-  /// 1: center
-  ///
-  /// 2: right
-  /// # Example
-  /// ```
-  /// printer.set_justification(1);
-  /// ```
-  pub fn set_justification(&mut self, value: u8) {
-    self.print_bytes(&[ESC, 0x61, value]);
+/// # About
+/// The ``size`` by ``size`` Bayer matrix as a threshold map for
+/// ``threshold_image``, ``size`` being a power of two. Each level is centered
+/// in its share of 0-255, so flat grays print the expected amount of dots.
+fn bayer_map(size: usize) -> Vec<Vec<u8>> {
+  // every step tiles the previous matrix four times as 4 * m + 0, 2, 3, 1
+  let mut indices: Vec<Vec<usize>> = vec![vec![0]];
+  while indices.len() < size {
+    let n = indices.len();
+    let mut next = vec![vec![0; n * 2]; n * 2];
+    for y in 0..n {
+      for x in 0..n {
+        let m = indices[y][x] * 4;
+        next[y][x] = m;
+        next[y][x + n] = m + 2;
+        next[y + n][x] = m + 3;
+        next[y + n][x + n] = m + 1;
+      }
+    }
+    indices = next;
   }
+  let levels = size * size;
+  indices.iter()
+    .map(|row| row.iter().map(|&i| ((i * 2 + 1) * 256 / (levels * 2)) as u8).collect())
+    .collect()
+}
 
-  pub fn set_text_mode(
-    &mut self,
-    double_width: bool,
-    double_height: bool,
-    bold: bool,
-    underline: bool
+/// # About
+/// Turns a grayscale image into a ``width`` by ``height`` ``BitImage`` of
+/// black and white dots with ``dithering``.
+fn threshold_image(mut img: image::GrayImage, width: usize, height: usize, dithering: &Dither) -> BitImage {
+  fn add_error(
+      img: &mut image::GrayImage,
+      x: i32,
+      y: i32,
+      divided_error: &i32,
+      importance: i32
   ) {
-    let mut msg: Vec<u8> = Vec::from([ESC, b'!']);
-    let mut settings: u8 = 0;
-    if double_width {
-      settings |= 0b00100000;
-    }
-    if double_height {
-      settings |= 0b00010000;
+    let error: i32 = divided_error * importance;
+    if x >= 0 && x < img.width() as i32 && y >= 0 && y < img.height() as i32 {
+      let pixel = &mut img.get_pixel_mut(x as u32, y as u32).channels_mut()[0];
+      *pixel = (*pixel as i32 + error).clamp(0, 255) as u8;
     }
-    if bold {
-      settings |= 0b00001000;
+  }
+
+  let mut bitmap = BitImage::new(width, height);
+
+  let map = match dithering {
+    Dither::Custom(map) => Some(Cow::Borrowed(map)),
+    Dither::Bayer4 => Some(Cow::Owned(bayer_map(4))),
+    Dither::Bayer8 => Some(Cow::Owned(bayer_map(8))),
+    _ => None
+  };
+  if let Some(map) = map {
+    for (x, y, pixel) in img.enumerate_pixels() {
+      let row = &map[y as usize % map.len()];
+      bitmap.set_pixel(x as isize, y as isize, pixel.channels()[0] < row[x as usize % row.len()]);
     }
-    if underline {
-      settings |= 0b00000001;
+    return bitmap
+  }
+
+  let (weights, divisor) = dithering.kernel();
+  let divisor = divisor.max(1);
+  let shift = if (divisor as u32).is_power_of_two() { Some(divisor.trailing_zeros()) } else { None };
+
+  // the error is diffused in place, pixels are only read after all of the
+  // pixels before them were thresholded
+  let (img_width, img_height) = img.dimensions();
+  for (x, y) in (0..img_height).flat_map(|y| (0..img_width).map(move |x| (x, y))) {
+    let error: i32;
+    let pixel = &mut img.get_pixel_mut(x, y).channels_mut()[0];
+    match *pixel {
+      value if value > 127 => {
+        *pixel = 255;
+        error = value as i32 - 255;
+        bitmap.set_pixel(x as isize, y as isize, false);
+      },
+      value => {
+        *pixel = 0;
+        error = value as i32;
+        bitmap.set_pixel(x as isize, y as isize, true);
+      }
+    };
+
+    for &(dx, dy, weight) in weights.iter() {
+      let (divided_error, importance) = match shift {
+        Some(shift) => (error >> shift, weight),
+        // divisors that aren't powers of two divide every weight on its own
+        // to keep small errors from being rounded away entirely
+        None => (error * weight / divisor, 1)
+      };
+      add_error(&mut img, x as i32 + dx, y as i32 + dy, &divided_error, importance);
     }
-    msg.push(settings);
-    self.write_vec(&msg);
-    self.flush_buf();
   }
 
-  pub fn print_qr_code(&mut self, size: u8, data: &[u8]) {
+  bitmap
+}
 
-    self.print_bytes(&[GS, 0x28, 0x6b, 0x03, 0x00, 0x31, 0x43, size]);
+/// # About
+/// Removes every control character but newlines and tabs from ``text``,
+/// including ``ESC``, ``GS``, ``FS`` and ``DLE``, which start ESC-POS commands,
+/// so text from untrusted sources can't change the printer's settings.
+///
+/// The parameters of a removed command are printable and stay in the text,
+/// but without the byte that introduces them they're printed as text.
+/// Unlike ``Printer::set_ascii_safe`` non-ASCII characters are kept.
+/// # Examples
+/// ```
+/// assert_eq!(sanitize("hello \x1b@world\x0c"), "hello @world");
+/// ```
+pub fn sanitize(text: &str) -> String {
+  text.chars()
+    .filter(|c| *c == '\n' || *c == '\t' || !c.is_control())
+    .collect()
+}
 
+/// # About
+/// Keeps printable ASCII, newlines and tabs, drops every other control
+/// character and replaces non-ASCII characters with ``?``.
+fn to_ascii_safe(text: &str) -> String {
+  sanitize(text).chars()
+    .map(|c| if c.is_ascii() { c } else { '?' })
+    .collect()
+}
 
-    let mut cmd: Vec<u8> = Vec::from([GS, 0x28, 0x6b]);
-    cmd.extend_from_slice(&self.to_two_byte(data.len() as u16 + 3));
-    cmd.extend_from_slice(&[0x31, 0x50, 0x30]);
-    cmd.extend_from_slice(data);
+/// How long writes wait before trying again when the printer's handle is
+/// non-blocking and its buffer is full
+pub const WOULD_BLOCK_RETRY_DELAY: Duration = Duration::from_millis(5);
 
-    self.write_vec(&cmd);
-    self.flush_buf();
+/// # About
+/// Like ``Write::write_all``, but also keeps trying when a non-blocking
+/// ``writer`` returns ``WouldBlock``, since giving up halfway through a raster
+/// would shift the rest of the image. Short writes continue from the first
+/// byte that wasn't accepted.
+fn write_all_retrying<W: Write>(writer: &mut W, bytes: &[u8]) -> std::io::Result<()> {
+  let mut bytes = bytes;
+  while !bytes.is_empty() {
+    match writer.write(bytes) {
+      Ok(0) => return Err(std::io::Error::new(
+        std::io::ErrorKind::WriteZero,
+        "the printer stopped accepting data"
+      )),
+      Ok(written) => bytes = &bytes[written..],
+      Err(e) if e.kind() == std::io::ErrorKind::Interrupted => (),
+      Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => std::thread::sleep(WOULD_BLOCK_RETRY_DELAY),
+      Err(e) => return Err(e)
+    }
   }
+  Ok(())
+}
 
-  /// # About
-  /// Prints bitmaps using the "GS v 0" ESC-POS command
-  ///
-  /// Bitmaps are read left-to-right, top-to-bottom, with every bit being a dot in the printer
-  /// # Examples
-  /// ```
-  /// // A 16x8 frame
-  /// let bitmap: [u8; 8] = [
-  ///   0b11111111, 0b11111111,
-  ///   0b10000000, 0b00000001,
-  ///   0b10000000, 0b00000001,
-  ///   0b10000000, 0b00000001,
-  ///   0b10000000, 0b00000001,
-  ///   0b10000000, 0b00000001,
-  ///   0b10000000, 0b00000001,
-  ///   0b11111111, 0b11111111,
-  /// ];
-  /// printer.print_bitmap(width = 16, height = 8, w_bytes = 2, &bitmap);
-  /// ```
-  pub fn print_bitmap(
-    &mut self,
-    width: u16,
-    height: u16,
-    w_bytes: usize,
-    bitmap: &[u8]
-  ) {
-    let flush_height: u16 = 64;
-    let mut cmd: Vec<u8> = Vec::with_capacity(4 + (w_bytes * flush_height as usize));
-    // self.print_bytes(&[GS, 0x76, 0x30, 0x00]);
-    // if width > 382 { return };
-    // self.print_bytes(self.to_two_byte(w_bytes as u16).as_ref());
-    // self.print_bytes(self.to_two_byte(height as u16).as_ref());
+/// # About
+/// ``Write::flush`` that keeps trying on ``WouldBlock``, see
+/// ``write_all_retrying``.
+fn flush_retrying<W: Write>(writer: &mut W) -> std::io::Result<()> {
+  loop {
+    match writer.flush() {
+      Err(e) if e.kind() == std::io::ErrorKind::Interrupted => (),
+      Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => std::thread::sleep(WOULD_BLOCK_RETRY_DELAY),
+      result => return result
+    }
+  }
+}
 
-    let mut last_pos: usize = 0;
-    let mut last_height: u16 = 0;
-    loop {
-      let range_end = (last_pos + (w_bytes * flush_height as usize)).clamp(0, bitmap.len());
-      let next_height = (last_height + flush_height).clamp(0, height as u16);
-      let part_height: u16 = next_height - last_height;
+/// How long ``Printer::list_nv_logos`` waits for each block of the response
+pub const NV_RESPONSE_TIMEOUT: Duration = Duration::from_secs(2);
 
-      cmd.extend_from_slice(&[GS, b'v', b'0', 0x00]);
-      cmd.extend_from_slice(&self.to_two_byte(w_bytes as u16));
-      cmd.extend_from_slice(&self.to_two_byte(part_height));
-      cmd.extend_from_slice(&bitmap[last_pos..range_end]);
+/// How long ``AsbEvents`` waits for a packet on device files before
+/// returning ``PrinterError::Timeout``
+pub const ASB_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
 
-      self.write_vec(&cmd);
-      self.flush_buf();
-      self.print_bytes(&[0x0c]);
-      cmd.clear();
+/// Smallest QR code module, in millimeters, that most scanners read reliably
+pub const MIN_QR_MODULE_MM: f32 = 0.3;
 
-      // self.print_bytes(&bitmap[last_pos..range_end]);
-      last_height += 32;
-      last_pos = range_end;
-      if range_end == bitmap.len() {
-        break
-      }
-      std::thread::sleep(std::time::Duration::from_millis(1500));
-    }
-    // cmd.extend_from_slice(bitmap);
-    // cmd.extend_from_slice("\r\n".as_bytes());
+/// Barcodes longer than this, in millimeters, are hard to scan in one pass
+/// with most handheld scanners, see ``Printer::barcode_length_mm``
+pub const LONG_BARCODE_MM: f32 = 150.0;
+
+/// Longest vertical barcode ``Printer::print_barcode_raster`` prints, in
+/// millimeters
+pub const MAX_BARCODE_MM: f32 = 1000.0;
+
+/// Lets the printer pick the number of PDF417 columns, see
+/// ``Printer::print_pdf417``
+pub const PDF417_DEFAULT_COLUMNS: u8 = 0;
+
+/// PDF417 error correction level recommended for up to 40 data codewords,
+/// about the size of a tracking number, longer data is better off with 3 or 4
+pub const PDF417_DEFAULT_ECC: u8 = 2;
+
+/// Longest data ``Printer::print_qr_code`` can send, the length field of
+/// ``GS ( k`` also counts the 3 bytes of the function code. Longer data fails
+/// with ``PrinterError::TooLarge``.
+pub const QR_MAX_DATA_LEN: usize = u16::MAX as usize - 3;
+
+/// Blank modules the QR code specification asks for around a symbol, see
+/// ``Printer::set_qr_quiet_zone``
+pub const QR_QUIET_ZONE: u8 = 4;
 
-    #[cfg(debug_assertions)]
-    for i in 0..height {
-      for j in 0..w_bytes {
-        let pos = j + (i as usize * w_bytes);
-        // print!(" {}: ", pos);
-        // let byte = &bitmap[pos];
-        print!("{:08b}", &bitmap[pos]);
-        // cmd.push(byte);
+/// Bytes a QR code can hold in byte mode with error correction level L,
+/// indexed by version - 1
+const QR_CAPACITY_L: [usize; 40] = [
+  17, 32, 53, 78, 106, 134, 154, 192, 230, 271,
+  321, 367, 425, 458, 520, 586, 644, 718, 792, 858,
+  929, 1003, 1091, 1171, 1273, 1367, 1465, 1528, 1628, 1732,
+  1840, 1952, 2068, 2188, 2303, 2431, 2563, 2699, 2809, 2953,
+];
+
+/// # About
+/// Width in modules of the smallest QR code that can hold ``data_len`` bytes,
+/// ``None`` if it's too much data for any version.
+fn qr_modules(data_len: usize) -> Option<usize> {
+  QR_CAPACITY_L.iter()
+    .position(|capacity| data_len <= *capacity)
+    .map(|version| 17 + 4 * (version + 1))
+}
+
+/// # About
+/// Module size in dots that makes a QR code ``modules`` wide come closest to
+/// ``size_mm`` millimeters at ``dpi``, at least 1.
+fn qr_module_dots(size_mm: f32, modules: usize, dpi: u16) -> u8 {
+  let dots = size_mm / 25.4 * dpi as f32 / modules as f32;
+  dots.round().clamp(1.0, 255.0) as u8
+}
+
+/// # About
+/// Builds the ``WIFI:`` payload understood by phone cameras, escaping the
+/// characters that are special to it.
+pub fn wifi_payload(ssid: &str, password: &str, security: WifiSecurity) -> String {
+  fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+      if matches!(c, '\\' | ';' | ',' | ':' | '"') {
+        escaped.push('\\');
       }
-      print!("\n");
+      escaped.push(c);
     }
+    escaped
+  }
 
-    // cmd.append(&mut Vec::from(bitmap));
-    // self.write_vec(&cmd);
-    // self.flush_buf();
+  match security {
+    WifiSecurity::Open => format!("WIFI:T:nopass;S:{};;", escape(ssid)),
+    WifiSecurity::Wpa => format!("WIFI:T:WPA;S:{};P:{};;", escape(ssid), escape(password)),
+    WifiSecurity::Wep => format!("WIFI:T:WEP;S:{};P:{};;", escape(ssid), escape(password))
+  }
+}
 
-    #[cfg(debug_assertions)]
-    println!("dimensions: {:?}x{:?}", width, height);
+/// # About
+/// Builds a minimal vCard 3.0 payload.
+pub fn vcard_payload(name: &str, phone: Option<&str>, email: Option<&str>) -> String {
+  let mut payload = format!("BEGIN:VCARD\r\nVERSION:3.0\r\nN:{0}\r\nFN:{0}\r\n", name);
+  if let Some(phone) = phone {
+    payload += &format!("TEL:{}\r\n", phone);
+  }
+  if let Some(email) = email {
+    payload += &format!("EMAIL:{}\r\n", email);
   }
+  payload + "END:VCARD"
+}
 
-  pub fn print_markdown(&mut self, md: BufReader<File>) {
-    let reg_title = Regex::new(r"^#{1} (.*)").unwrap();
-    let reg_subtitle = Regex::new(r"^#{2} (.*)").unwrap();
-    let reg_subsubtitle = Regex::new(r"^#{3,} (.*)").unwrap();
-    let reg_bold = Regex::new(r"(.*?)(?:\*|_)+([^\s][^\*\n]+[^\s])(?:\*|_)([^\*\n]*)").unwrap();
-    let reg_checkmark = Regex::new(r"- (\[[ x]\].+)").unwrap();
-    for line_res in md.lines() {
-      let mut dwidth = false;
-      let mut dheight = false;
-      let mut bold = false;
-      let underline = false;
-      let liner = match line_res {
-        Ok(o) => o,
-        Err(_) => panic!("bruh!")
-      };
-      let mut text: &str = &liner;
-
-      // start testing for matches for linewide markdown syntax
-      if let Some(capture) = reg_title.captures(&liner) {
-        dwidth = true;
-        dheight = true;
-        text = capture.get(1).unwrap().as_str();
-      } else if let Some(capture) = reg_subtitle.captures(&liner) {
-        dheight = true;
-        text = capture.get(1).unwrap().as_str();
-      } else if let Some(capture) = reg_subsubtitle.captures(&liner) {
-        bold = true;
-        text = capture.get(1).unwrap().as_str();
-      } else if let Some(capture) = reg_checkmark.captures(&liner) {
-        text = capture.get(1).unwrap().as_str();
-      }
-
-      // test for inline markdown syntax
-      if reg_bold.is_match(text) {
-        for cap in reg_bold.captures_iter(text) {
-          self.set_text_mode(dwidth, dheight, false, false);
-          self.print_bytes(cap[1].as_bytes());
-          self.set_text_mode(dwidth, dheight, true, false);
-          self.print_bytes(cap[2].as_bytes());
-          self.set_text_mode(dwidth, dheight, false, false);
-          self.print_bytes(cap[3].as_bytes());
-          self.print_bytes(&[0x0c]);
-        }
-      } else {
-        self.set_text_mode(dwidth, dheight, bold, underline);
-        self.println(text);
-      }
+/// # About
+/// Builds a ``geo:`` URI payload.
+pub fn geo_payload(lat: f64, lon: f64) -> String {
+  format!("geo:{},{}", lat, lon)
+}
 
+/// # About
+/// Checks that ``data`` can be encoded with ``symbology`` and returns the bytes
+/// sent after ``GS k``.
+pub(crate) fn barcode_data(symbology: Barcode, data: &[u8]) -> Result<Vec<u8>, PrinterError> {
+  let invalid = |reason: &str| Err(PrinterError::InvalidArgument(
+    format!("{:?} barcode {:?} {}", symbology, String::from_utf8_lossy(data), reason)
+  ));
 
+  let digits = match symbology {
+    Barcode::UpcA => Some(11),
+    Barcode::Ean13 => Some(12),
+    Barcode::Ean8 => Some(7),
+    _ => None
+  };
+  if let Some(digits) = digits {
+    if !data.iter().all(u8::is_ascii_digit) || (data.len() != digits && data.len() != digits + 1) {
+      return invalid(&format!("must be {} or {} digits", digits, digits + 1))
     }
+    if data.len() == digits + 1 && data[digits] - b'0' != barcode_check_digit(&data[..digits]) {
+      return invalid("has a wrong check digit")
+    }
+    return Ok(data.to_vec())
   }
 
-  /// # About
-  /// Takes in the path to an image file, scales the image to the width
-  /// provided and turns it into a black & white image.
-  ///
-  /// Uses the Floyd-Steinberg dithering algorithm as described on:
-  ///
-  /// <https://en.wikipedia.org/wiki/Floyd%E2%80%93Steinberg_dithering>
-  ///
-  /// # Panics
-  /// - if the file cannot be found
-  pub fn print_image(&mut self, path: &str, width:u32, dithering: u8) {
-    fn get_pixel(vector: &Vec<Vec<u8>>,x: i32, y: i32) -> u8 {
-      if x >= 0 && x < vector.len() as i32 && y >= 0 && y < vector.get(0).unwrap().len() as i32 {
-        if let Some(row) = vector.get(x as usize) {
-          if let Some(pixel) = row.get(y as usize) {
-            return *pixel;
-          } else {
-            panic!();
-          }
-        } else {
-          panic!();
-        }
-      } else {
-        return 0;
-        // panic!("panicked while accessing coords: {:?},{:?}", x, y);
+  match symbology {
+    Barcode::Code39 => {
+      if data.is_empty() || data.len() > 255 {
+        return invalid("must be 1 to 255 characters long")
       }
-    }
-
-    fn set_pixel(vector: &mut Vec<Vec<u8>>,x: i32, y: i32, val: u8) {
-      if x >= 0 && x < vector.len() as i32 && y >= 0 && y < vector.get(0).unwrap().len() as i32 {
-        if let Some(row) = vector.get_mut(x as usize) {
-          if let Some(pixel) = row.get_mut(y as usize) {
-            *pixel = val;
+      if !data.iter().all(|c| c.is_ascii_digit() || c.is_ascii_uppercase() || b" $%*+-./".contains(c)) {
+        return invalid("can only have digits, uppercase letters, space and $%*+-./")
+      }
+      Ok(data.to_vec())
+    },
+    _ => {
+      if !data.is_ascii() {
+        return invalid("can only have ASCII characters")
+      }
+      let mut encoded = Vec::with_capacity(data.len() + 2);
+      if matches!(data, [b'{', b'A' | b'B' | b'C', ..]) {
+        encoded.extend_from_slice(data);
+      } else {
+        // plain text, so braces can't start an escape
+        encoded.extend_from_slice(b"{B");
+        for c in data {
+          if *c == b'{' {
+            encoded.push(b'{');
           }
+          encoded.push(*c);
         }
       }
+      if encoded.len() <= 2 || encoded.len() > 255 {
+        return invalid("must be 1 to 253 characters long, counting a { twice")
+      }
+      Ok(encoded)
     }
+  }
+}
 
-    fn add_error(
-        vector: &mut Vec<Vec<u8>>,
-        x: i32,
-        y: i32,
-        divided_error: &i32,
-        importance: i32
-    ) {
-      let error: i32 = divided_error * importance;
-      if x >= 0 && x < vector.len() as i32 && y >= 0 && y < vector.get(0).unwrap().len() as i32 {
-        if let Some(row) = vector.get_mut(x as usize) {
-          if let Some(pixel) = row.get_mut(y as usize) {
-            *pixel = (*pixel as i32 + error).clamp(0, 255) as u8;
-          }
-        }
-      }
+/// # About
+/// The UPC/EAN check digit of ``digits``, which are ASCII digits without the
+/// check digit.
+pub(crate) fn barcode_check_digit(digits: &[u8]) -> u8 {
+  // weights alternate 3, 1, ... starting from the rightmost digit
+  let sum: u32 = digits.iter().rev().enumerate()
+    .map(|(i, d)| (d - b'0') as u32 * if i % 2 == 0 { 3 } else { 1 })
+    .sum();
+  ((10 - sum % 10) % 10) as u8
+}
+
+/// # About
+/// Formats ``amount`` rounded to cents with the separators and currency
+/// symbol placement of ``options``, as printed by ``Printer::print_total``.
+/// # Examples
+/// ```
+/// let options = TotalOptions::default();
+/// assert_eq!(format_amount(1234.5, "$", &options), "$1,234.50");
+/// assert_eq!(format_amount(0.99, "$", &options), "$0.99");
+/// assert_eq!(format_amount(-1234567.0, "$", &options), "-$1,234,567.00");
+/// ```
+pub fn format_amount(amount: f64, currency: &str, options: &TotalOptions) -> String {
+  let cents = (amount.abs() * 100.0).round() as u64;
+  let digits = (cents / 100).to_string();
+  let mut whole = String::with_capacity(digits.len() * 4 / 3);
+  for (i, digit) in digits.chars().enumerate() {
+    if i > 0 && (digits.len() - i).is_multiple_of(3) {
+      whole.push(options.thousands_separator);
     }
+    whole.push(digit);
+  }
+  let number = format!("{}{}{:02}", whole, options.decimal_separator, cents % 100);
+  // -0.001 rounds to zero cents and shouldn't print a sign
+  let sign = if amount < 0.0 && cents > 0 { "-" } else { "" };
+  if options.symbol_after {
+    format!("{}{} {}", sign, number, currency)
+  } else {
+    format!("{}{}{}", sign, currency, number)
+  }
+}
 
-    let mut img = match image::open(path) {
-      Ok(o) => o,
-      Err(e) => panic!("error opening image: {}", e)
-    };
-    let height: u32 = (img.height() as f32 * (width as f32/ img.width() as f32)) as u32;
-    img = img.resize(width, height, imageops::Triangle);
-    let mut alphaimg = img.to_rgba32f();
-    let mut img: image::ImageBuffer<Luma<u8>, Vec<u8>> = image::ImageBuffer::new(img.width(), img.height());
-    for pix in alphaimg.enumerate_pixels_mut() {
-      // makes the background for transparent images white
-      for channel in 0..=2 {
-        pix.2.channels_mut()[channel] = pix.2.channels()[channel] * pix.2.channels()[3] + (1.0 * (1.0 - pix.2.channels()[3]));
-      }
-      // uses the ITU BT.709 formula for Luma calculation
-      let lightness: u8 = ((pix.2.channels()[0] * 0.2126 + pix.2.channels()[1] * 0.7152 + pix.2.channels()[2] * 0.0722) * 255.0).clamp(0.0, 255.0).round() as u8;
-      img.put_pixel(pix.0, pix.1, Luma([lightness]));
+/// # About
+/// Encodes every line of ``text`` followed by a line feed, as UTF-8 without
+/// an ``encoding``.
+fn encode_lines(text: &str, encoding: Option<Encoding>) -> Vec<u8> {
+  let mut bytes: Vec<u8> = Vec::with_capacity(text.len() + 1);
+  for line in text.lines() {
+    match encoding {
+      Some(encoding) => bytes.extend(encoding.encode(line)),
+      None => bytes.extend_from_slice(line.as_bytes())
     }
+    bytes.push(LF);
+  }
+  bytes
+}
 
-    let mut dithered_img = image::GrayImage::new(width + 1, height + 1);
+/// # About
+/// Converts ``value`` for a two-byte parameter, failing with
+/// ``PrinterError::TooLarge`` instead of wrapping around when it doesn't fit.
+/// ``what`` names the value in the error.
+fn checked_u16(value: usize, what: &str) -> Result<u16, PrinterError> {
+  u16::try_from(value).map_err(|_| PrinterError::TooLarge(format!(
+    "{} is {}, at most {} can be sent", what, value, u16::MAX
+  )))
+}
 
-    let mut grayscale = vec![vec![0u8 ; height as usize]; width as usize];
-    // let mut bitmap = vec![vec![0u8 ; (width as f32 / 8.0).ceil() as usize]; height as usize];
-    let mut bitmap = BitImage::new(width as usize, height as usize);
+/// # About
+/// Pads ``text`` with spaces to exactly ``width`` characters, truncating it if
+/// it's longer.
+fn align_text(text: &str, width: usize, align: Align) -> String {
+  let text: String = text.chars().take(width).collect();
+  let padding = width - text.chars().count();
+  match align {
+    Align::Left => format!("{}{}", text, " ".repeat(padding)),
+    Align::Right => format!("{}{}", " ".repeat(padding), text),
+    Align::Center => format!("{}{}{}", " ".repeat(padding / 2), text, " ".repeat(padding - padding / 2))
+  }
+}
 
-    for pix in img.enumerate_pixels() {
-      if let Some(row) = grayscale.get_mut(pix.0 as usize) {
-        if let Some(pixel) = row.get_mut(pix.1 as usize) {
-          *pixel = pix.2.channels()[0];
-        }
-      }
+/// # About
+/// Pads the gaps between the words of ``line`` until it is ``width`` characters
+/// long, giving the leftmost gaps the extra spaces when they can't be spread
+/// evenly. Lines with a single word are left as they are.
+fn justify_line(line: &str, width: usize) -> String {
+  let words: Vec<&str> = line.split(' ').collect();
+  let gaps = words.len() - 1;
+  let text_len: usize = words.iter().map(|w| w.chars().count()).sum();
+  if gaps == 0 || text_len + gaps >= width {
+    return line.to_owned();
+  }
+  let spaces = width - text_len;
+  let mut justified = String::with_capacity(width);
+  for (i, word) in words.iter().enumerate() {
+    justified.push_str(word);
+    if i < gaps {
+      let gap = spaces / gaps + if i < spaces % gaps { 1 } else { 0 };
+      justified.push_str(&" ".repeat(gap));
     }
+  }
+  justified
+}
 
-    for pos in img.enumerate_pixels() {
-      if
-        pos.0 > grayscale.len() as u32 ||
-        pos.1 > grayscale.get(0).unwrap().len() as u32
-      {
-        continue;
+/// # About
+/// Splits ``text`` into lines of at most ``width`` characters, breaking on
+/// whitespace when possible and splitting words that don't fit in a line.
+pub(crate) fn wrap_text(text: &str, width: usize) -> Vec<String> {
+  let width = width.max(1);
+  let mut lines: Vec<String> = Vec::new();
+  let mut line = String::new();
+  for word in text.split_whitespace() {
+    let mut word: Vec<char> = word.chars().collect();
+    while word.len() > width {
+      if !line.is_empty() {
+        lines.push(std::mem::take(&mut line));
       }
-      let error: i32;
-      match get_pixel(&grayscale, pos.0 as i32, pos.1 as i32) {
-        x if x> 127 => {
-          set_pixel(&mut grayscale, pos.0 as i32, pos.1 as i32, 255);
-          dithered_img.put_pixel(pos.0, pos.1, Luma([255]));
-          error = x as i32 - 255;
-          bitmap.set_pixel(pos.0 as isize, pos.1 as isize, false);
-        },
-        x => {
-          set_pixel(&mut grayscale, pos.0 as i32, pos.1 as i32, 0);
-          dithered_img.put_pixel(pos.0, pos.1, Luma([0]));
-          error = x as i32;
-          bitmap.set_pixel(pos.0 as isize, pos.1 as isize, true);
-        }
-      };
+      lines.push(word.drain(..width).collect());
+    }
+    let line_len = line.chars().count();
+    if line_len > 0 && line_len + 1 + word.len() > width {
+      lines.push(std::mem::take(&mut line));
+    }
+    if !line.is_empty() {
+      line.push(' ');
+    }
+    line.extend(word);
+  }
+  if !line.is_empty() || lines.is_empty() {
+    lines.push(line);
+  }
+  lines
+}
 
-      let xpos = pos.0 as i32;
-      let ypos = pos.1 as i32;
-      match dithering {
-        0 => {
-          let div_err = error >> 4;
-          add_error(&mut grayscale, xpos + 1, ypos    , &div_err, 7);
-          add_error(&mut grayscale, xpos - 1, ypos + 1, &div_err, 3);
-          add_error(&mut grayscale, xpos    , ypos + 1, &div_err, 5);
-          add_error(&mut grayscale, xpos + 1, ypos + 1, &div_err, 1);
-        },
-        1 => {
-          let div_err = error >> 4;
-          add_error(&mut grayscale, xpos + 1, ypos    , &div_err, 4);
-          add_error(&mut grayscale, xpos + 2, ypos    , &div_err, 3);
-          add_error(&mut grayscale, xpos - 2, ypos + 1, &div_err, 1);
-          add_error(&mut grayscale, xpos - 1, ypos + 1, &div_err, 2);
-          add_error(&mut grayscale, xpos    , ypos + 1, &div_err, 3);
-          add_error(&mut grayscale, xpos + 1, ypos + 1, &div_err, 2);
-          add_error(&mut grayscale, xpos + 2, ypos + 1, &div_err, 1);
-        },
-        2 => {
-          let div_err = error >> 5;
-          add_error(&mut grayscale, xpos + 1, ypos    , &div_err, 5);
-          add_error(&mut grayscale, xpos + 2, ypos    , &div_err, 3);
-          add_error(&mut grayscale, xpos - 2, ypos + 1, &div_err, 2);
-          add_error(&mut grayscale, xpos - 1, ypos + 1, &div_err, 4);
-          add_error(&mut grayscale, xpos    , ypos + 1, &div_err, 5);
-          add_error(&mut grayscale, xpos + 1, ypos + 1, &div_err, 4);
-          add_error(&mut grayscale, xpos + 2, ypos + 1, &div_err, 2);
-          add_error(&mut grayscale, xpos - 1, ypos + 2, &div_err, 2);
-          add_error(&mut grayscale, xpos    , ypos + 2, &div_err, 3);
-          add_error(&mut grayscale, xpos + 1, ypos + 2, &div_err, 2);
-        },
-        _ => ()
+impl<C: Connection> Printer<C> {
+  /// # About
+  /// Finds out how many bytes of ``GS v 0`` raster data the printer accepts
+  /// in a single command and stores it in ``Capabilities::max_raster_bytes``,
+  /// so the following ``print_bitmap`` calls send bands as large as possible.
+  ///
+  /// Blank rasters the width of the paper are sent, doubling their height
+  /// each time, and after each one ``wait_complete`` checks that the printer
+  /// is still answering. The first raster that goes unanswered within
+  /// ``timeout`` is taken as an overflow, so the result is the last size that
+  /// worked, which can be up to half of the real limit. The printer is reset
+  /// after an overflow, but some models need to be power cycled.
+  ///
+  /// Every raster feeds some blank paper.
+  /// # Example
+  /// ```
+  /// let max_bytes = printer.calibrate_raster_size(Duration::from_secs(5))?;
+  /// ```
+  /// # Errors
+  /// - ``PrinterError::Unsupported`` without ``Capabilities::status_readback``,
+  ///   since write-only printers can't report overflows
+  pub fn calibrate_raster_size(&mut self, timeout: Duration) -> Result<usize, PrinterError> {
+    if !self.capabilities.status_readback {
+      return Err(PrinterError::Unsupported(
+        "calibrating the raster size needs a printer that sends responses back".to_string()
+      ))
+    }
+    let w_bytes = (self.capabilities.print_width as usize).div_ceil(8).max(1);
+    let mut last_good: usize = 0;
+    let mut height: usize = 8;
+    while height <= u16::MAX as usize {
+      let mut cmd: Vec<u8> = Vec::with_capacity(8 + w_bytes * height);
+      cmd.extend_from_slice(&[GS, b'v', b'0', 0x00]);
+      cmd.extend_from_slice(&self.to_two_byte(w_bytes as u16));
+      cmd.extend_from_slice(&self.to_two_byte(height as u16));
+      cmd.resize(cmd.len() + w_bytes * height, 0);
+      self.print_bytes(&cmd)?;
+      if !self.wait_complete(timeout)? {
+        self.reset()?;
+        break
       }
+      last_good = w_bytes * height;
+      height *= 2;
     }
-
-    self.print_bitmap(width as u16, height as u16, bitmap.get_width_in_bytes(), bitmap.as_slice());
+    self.capabilities.max_raster_bytes = last_good;
+    Ok(last_good)
   }
 }
 
 #[cfg(debug_assertions)]
-impl Printer {
-  pub fn test_bitmap_buffer_size(&mut self) {
+impl<C: Connection> Printer<C> {
+  /// # About
+  /// Prints 256 dot wide bitmaps one row taller at a time, asking on stdin
+  /// whether each one printed, and returns the size in bytes of the last one
+  /// that did.
+  pub fn test_bitmap_buffer_size(&mut self) -> Result<usize, PrinterError> {
     let step_size = 1;
     let mut bitmap: Vec<u8> = Vec::with_capacity(32*256);
     let mut i = 100;
     for k in 0..i*32 {
-      bitmap.push((k & 1 & (k/32 & 1)) as u8 * 255);
+      bitmap.push((k & 1 & ((k/32) & 1)) as u8 * 255);
     }
     let mut input = String::new();
     loop {
       for k in 0..=255 {
-        bitmap.push(k & 1 & (i & 1) as u8 * 255);
+        bitmap.push(k & 1 & ((i & 1) as u8 * 255));
       }
       println!("Printing 256 X {} bitmap", i);
-      self.print_bitmap(256, i, 32, bitmap.as_slice());
+      self.print_bitmap(256, i, 32, bitmap.as_slice())?;
       println!("Worked? Y/n");
-      std::io::stdin().read_line(&mut input).expect("error: unable to read stdin!");
+      input.clear();
+      std::io::stdin().read_line(&mut input)?;
       match input.trim().to_lowercase().as_str() {
         "y" => {
           i += step_size;
@@ -477,7 +4375,7 @@ impl Printer {
         },
         "n" => {
           println!("max size reached! size in bytes: {}", 32 * (i - step_size));
-          break;
+          break Ok(32 * (i - step_size) as usize);
         },
         _ => {
           i += step_size;
@@ -558,3 +4456,1037 @@ pub mod examples {
   0b11111111,0b11111111,0b11100000,0b00001111,0b11000111,0b11110000,0b11111110,0b00011111,0b10001111,0b10000111,0b11000111,0b00011100,0b11111000,0b11111111,0b11111111,0b11111111,
   ];
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn reset_sends_esc_at() {
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.reset().unwrap();
+    assert_eq!(printer.into_inner().unwrap(), [0x1b, 0x40]);
+  }
+
+  #[test]
+  fn reset_clears_tracked_state() {
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.set_justification(1).unwrap();
+    printer.set_text_mode(true, true, true, false).unwrap();
+    printer.set_line_spacing(40).unwrap();
+    printer.print_bitmap(8, 3, 1, &[0xff; 3]).unwrap();
+    assert_eq!(printer.get_raster_progress(), 3);
+    printer.reset().unwrap();
+    assert_eq!(printer.snapshot(), PrinterState::default());
+    assert_eq!(printer.get_raster_progress(), 0);
+  }
+
+  /// Splits the output of ``print_bitmap`` into its ``GS v 0`` bands and joins
+  /// their rows back together, returning the row length, every row and the
+  /// height of each band
+  fn decode_raster(mut bytes: &[u8]) -> (usize, Vec<u8>, Vec<usize>) {
+    let (mut w_bytes, mut rows, mut bands) = (0, Vec::new(), Vec::new());
+    while !bytes.is_empty() {
+      assert_eq!(&bytes[..4], &[GS, b'v', b'0', 0x00]);
+      let x = u16::from_le_bytes([bytes[4], bytes[5]]) as usize;
+      let y = u16::from_le_bytes([bytes[6], bytes[7]]) as usize;
+      assert!(w_bytes == 0 || w_bytes == x, "bands of different widths");
+      w_bytes = x;
+      rows.extend_from_slice(&bytes[8..8 + x * y]);
+      bands.push(y);
+      bytes = &bytes[8 + x * y..];
+    }
+    (w_bytes, rows, bands)
+  }
+
+  #[test]
+  fn print_bitmap_bands_match_the_input() {
+    // a diagonal gradient, darker towards the bottom right
+    let gradient = BitImage::from_fn(100, 256, |x, y| (x * 7 + y * 13) % 256 < (x + y) * 256 / 356);
+    for band_height in [64, 48, 1, 300] {
+      let mut printer = Printer::new_with_writer(Vec::new());
+      printer.set_raster_band_height(band_height);
+      printer.print_bitmap(100, 256, gradient.get_width_in_bytes(), gradient.as_slice()).unwrap();
+      assert_eq!(printer.get_raster_progress(), 256);
+
+      let (w_bytes, rows, bands) = decode_raster(&printer.into_inner().unwrap());
+      assert_eq!(w_bytes, 13);
+      assert_eq!(rows, gradient.as_slice(), "bands of {} rows", band_height);
+      assert_eq!(bands.iter().sum::<usize>(), 256);
+      assert!(bands.iter().all(|rows| *rows <= band_height as usize));
+    }
+  }
+
+  #[test]
+  fn resume_bitmap_sends_only_the_remaining_rows() {
+    let gradient = BitImage::from_fn(100, 256, |x, y| (x + y) % 3 == 0);
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.resume_bitmap(&gradient, 200).unwrap();
+    let (_, rows, _) = decode_raster(&printer.into_inner().unwrap());
+    assert_eq!(rows, &gradient.as_slice()[200 * 13..]);
+  }
+
+  #[test]
+  fn long_qr_payload_is_too_large() {
+    let mut printer = Printer::new_with_writer(Vec::new());
+    let result = printer.print_qr_code(8, QrEcc::L, &vec![b'a'; 70000]);
+    assert!(matches!(result, Err(PrinterError::TooLarge(_))), "{:?}", result);
+    // nothing was sent, not even the module size
+    assert!(printer.into_inner().unwrap().is_empty());
+  }
+
+  #[test]
+  fn wide_bitmap_is_too_large() {
+    let mut printer = Printer::new_with_writer(Vec::new());
+    let result = printer.print_bitmap(8, 1, 70000, &vec![0; 70000]);
+    assert!(matches!(result, Err(PrinterError::TooLarge(_))), "{:?}", result);
+    let result = printer.print_bit_image_rotated(&BitImage::new(1, 70000), Rotation::Cw90);
+    assert!(matches!(result, Err(PrinterError::TooLarge(_))), "{:?}", result);
+    assert!(printer.into_inner().unwrap().is_empty());
+  }
+
+  #[test]
+  fn qr_module_size_boundaries() {
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.set_allow_small_qr(true);
+    for size in [0, 17] {
+      let result = printer.print_qr_code(size, QrEcc::L, b"a");
+      assert!(matches!(result, Err(PrinterError::InvalidQrSize(_))), "size {}: {:?}", size, result);
+    }
+    assert!(printer.get_ref().is_empty());
+
+    for size in [1, 16] {
+      printer.print_qr_code(size, QrEcc::L, b"a").unwrap();
+    }
+    let bytes = printer.into_inner().unwrap();
+    let sizes: Vec<u8> = bytes.windows(8)
+      .filter(|w| w[..7] == [GS, 0x28, 0x6b, 0x03, 0x00, 0x31, 0x43])
+      .map(|w| w[7])
+      .collect();
+    assert_eq!(sizes, [1, 16]);
+  }
+
+  #[test]
+  fn qr_data_length_boundaries() {
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.set_qr_quiet_zone(0);
+    printer.print_qr_code(8, QrEcc::L, &vec![b'a'; QR_MAX_DATA_LEN]).unwrap();
+    let bytes = printer.into_inner().unwrap();
+    // size and level, then the store function with the length ff ff
+    assert_eq!(&bytes[16..24], &[GS, 0x28, 0x6b, 0xff, 0xff, 0x31, 0x50, 0x30]);
+    assert_eq!(bytes.len(), 16 + 8 + QR_MAX_DATA_LEN + 8);
+
+    let mut printer = Printer::new_with_writer(Vec::new());
+    let result = printer.print_qr_code(8, QrEcc::L, &vec![b'a'; QR_MAX_DATA_LEN + 1]);
+    assert!(matches!(result, Err(PrinterError::TooLarge(_))), "{:?}", result);
+    assert!(printer.into_inner().unwrap().is_empty());
+  }
+
+  #[test]
+  fn dither_modes_differ_on_a_gradient() {
+    // 8 to 233 in steps of 15, left to right and top to bottom
+    let gradient = image::GrayImage::from_fn(4, 4, |x, y| image::Luma([(8 + (y * 4 + x) * 15) as u8]));
+    let modes = [
+      Dither::Threshold,
+      Dither::FloydSteinberg,
+      Dither::TwoRowSierra,
+      Dither::Sierra,
+      Dither::Bayer4,
+      Dither::Bayer8,
+    ];
+    let bitmaps: Vec<BitImage> = modes.iter()
+      .map(|mode| threshold_image(gradient.clone(), 4, 4, mode))
+      .collect();
+
+    // no diffusion, only the 8 pixels darker than 128
+    assert_eq!(bitmaps[0].as_slice(), &[0xf0, 0xf0, 0x00, 0x00]);
+    for (i, a) in bitmaps.iter().enumerate() {
+      for (j, b) in bitmaps.iter().enumerate().skip(i + 1) {
+        assert_ne!(a, b, "{:?} and {:?} dither the same", modes[i], modes[j]);
+      }
+    }
+  }
+
+  /// Two ASB packets with a stray byte in between: paper near end, then the
+  /// cover open with the paper out
+  const ASB_STREAM: [u8; 9] = [0x10, 0x00, 0x03, 0x00, 0xff, 0x30, 0x00, 0x0c, 0x00];
+
+  #[test]
+  fn asb_events_end_when_the_connection_closes() {
+    let mut events = AsbEvents {
+      reader: Box::new(std::io::Cursor::new(ASB_STREAM.to_vec())),
+      received: Vec::new(),
+      ends_at_eof: true,
+      idle_timeout: ASB_IDLE_TIMEOUT
+    };
+    let first = events.next().unwrap().unwrap();
+    assert!(first.paper_near_end && !first.paper_end);
+    let second = events.next().unwrap().unwrap();
+    assert!(second.cover_open && second.paper_end);
+    assert!(events.next().is_none());
+  }
+
+  #[test]
+  fn asb_events_time_out_on_quiet_device_files() {
+    let mut events = AsbEvents {
+      reader: Box::new(std::io::Cursor::new(ASB_STREAM[..4].to_vec())),
+      received: Vec::new(),
+      ends_at_eof: false,
+      idle_timeout: ASB_IDLE_TIMEOUT
+    };
+    events.set_idle_timeout(Duration::from_millis(100));
+    assert!(events.next().unwrap().unwrap().paper_near_end);
+    let started = Instant::now();
+    assert!(matches!(events.next(), Some(Err(PrinterError::Timeout(_)))));
+    assert!(started.elapsed() >= Duration::from_millis(100));
+    // still waiting for more afterwards
+    assert!(matches!(events.next(), Some(Err(PrinterError::Timeout(_)))));
+  }
+
+  #[test]
+  fn small_qr_modules_are_refused_unless_allowed() {
+    let mut printer = Printer::new_with_writer(Vec::new());
+    // 0.3mm at 203 dpi
+    assert_eq!(printer.min_qr_size(), 3);
+    let result = printer.print_qr_code(2, QrEcc::L, b"a");
+    assert!(matches!(result, Err(PrinterError::InvalidQrSize(_))), "{:?}", result);
+    assert!(printer.get_ref().is_empty());
+
+    printer.print_qr_code(3, QrEcc::L, b"a").unwrap();
+    printer.set_allow_small_qr(true);
+    printer.print_qr_code(2, QrEcc::L, b"a").unwrap();
+  }
+
+  /// Accepts ``limit`` bytes, then fails every write
+  struct FailingWriter {
+    written: Vec<u8>,
+    limit: usize,
+  }
+
+  impl Write for FailingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+      if self.written.len() + buf.len() > self.limit {
+        return Err(std::io::Error::new(std::io::ErrorKind::BrokenPipe, "unplugged"))
+      }
+      self.written.extend_from_slice(buf);
+      Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+      Ok(())
+    }
+  }
+
+  impl Connection for FailingWriter {
+    fn open(_address: &str) -> std::io::Result<Self> {
+      Ok(FailingWriter { written: Vec::new(), limit: 0 })
+    }
+  }
+
+  #[test]
+  fn interrupted_bitmap_reports_the_row_reached() {
+    let bitmap = BitImage::from_fn(16, 100, |x, y| (x + y) % 2 == 0);
+    // room for two bands of 32 rows, 8 bytes of header and 64 of data each
+    let writer = FailingWriter { written: Vec::new(), limit: 2 * 72 };
+    let mut printer = Printer::new_with_writer(writer);
+    printer.set_raster_band_height(32);
+    let result = printer.print_bitmap(16, 100, 2, bitmap.as_slice());
+    match result {
+      Err(PrinterError::Interrupted { row, source }) => {
+        assert_eq!(row, 64);
+        assert!(matches!(*source, PrinterError::Io(_)));
+      }
+      other => panic!("expected an interruption, got {:?}", other)
+    }
+    assert_eq!(printer.get_raster_progress(), 64);
+
+    // resuming from the reported row sends exactly the rest
+    let mut resumed = Printer::new_with_writer(Vec::new());
+    resumed.resume_bitmap(&bitmap, 64).unwrap();
+    let (_, rows, _) = decode_raster(&resumed.into_inner().unwrap());
+    assert_eq!(rows, &bitmap.as_slice()[64 * 2..]);
+  }
+
+  #[test]
+  fn restore_only_sends_what_changed() {
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.set_justification(1).unwrap();
+    printer.set_text_mode(false, true, true, false).unwrap();
+    printer.set_line_spacing(40).unwrap();
+    printer.set_font(Font::B).unwrap();
+    let saved = printer.snapshot();
+    assert_ne!(saved, PrinterState::default());
+
+    printer.set_justification(2).unwrap();
+    printer.set_line_spacing(20).unwrap();
+    printer.set_upside_down(true).unwrap();
+    let sent = printer.get_ref().len();
+    printer.restore(&saved).unwrap();
+    assert_eq!(&printer.get_ref()[sent..], &[ESC, b'a', 1, ESC, b'3', 40, ESC, b'{', 0]);
+    assert_eq!(printer.snapshot(), saved);
+
+    // nothing left to change
+    let sent = printer.get_ref().len();
+    printer.restore(&saved).unwrap();
+    assert_eq!(printer.get_ref().len(), sent);
+
+    // starting from the defaults every saved setting is sent
+    printer.restore(&PrinterState::default()).unwrap();
+    let sent = printer.get_ref().len();
+    printer.restore(&saved).unwrap();
+    assert_eq!(
+      &printer.get_ref()[sent..],
+      &[ESC, b'a', 1, ESC, b'!', 0b00011000, ESC, b'3', 40, ESC, b'M', 1]
+    );
+  }
+
+  #[test]
+  fn raster_barcode_is_printed_as_a_bitmap() {
+    let mut printer = Printer::new_with_writer(Vec::new());
+    let options = BarcodeOptions { module_width: 2, height: 40, text: false, vertical: false };
+    printer.print_barcode_raster(Barcode::Ean13, b"400638133393", &options).unwrap();
+    let (w_bytes, rows, _) = decode_raster(printer.get_ref());
+    assert_eq!(w_bytes, (95 * 2usize).div_ceil(8));
+    assert_eq!(rows.len(), w_bytes * 40);
+    // the start guard's bar, space and bar, 2 dots each
+    assert_eq!(rows[0], 0b11001100);
+
+    let wide = BarcodeOptions { module_width: 6, ..options };
+    let data = b"ORDER-1234-5678";
+    assert!(matches!(
+      printer.print_barcode_raster(Barcode::Code128, data, &wide),
+      Err(PrinterError::OutOfBounds(_))
+    ));
+  }
+
+  #[test]
+  fn vertical_barcodes_run_down_the_paper() {
+    let mut printer = Printer::new_with_writer(Vec::new());
+    let data = b"ORDER-1234-5678";
+    let options = BarcodeOptions { module_width: 3, height: 60, text: false, vertical: true };
+    // start, 15 characters and the checksum, then the 13 module stop
+    let length = 17 * 11 + 13;
+    let mm = printer.barcode_length_mm(Barcode::Code128, data, &options).unwrap();
+    assert!((mm - (length * 3) as f32 * 25.4 / 203.0).abs() < 0.01, "{}", mm);
+
+    printer.print_barcode_raster(Barcode::Code128, data, &options).unwrap();
+    let (w_bytes, rows, _) = decode_raster(printer.get_ref());
+    assert_eq!(w_bytes, 60usize.div_ceil(8));
+    assert_eq!(rows.len(), w_bytes * length * 3);
+    // every row crosses all the bars, so each is either fully black or blank
+    let bar = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xf0];
+    assert!(rows.chunks(w_bytes).all(|row| row == bar || row == [0; 8]));
+
+    let too_long = vec![b'0'; 253];
+    let result = printer.print_barcode_raster(Barcode::Code128, &too_long, &options);
+    assert!(matches!(result, Err(PrinterError::TooLarge(_))), "{:?}", result);
+  }
+
+  /// A printer that answers every ``GS ( H`` process ID until it is sent a
+  /// ``GS v 0`` raster bigger than ``limit`` bytes
+  struct Responder {
+    written: Vec<u8>,
+    answers: std::sync::Arc<std::sync::Mutex<Vec<u8>>>,
+    limit: usize,
+    answered: usize,
+  }
+
+  impl Write for Responder {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+      self.written.extend_from_slice(buf);
+      let (mut pos, mut ids, mut overflowed) = (0, Vec::new(), false);
+      while pos < self.written.len() {
+        match &self.written[pos..] {
+          [GS, b'v', b'0', 0x00, xl, xh, yl, yh, ..] => {
+            let size = u16::from_le_bytes([*xl, *xh]) as usize * u16::from_le_bytes([*yl, *yh]) as usize;
+            overflowed |= size > self.limit;
+            pos += 8 + size;
+          },
+          [GS, b'(', b'H', 0x06, 0x00, 48, 48, id @ ..] if id.len() >= 4 => {
+            if !overflowed {
+              ids.push(id[..4].to_vec());
+            }
+            pos += 11;
+          },
+          _ => pos += 1
+        }
+      }
+      for id in &ids[self.answered..] {
+        let mut answers = self.answers.lock().unwrap();
+        answers.extend_from_slice(&[0x37, 0x22]);
+        answers.extend_from_slice(id);
+        answers.push(0x00);
+      }
+      self.answered = ids.len();
+      Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+      Ok(())
+    }
+  }
+
+  struct Answers(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+  impl Read for Answers {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+      let mut answers = self.0.lock().unwrap();
+      let read = buf.len().min(answers.len());
+      buf[..read].copy_from_slice(&answers[..read]);
+      answers.drain(..read);
+      Ok(read)
+    }
+  }
+
+  impl Connection for Responder {
+    fn open(_address: &str) -> std::io::Result<Self> {
+      Ok(Responder { written: Vec::new(), answers: Default::default(), limit: 0, answered: 0 })
+    }
+
+    fn reader(&self, _address: &str) -> std::io::Result<Box<dyn Read + Send>> {
+      Ok(Box::new(Answers(self.answers.clone())))
+    }
+  }
+
+  #[test]
+  fn calibrate_raster_size_doubles_until_the_printer_stops_answering() {
+    // 384 dots are 48 bytes a row, so rasters of up to 40 rows fit
+    let responder = Responder { limit: 48 * 40, ..Responder::open("").unwrap() };
+    let mut printer = Printer::new_with_writer(responder);
+    printer.set_capabilities(Capabilities { status_readback: true, ..Default::default() });
+    let max_bytes = printer.calibrate_raster_size(Duration::from_millis(200)).unwrap();
+    assert_eq!(max_bytes, 48 * 32);
+    assert_eq!(printer.get_capabilities().max_raster_bytes, 48 * 32);
+
+    let mut expected: Vec<u8> = Vec::new();
+    for (i, height) in [8u16, 16, 32, 64].iter().enumerate() {
+      expected.extend_from_slice(&[GS, b'v', b'0', 0x00, 48, 0]);
+      expected.extend_from_slice(&height.to_le_bytes());
+      expected.resize(expected.len() + 48 * *height as usize, 0);
+      expected.extend_from_slice(&[GS, b'(', b'H', 0x06, 0x00, 48, 48]);
+      expected.extend_from_slice(format!("{:04}", i + 1).as_bytes());
+    }
+    // reset after the overflow
+    expected.extend_from_slice(&[ESC, b'@']);
+    assert_eq!(printer.get_ref().written, expected);
+  }
+
+  #[test]
+  fn calibrate_raster_size_needs_status_readback() {
+    let mut printer = Printer::new_with_writer(Vec::new());
+    let result = printer.calibrate_raster_size(Duration::from_millis(10));
+    assert!(matches!(result, Err(PrinterError::Unsupported(_))), "{:?}", result);
+    assert!(printer.get_ref().is_empty());
+  }
+
+  #[test]
+  fn code128_braces_are_escaped() {
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.print_barcode(Barcode::Code128, b"a{b").unwrap();
+    let sent = printer.into_inner().unwrap();
+    assert!(sent.ends_with(b"\x1dkI\x06{Ba{{b\n"));
+
+    // data picking its own code set is sent as written
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.print_barcode(Barcode::Code128, b"{Ca").unwrap();
+    let sent = printer.into_inner().unwrap();
+    assert!(sent.ends_with(b"\x1dkI\x03{Ca\n"));
+
+    let long = [b'{'; 127];
+    let result = Printer::new_with_writer(Vec::new()).print_barcode(Barcode::Code128, &long);
+    assert!(matches!(result, Err(PrinterError::InvalidArgument(_))), "{:?}", result);
+  }
+
+  #[test]
+  fn set_color_needs_two_color_paper() {
+    let mut printer = Printer::new_with_writer(Vec::new());
+    let result = printer.set_color(Color::Red);
+    assert!(matches!(result, Err(PrinterError::Unsupported(_))), "{:?}", result);
+    assert!(printer.get_ref().is_empty());
+    assert_eq!(printer.snapshot().color, Color::Black);
+
+    printer.set_capabilities(Capabilities { two_color: true, ..Default::default() });
+    printer.set_color(Color::Red).unwrap();
+    printer.set_color(Color::Black).unwrap();
+    assert_eq!(printer.into_inner().unwrap(), [ESC, b'r', 1, ESC, b'r', 0]);
+  }
+
+  #[test]
+  fn set_print_speed_needs_the_capability() {
+    let mut printer = Printer::new_with_writer(Vec::new());
+    let result = printer.set_print_speed(3);
+    assert!(matches!(result, Err(PrinterError::Unsupported(_))), "{:?}", result);
+    assert!(printer.get_ref().is_empty());
+
+    printer.set_capabilities(Capabilities { print_speed: true, ..Default::default() });
+    printer.set_print_speed(3).unwrap();
+    // clamped to 1..=9
+    printer.set_print_speed(0).unwrap();
+    printer.set_print_speed(12).unwrap();
+    assert_eq!(printer.into_inner().unwrap(), [
+      GS, b'(', b'K', 0x02, 0x00, 50, 3,
+      GS, b'(', b'K', 0x02, 0x00, 50, 1,
+      GS, b'(', b'K', 0x02, 0x00, 50, 9,
+    ]);
+  }
+
+  #[test]
+  fn qr_codes_are_surrounded_by_the_quiet_zone() {
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.print_qr_code(4, QrEcc::M, b"hi").unwrap();
+    let qr: &[u8] = &[
+      GS, b'(', b'k', 0x03, 0x00, 0x31, 0x43, 4,
+      GS, b'(', b'k', 0x03, 0x00, 0x31, 0x45, 49,
+      GS, b'(', b'k', 0x05, 0x00, 0x31, 0x50, 0x30, b'h', b'i',
+      GS, b'(', b'k', 0x03, 0x00, 0x31, 0x51, 0x30,
+    ];
+    // 4 modules of 4 dots above and below
+    assert_eq!(printer.into_inner().unwrap(), [&[ESC, b'J', 16], qr, &[ESC, b'J', 16]].concat());
+
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.set_qr_quiet_zone(0);
+    printer.print_qr_code(4, QrEcc::M, b"hi").unwrap();
+    assert_eq!(printer.into_inner().unwrap(), qr);
+
+    // 320 dots are split into feeds of at most 255
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.set_qr_quiet_zone(20);
+    printer.print_qr_code(16, QrEcc::L, b"hi").unwrap();
+    let sent = printer.into_inner().unwrap();
+    assert_eq!(&sent[..6], &[ESC, b'J', 255, ESC, b'J', 65]);
+    assert_eq!(&sent[sent.len() - 6..], &[ESC, b'J', 255, ESC, b'J', 65]);
+  }
+
+  #[test]
+  fn ascii_safe_strips_control_sequences() {
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.set_ascii_safe(true);
+    printer.println("hello \x1b@world\x1dV\x00 café").unwrap();
+    // the ESC and GS are gone, the parameters after them are plain text
+    assert_eq!(printer.into_inner().unwrap(), b"hello @worldV caf?\n");
+
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.println("\x1b@").unwrap();
+    assert_eq!(printer.into_inner().unwrap(), [ESC, b'@', LF]);
+  }
+
+  #[test]
+  fn text_jobs_have_no_form_feeds() {
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.set_justification(1).unwrap();
+    printer.println("Receipt\nline two").unwrap();
+    printer.print_inplace("50%").unwrap();
+    printer.blank_lines(2).unwrap();
+    printer.set_pagination(Some(Pagination {
+      page_length: 1,
+      header: "header".to_string(),
+      footer: "footer".to_string()
+    }));
+    printer.println("paged").unwrap();
+    printer.end_page().unwrap();
+    printer.feed_and_cut(CutMode::Full).unwrap();
+    assert!(!printer.into_inner().unwrap().contains(&FF));
+
+    // bands follow each other directly, with nothing in between
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.print_bitmap(16, 200, 2, &[0xaa; 400]).unwrap();
+    let (_, rows, bands) = decode_raster(&printer.into_inner().unwrap());
+    assert_eq!(rows, [0xaa; 400]);
+    assert_eq!(bands, [64, 64, 64, 8]);
+
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.form_feed().unwrap();
+    assert_eq!(printer.into_inner().unwrap(), [FF]);
+  }
+
+  #[test]
+  fn diffusion_matches_a_plain_floyd_steinberg() {
+    let gradient = image::GrayImage::from_fn(13, 7, |x, y| image::Luma([((x * 37 + y * 71) % 256) as u8]));
+    // the textbook version over a nested copy of the image, adding each share
+    // of the error and clamping it to a byte like the printing code does
+    let mut grid: Vec<Vec<i32>> = (0..7)
+      .map(|y| (0..13).map(|x| gradient.get_pixel(x, y).0[0] as i32).collect())
+      .collect();
+    let mut expected = BitImage::new(13, 7);
+    for y in 0..7 {
+      for x in 0..13 {
+        let value = grid[y][x];
+        let error = if value > 127 { value - 255 } else { value };
+        expected.set_pixel(x as isize, y as isize, value <= 127);
+        for (dx, dy, weight) in [(1, 0, 7), (-1, 1, 3), (0, 1, 5), (1, 1, 1)] {
+          let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+          if (0..13).contains(&nx) && ny < 7 {
+            let pixel = &mut grid[ny as usize][nx as usize];
+            *pixel = (*pixel + (error >> 4) * weight).clamp(0, 255);
+          }
+        }
+      }
+    }
+    assert_eq!(threshold_image(gradient, 13, 7, &Dither::FloydSteinberg), expected);
+  }
+
+  #[test]
+  fn print_jobs_cuts_between_jobs() {
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.print_jobs(&[b"\x1b@first\n".to_vec(), b"\x1b@second\n".to_vec()]).unwrap();
+    assert_eq!(
+      printer.into_inner().unwrap(),
+      b"\x1b@first\n\x1dV\x00\x1b@second\n\x1dV\x00"
+    );
+  }
+
+  #[test]
+  fn lsb_first_printers_get_reversed_bytes() {
+    // the two leftmost dots of each row, then the rightmost of the second
+    let data = [0xc0, 0x00, 0x00, 0x01];
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.print_bitmap(16, 2, 2, &data).unwrap();
+    assert_eq!(printer.into_inner().unwrap(), [&[GS, b'v', b'0', 0, 2, 0, 2, 0][..], &data].concat());
+
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.set_capabilities(Capabilities { bit_order: BitOrder::LsbFirst, ..Capabilities::default() });
+    printer.print_bitmap(16, 2, 2, &data).unwrap();
+    assert_eq!(printer.into_inner().unwrap(), [GS, b'v', b'0', 0, 2, 0, 2, 0, 0x03, 0x00, 0x00, 0x80]);
+  }
+
+  #[test]
+  fn padding_bits_are_cleared_at_width_129() {
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.print_bitmap(129, 3, 17, &[0xff; 17 * 3]).unwrap();
+    let (w_bytes, rows, _) = decode_raster(&printer.into_inner().unwrap());
+    assert_eq!(w_bytes, 17);
+    for row in rows.chunks(17) {
+      // only the first bit of the last byte is on the paper
+      assert_eq!(row[..16], [0xff; 16]);
+      assert_eq!(row[16], 0x80);
+    }
+  }
+
+  #[test]
+  fn stateless_lines_start_with_an_init() {
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.set_stateless(true);
+    printer.println("a").unwrap();
+    printer.println("b").unwrap();
+    let init = [ESC, b'@', ESC, b'a', 0, ESC, b'!', 0, ESC, b'G', 0];
+    assert_eq!(printer.into_inner().unwrap(), [&init[..], b"a\n", &init, b"b\n"].concat());
+
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.set_justification(1).unwrap();
+    printer.set_stateless(true);
+    printer.println("c").unwrap();
+    assert!(printer.into_inner().unwrap().ends_with(&[ESC, b'@', ESC, b'a', 1, ESC, b'!', 0, ESC, b'G', 0, b'c', LF]));
+  }
+
+  #[test]
+  fn sanitize_removes_control_bytes() {
+    assert_eq!(sanitize("\x1b@total\x1dV\x00 12.50\x0c"), "@totalV 12.50");
+    assert_eq!(sanitize("\x10\x14\x01\x00\x05\x1c.tab\there\nnext"), ".tab\there\nnext");
+    // unlike the ASCII-safe mode non-ASCII text is kept
+    assert_eq!(sanitize("café\x1b"), "café");
+  }
+
+  #[test]
+  fn cut_with_feed_is_a_single_command() {
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.cut_paper_with_feed(CutMode::Partial, 40).unwrap();
+    printer.cut_paper_with_feed(CutMode::Full, 0).unwrap();
+    assert_eq!(printer.into_inner().unwrap(), [GS, 0x56, 66, 40, GS, 0x56, 65, 0]);
+
+    // 4 lines of 1/6 inch at 203 dpi
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.set_capabilities(Capabilities { feed_cut: true, ..Capabilities::default() });
+    printer.feed_and_cut(CutMode::Partial).unwrap();
+    assert_eq!(printer.into_inner().unwrap(), [GS, 0x56, 66, 135]);
+
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.feed_and_cut(CutMode::Partial).unwrap();
+    assert_eq!(printer.into_inner().unwrap(), [ESC, b'd', 4, GS, 0x56, 1]);
+  }
+
+  /// Takes at most 3 bytes per write and returns ``WouldBlock`` every 8th
+  /// call, like a slow non-blocking connection
+  struct Trickle {
+    written: Vec<u8>,
+    calls: usize,
+  }
+
+  impl Write for Trickle {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+      self.calls += 1;
+      if self.calls.is_multiple_of(8) {
+        return Err(std::io::Error::new(std::io::ErrorKind::WouldBlock, "buffer full"))
+      }
+      let accepted = buf.len().min(3);
+      self.written.extend_from_slice(&buf[..accepted]);
+      Ok(accepted)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+      Ok(())
+    }
+  }
+
+  impl Connection for Trickle {
+    fn open(_address: &str) -> std::io::Result<Self> {
+      Ok(Trickle { written: Vec::new(), calls: 0 })
+    }
+  }
+
+  #[test]
+  fn short_writes_and_would_block_lose_no_raster_bytes() {
+    let data: Vec<u8> = (0..2 * 150).map(|i| (i * 7) as u8).collect();
+    let mut printer = Printer::new_with_writer(Trickle { written: Vec::new(), calls: 0 });
+    printer.print_bitmap(16, 150, 2, &data).unwrap();
+    let connection = printer.into_inner().unwrap();
+    assert!(connection.calls > data.len() / 3);
+    let (_, rows, bands) = decode_raster(&connection.written);
+    assert_eq!(rows, data);
+    assert_eq!(bands, [64, 64, 22]);
+  }
+
+  #[test]
+  fn print_kv_aligns_on_the_longest_key() {
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.print_kv(&[
+      ("A".to_string(), "1".to_string()),
+      ("Speed".to_string(), "9600 baud".to_string()),
+      ("Paper width".to_string(), "58mm".to_string()),
+      ("Note".to_string(), "the quick brown fox jumps over".to_string()),
+    ]).unwrap();
+    assert_eq!(
+      String::from_utf8(printer.into_inner().unwrap()).unwrap(),
+      "A:           1\n\
+       Speed:       9600 baud\n\
+       Paper width: 58mm\n\
+       Note:        the quick brown fox\n\
+       \x20            jumps over\n"
+    );
+
+    // keys longer than half of the line get a line of their own
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.print_kv(&[
+      ("Automatic cutter enabled".to_string(), "yes".to_string()),
+      ("Font".to_string(), "A".to_string()),
+    ]).unwrap();
+    assert_eq!(
+      String::from_utf8(printer.into_inner().unwrap()).unwrap(),
+      "Automatic cutter enabled:\n                yes\nFont:           A\n"
+    );
+  }
+
+  #[test]
+  fn rotated_bitmaps_keep_their_corners() {
+    // a 10x3 image with only its top-left corner set, plus a bar along the bottom
+    let img = BitImage::from_fn(10, 3, |x, y| (x == 0 && y == 0) || (y == 2 && x >= 5));
+    let cases = [
+      (Rotation::Cw90, 3_usize, 10_usize, (2, 0)),
+      (Rotation::Cw180, 10, 3, (9, 2)),
+      (Rotation::Cw270, 3, 10, (0, 9)),
+    ];
+    for (rotation, width, height, corner) in cases {
+      let mut printer = Printer::new_with_writer(Vec::new());
+      printer.print_bit_image_rotated(&img, rotation).unwrap();
+      let (w_bytes, rows, bands) = decode_raster(&printer.into_inner().unwrap());
+      assert_eq!(w_bytes, width.div_ceil(8), "{:?}", rotation);
+      assert_eq!(bands, [height], "{:?}", rotation);
+
+      let mut sent = BitImage::new(width, height);
+      for y in 0..height {
+        for x in 0..width {
+          sent.set_pixel(x as isize, y as isize, rows[y * w_bytes + x / 8] & (0x80 >> (x % 8)) != 0);
+        }
+      }
+      assert!(sent.get_pixel(corner.0, corner.1), "{:?}", rotation);
+      assert_eq!(sent, img.rotate(rotation), "{:?}", rotation);
+    }
+  }
+
+  #[test]
+  fn pages_break_after_page_length_lines() {
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.set_pagination(Some(Pagination {
+      page_length: 2,
+      header: "REPORT".to_string(),
+      footer: "-- continues --".to_string()
+    }));
+    printer.println("1\n2\n3").unwrap();
+    printer.println("4").unwrap();
+    printer.println("5").unwrap();
+    printer.end_page().unwrap();
+    // nothing is left to close
+    printer.end_page().unwrap();
+
+    let cut = [ESC, b'd', 4, GS, b'V', 0];
+    let page = |lines: &str| [b"REPORT\n", lines.as_bytes(), b"-- continues --\n", &cut].concat();
+    assert_eq!(printer.into_inner().unwrap(), [page("1\n2\n"), page("3\n4\n"), page("5\n")].concat());
+  }
+
+  /// Saves ``img`` as a PNG in the temporary directory, returning its path
+  fn temp_image(name: &str, img: image::DynamicImage) -> String {
+    let path = std::env::temp_dir().join(format!("thermal_printer_{}_{}.png", std::process::id(), name));
+    img.save(&path).unwrap();
+    path.to_str().unwrap().to_string()
+  }
+
+  #[test]
+  fn background_modes_on_a_half_transparent_image() {
+    // black, at a quarter of opacity on the left and mostly opaque on the right
+    let img = image::RgbaImage::from_fn(8, 1, |x, _| image::Rgba([0, 0, 0, if x < 4 { 64 } else { 200 }]));
+    let path = temp_image("half_transparent", image::DynamicImage::ImageRgba8(img));
+    let dots = |background| {
+      let options = ImageOptions { background, ..Default::default() };
+      dither_image(&path, 8, &Dither::Threshold, &options).unwrap().as_slice()[0]
+    };
+    // over white the faint half is light gray and isn't printed
+    assert_eq!(dots(BgMode::White), 0x0f);
+    assert_eq!(dots(BgMode::White), dots(BgMode::default()));
+    assert_eq!(dots(BgMode::Black), 0xff);
+    // pixels above the threshold print as if they were opaque
+    assert_eq!(dots(BgMode::Transparent(128)), 0x0f);
+    assert_eq!(dots(BgMode::Transparent(32)), 0xff);
+    std::fs::remove_file(&path).unwrap();
+  }
+
+  #[test]
+  fn burkes_and_stucki_spread_the_whole_error() {
+    for (dither, divisor) in [(Dither::Burkes, 32), (Dither::Stucki, 42)] {
+      let (weights, kernel_divisor) = dither.kernel();
+      assert_eq!(kernel_divisor, divisor);
+      assert_eq!(weights.iter().map(|w| w.2).sum::<i32>(), divisor, "{:?}", dither);
+    }
+
+    // flat grays keep roughly their darkness, the error is divided before it's
+    // weighted like in the other kernels, so a bit of it is rounded away
+    for level in [60, 100, 180] {
+      let gray = image::GrayImage::from_pixel(32, 32, image::Luma([level]));
+      let burkes = threshold_image(gray.clone(), 32, 32, &Dither::Burkes);
+      let stucki = threshold_image(gray.clone(), 32, 32, &Dither::Stucki);
+      let floyd = threshold_image(gray, 32, 32, &Dither::FloydSteinberg);
+      for bitmap in [&burkes, &stucki, &floyd] {
+        assert!((bitmap.coverage() - (255 - level) as f32 / 255.0).abs() < 0.1, "{} at {}", bitmap.coverage(), level);
+      }
+      assert_ne!(burkes, stucki);
+      assert_ne!(burkes, floyd);
+      assert_ne!(stucki, floyd);
+    }
+  }
+
+  #[test]
+  fn grid_rows_are_as_wide_as_their_cells() {
+    let widths = [4, 10, 7];
+    let aligns = [Align::Right, Align::Left, Align::Right];
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.print_grid(&["2", "Coffee", "$5.00"], &widths, &aligns).unwrap();
+    // wrapped on whitespace, words longer than the cell are split
+    printer.print_grid(&["1", "Cappuccino grande", "$12.00"], &widths, &aligns).unwrap();
+    printer.print_grid(&["10", "Refreshments", "$0.50"], &widths, &[Align::Center]).unwrap();
+    let sent = String::from_utf8(printer.into_inner().unwrap()).unwrap();
+    let lines: Vec<&str> = sent.lines().collect();
+    assert_eq!(lines, [
+      "   2Coffee      $5.00",
+      "   1Cappuccino $12.00",
+      "    grande           ",
+      " 10 Refreshmen$0.50  ",
+      "    ts               ",
+    ]);
+    for line in lines {
+      assert_eq!(line.chars().count(), 21, "{:?}", line);
+    }
+  }
+
+  #[test]
+  fn images_at_the_print_width_pass_through_unchanged() {
+    // 1-bit art with single dot details that resampling would blur
+    let art = BitImage::from_fn(24, 6, |x, y| (x + y) % 3 == 0 || x == 23);
+    let img = image::GrayImage::from_fn(24, 6, |x, y| image::Luma([if art.get_pixel(x as isize, y as isize) { 0 } else { 255 }]));
+    let path = temp_image("native_width", image::DynamicImage::ImageLuma8(img));
+
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.print_image(&path, 24, &Dither::FloydSteinberg, &ImageOptions::default()).unwrap();
+    assert_eq!(printer.into_inner().unwrap(), [&[GS, b'v', b'0', 0, 3, 0, 6, 0][..], art.as_slice()].concat());
+    std::fs::remove_file(&path).unwrap();
+  }
+
+  #[test]
+  fn justified_lines_reach_both_edges() {
+    let text = "Items can be returned within 30 days of purchase with this receipt and the original packaging.\nThank you!";
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.print_justified(text).unwrap();
+    let sent = String::from_utf8(printer.into_inner().unwrap()).unwrap();
+    let lines: Vec<&str> = sent.lines().collect();
+    assert_eq!(lines.len(), 5);
+    // every line of the first paragraph but its last one is 32 characters wide
+    for line in &lines[..3] {
+      assert_eq!(line.chars().count(), 32, "{:?}", line);
+      assert!(!line.starts_with(' ') && !line.ends_with(' '), "{:?}", line);
+    }
+    assert_eq!(lines[3], "packaging.");
+    assert_eq!(lines[4], "Thank you!");
+    // only spaces were added
+    let words: Vec<&str> = lines.iter().flat_map(|l| l.split_whitespace()).collect();
+    assert_eq!(words, text.split_whitespace().collect::<Vec<&str>>());
+  }
+
+  #[test]
+  fn qr_payload_strings() {
+    assert_eq!(wifi_payload("Cafe; Guest", "pa:ss\\", WifiSecurity::Wpa), r"WIFI:T:WPA;S:Cafe\; Guest;P:pa\:ss\\;;");
+    assert_eq!(wifi_payload("Lobby", "ignored", WifiSecurity::Open), "WIFI:T:nopass;S:Lobby;;");
+    assert_eq!(wifi_payload("Old", "12345", WifiSecurity::Wep), "WIFI:T:WEP;S:Old;P:12345;;");
+    assert_eq!(
+      vcard_payload("Julia", Some("+55 11 5555-5555"), None),
+      "BEGIN:VCARD\r\nVERSION:3.0\r\nN:Julia\r\nFN:Julia\r\nTEL:+55 11 5555-5555\r\nEND:VCARD"
+    );
+    assert_eq!(geo_payload(-23.5505, -46.6333), "geo:-23.5505,-46.6333");
+
+    // the payload is what's stored in the symbol
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.print_qr_geo(4, 1.5, 2.0).unwrap();
+    let sent = printer.into_inner().unwrap();
+    let store = [&[GS, b'(', b'k', 12, 0, 0x31, 0x50, 0x30][..], b"geo:1.5,2"].concat();
+    assert!(sent.windows(store.len()).any(|w| w == store.as_slice()));
+  }
+
+  #[test]
+  fn sharpening_steepens_edges() {
+    // a soft edge between two grays
+    let img = image::DynamicImage::ImageLuma8(image::GrayImage::from_fn(32, 8, |x, _| image::Luma([if x < 16 { 100 } else { 160 }])));
+    let (plain, _) = gray_image(&img, 32, 8, &ImageOptions::default());
+    let options = ImageOptions { sharpen: Some(Sharpen { radius: 2, amount: 100 }), ..Default::default() };
+    let (sharpened, _) = gray_image(&img, 32, 8, &options);
+
+    assert_eq!(plain.get_pixel(15, 4).0[0], 100);
+    assert_eq!(plain.get_pixel(16, 4).0[0], 160);
+    // darker right before the edge, lighter right after it
+    assert!(sharpened.get_pixel(15, 4).0[0] < 100);
+    assert!(sharpened.get_pixel(16, 4).0[0] > 160);
+    // flat areas far from the edge are left alone
+    assert!(sharpened.get_pixel(0, 4).0[0].abs_diff(100) <= 1);
+    assert!(sharpened.get_pixel(31, 4).0[0].abs_diff(160) <= 1);
+  }
+
+  #[test]
+  fn millimeters_to_qr_modules() {
+    // 30mm over 25 modules at 203 dpi is 9.59 dots per module
+    assert_eq!(qr_module_dots(30.0, 25, 203), 10);
+    assert_eq!(qr_module_dots(30.0, 25, 300), 14);
+    // never below a dot
+    assert_eq!(qr_module_dots(1.0, 177, 203), 1);
+
+    let mut printer = Printer::new_with_writer(Vec::new());
+    let data = b"https://example.com";
+    let modules = qr_modules(data.len()).unwrap();
+    assert_eq!(modules, 25);
+    let printed = printer.print_qr_mm(data, 30.0).unwrap();
+    assert!((printed - 25.0 * 10.0 / 203.0 * 25.4).abs() < 0.001, "{}", printed);
+    let sent = printer.into_inner().unwrap();
+    assert!(sent.windows(8).any(|w| w == [GS, b'(', b'k', 0x03, 0x00, 0x31, 0x43, 10]));
+
+    // clamped to the paper, 384 dots fit 15 modules of 25
+    let mut printer = Printer::new_with_writer(Vec::new());
+    let printed = printer.print_qr_mm(data, 200.0).unwrap();
+    assert!((printed - 25.0 * 15.0 / 203.0 * 25.4).abs() < 0.001, "{}", printed);
+  }
+
+  #[test]
+  fn transparent_pixels_are_left_unset() {
+    // a transparent left half and a dark gray right half
+    let img = image::RgbaImage::from_fn(16, 16, |x, _| {
+      if x < 8 { image::Rgba([0, 0, 0, 0]) } else { image::Rgba([60, 60, 60, 255]) }
+    });
+    let path = temp_image("transparent_background", image::DynamicImage::ImageRgba8(img));
+    let options = ImageOptions { background: BgMode::Transparent(128), ..Default::default() };
+    let (stamp, mask) = dither_image_masked(&path, 16, &Dither::FloydSteinberg, &options).unwrap();
+    assert_eq!(mask.coverage_in(0, 0, 8, 16), 0.0);
+    assert_eq!(mask.coverage_in(8, 0, 8, 16), 1.0);
+    // no error reaches the transparent half
+    assert_eq!(stamp.coverage_in(0, 0, 8, 16), 0.0);
+    assert!(stamp.coverage_in(8, 0, 8, 16) > 0.5);
+
+    // pasted over a printed form, the transparent half keeps what was there
+    let mut form = BitImage::from_fn(16, 16, |_, _| true);
+    form.paste_masked(&stamp, &mask, 0, 0);
+    assert_eq!(form.coverage_in(0, 0, 8, 16), 1.0);
+    assert_eq!(form.crop(8, 0, 8, 16), stamp.crop(8, 0, 8, 16));
+    std::fs::remove_file(&path).unwrap();
+  }
+
+  #[test]
+  fn mirrored_images_are_flipped_left_to_right() {
+    // an L shape, which looks different mirrored
+    let img = image::GrayImage::from_fn(16, 8, |x, y| image::Luma([if x < 3 || y > 5 { 0 } else { 255 }]));
+    let path = temp_image("mirrored", image::DynamicImage::ImageLuma8(img));
+    let options = ImageOptions::default();
+
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.print_image(&path, 16, &Dither::Threshold, &options).unwrap();
+    let (_, rows, _) = decode_raster(&printer.into_inner().unwrap());
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.print_image_mirrored(&path, 16, &Dither::Threshold, &options).unwrap();
+    let (w_bytes, mirrored_rows, bands) = decode_raster(&printer.into_inner().unwrap());
+    assert_eq!((w_bytes, bands), (2, vec![8]));
+
+    let to_image = |rows: &[u8]| BitImage::from_fn(16, 8, |x, y| rows[y * 2 + x / 8] & (0x80 >> (x % 8)) != 0);
+    let (normal, mirrored) = (to_image(&rows), to_image(&mirrored_rows));
+    assert_ne!(normal, mirrored);
+    assert_eq!(mirrored, normal.flip_horizontal());
+    assert!(mirrored.get_pixel(15, 0) && !mirrored.get_pixel(0, 0));
+    std::fs::remove_file(&path).unwrap();
+  }
+
+  #[test]
+  fn square_images_are_exactly_the_requested_size() {
+    // wider than tall, with the middle square darker than the sides
+    let img = image::GrayImage::from_fn(60, 20, |x, _| image::Luma([if (20..40).contains(&x) { 0 } else { 255 }]));
+    let path = temp_image("square", image::DynamicImage::ImageLuma8(img));
+    for size in [1, 24, 75] {
+      let logo = dither_image_square(&path, size, &Dither::Threshold, &ImageOptions::default()).unwrap();
+      assert_eq!((logo.get_width(), logo.get_height()), (size as usize, size as usize));
+      // only the dark center square is left after cropping
+      assert_eq!(logo.coverage(), 1.0);
+    }
+    let result = dither_image_square(&path, 0, &Dither::Threshold, &ImageOptions::default());
+    assert!(matches!(result, Err(PrinterError::InvalidArgument(_))), "{:?}", result);
+    std::fs::remove_file(&path).unwrap();
+  }
+
+  #[test]
+  fn totals_are_formatted_and_right_aligned() {
+    let options = TotalOptions::default();
+    assert_eq!(format_amount(1234.5, "$", &options), "$1,234.50");
+    assert_eq!(format_amount(0.99, "$", &options), "$0.99");
+    let european = TotalOptions { thousands_separator: '.', decimal_separator: ',', symbol_after: true, ..options };
+    assert_eq!(format_amount(1234.5, "EUR", &european), "1.234,50 EUR");
+    assert_eq!(format_amount(0.99, "EUR", &european), "0,99 EUR");
+
+    let mut printer = Printer::new_with_writer(Vec::new());
+    printer.print_total("TOTAL", 1234.5, "$").unwrap();
+    printer.set_total_options(TotalOptions { bold: false, ..options });
+    printer.print_total("Change", 0.99, "$").unwrap();
+    assert_eq!(printer.into_inner().unwrap(), [
+      &[ESC, b'!', 8][..],
+      b"TOTAL                  $1,234.50\n",
+      &[ESC, b'!', 0],
+      b"Change                     $0.99\n",
+    ].concat());
+  }
+
+  #[test]
+  fn low_ink_mode_keeps_coverage_under_the_cap() {
+    // a dark photo, mostly black once dithered
+    let img = image::GrayImage::from_fn(96, 64, |x, y| image::Luma([((x + y) % 40) as u8]));
+    let path = temp_image("dark", image::DynamicImage::ImageLuma8(img));
+    let full = dither_image(&path, 96, &Dither::FloydSteinberg, &ImageOptions::default()).unwrap();
+    assert!(full.coverage() > 0.85, "{}", full.coverage());
+
+    for cap in [60, 40] {
+      let options = ImageOptions { max_coverage: Some(cap), ..Default::default() };
+      let low_ink = dither_image(&path, 96, &Dither::FloydSteinberg, &options).unwrap();
+      assert!(low_ink.coverage() <= cap as f32 / 100.0, "{} over {}", low_ink.coverage(), cap);
+      // tiles are either left as they were or inverted as a whole
+      let untouched = low_ink.crop(64, 32, 32, 32);
+      assert!(untouched == full.crop(64, 32, 32, 32) || untouched.coverage() < 0.15);
+    }
+    std::fs::remove_file(&path).unwrap();
+  }
+}